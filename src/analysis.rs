@@ -0,0 +1,189 @@
+// Copyright 2022 nitepone <luna@night.horse>
+//
+// Measures of how hard a board is, and how well it was played.
+
+use crate::error::MinrsResult;
+use crate::game::{Clicks, MinrsGame, Position, StdMinrsGame};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+/// How much work a mine layout takes to clear.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct BoardMetrics {
+    /// Bechtel's Board Benchmark Value, the fewest uncovers that clear the
+    /// board. That is one per opening, and one per isolated number.
+    pub bbbv: u32,
+    /// Groups of connected empty tiles, which a single uncover opens along
+    /// with the numbers around them.
+    pub openings: u32,
+    /// Numbers not next to any opening, which each need their own uncover.
+    pub isolated: u32,
+}
+
+/// How well a won game was played.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct GameStats {
+    pub metrics: BoardMetrics,
+    pub elapsed: Duration,
+    pub clicks: Clicks,
+    /// 3BV cleared per second.
+    pub bbbv_per_second: f64,
+    /// 3BV per click, 1.0 for a perfect game. Chords and flags can take it
+    /// above that.
+    pub efficiency: f64,
+}
+
+/// Measure the game's current mine layout.
+///
+/// A generated board is only final once the first tile is uncovered.
+pub fn board_metrics(game: &StdMinrsGame) -> MinrsResult<BoardMetrics> {
+    let mines: HashSet<Position> = game.get_mine_positions().into_iter().collect();
+    let mut counts = HashMap::new();
+    for pos in game.get_positions() {
+        if mines.contains(&pos) {
+            continue;
+        }
+        let mut count = 0;
+        for n_pos in game.get_neighbors_pos(&pos)? {
+            count += mines.contains(&n_pos) as u32;
+        }
+        counts.insert(pos, count);
+    }
+
+    // flood each opening, marking it and the numbers around it as cleared
+    let mut cleared = HashSet::new();
+    let mut openings = 0;
+    for pos in game.get_positions() {
+        if counts.get(&pos) != Some(&0) || cleared.contains(&pos) {
+            continue;
+        }
+        openings += 1;
+        cleared.insert(pos);
+        let mut pending = vec![pos];
+        while let Some(pos) = pending.pop() {
+            for n_pos in game.get_neighbors_pos(&pos)? {
+                if !mines.contains(&n_pos) && cleared.insert(n_pos) && counts[&n_pos] == 0 {
+                    pending.push(n_pos);
+                }
+            }
+        }
+    }
+    let isolated = counts.keys().filter(|pos| !cleared.contains(pos)).count() as u32;
+
+    Ok(BoardMetrics {
+        bbbv: openings + isolated,
+        openings,
+        isolated,
+    })
+}
+
+/// Rate a game from its layout, time and clicks.
+pub fn game_stats(game: &StdMinrsGame) -> MinrsResult<GameStats> {
+    let metrics = board_metrics(game)?;
+    let elapsed = game.get_elapsed();
+    let clicks = game.get_clicks();
+    let bbbv = metrics.bbbv as f64;
+    let seconds = elapsed.as_secs_f64();
+    Ok(GameStats {
+        metrics,
+        elapsed,
+        clicks,
+        bbbv_per_second: if seconds > 0.0 { bbbv / seconds } else { 0.0 },
+        efficiency: if clicks.total() > 0 {
+            bbbv / clicks.total() as f64
+        } else {
+            0.0
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::game::Neighborhood;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_board_metrics() {
+        // the mine column splits the board into two openings, and the mines
+        // in the top right corner leave six numbers out of their reach
+        let game = StdMinrsGame::from_ascii(
+            "
+            ...*...*
+            ...*..*.
+            ...*....
+            ...*....
+            ...*....
+            ...*....
+            ...*....
+            ...*....
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            board_metrics(&game).unwrap(),
+            BoardMetrics {
+                bbbv: 8,
+                openings: 2,
+                isolated: 6,
+            }
+        );
+    }
+
+    #[test]
+    fn test_board_metrics_follow_neighborhood() {
+        let mut game = StdMinrsGame::from_ascii(
+            "
+            ........
+            ........
+            ........
+            ...*....
+            ........
+            ........
+            ........
+            ........
+            ",
+        )
+        .unwrap();
+        assert_eq!(board_metrics(&game).unwrap().bbbv, 1);
+        // a knight in the corner can only jump to two numbers, which cuts it
+        // off as an opening of its own
+        game.set_neighborhood(Neighborhood::Knight).unwrap();
+        assert_eq!(
+            board_metrics(&game).unwrap(),
+            BoardMetrics {
+                bbbv: 2,
+                openings: 2,
+                isolated: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_game_stats() {
+        let clock = ManualClock::new();
+        let mut game = StdMinrsGame::from_mines(8, 8, &[Position::new(0, 0)]).unwrap();
+        game.set_clock(Arc::new(clock.clone()));
+        game.uncover_tile(&Position::new(1, 1)).unwrap();
+        game.cycle_flag(&Position::new(0, 0)).unwrap();
+        clock.advance(Duration::from_secs(4));
+        game.uncover_neighbors(&Position::new(1, 1)).unwrap();
+        assert!(game.victory());
+
+        let stats = game_stats(&game).unwrap();
+        // a single opening reaches every number
+        assert_eq!(stats.metrics.bbbv, 1);
+        assert_eq!(stats.elapsed, Duration::from_secs(4));
+        assert_eq!(
+            stats.clicks,
+            Clicks {
+                uncovers: 1,
+                chords: 1,
+                flags: 1,
+            }
+        );
+        assert_eq!(stats.bbbv_per_second, 0.25);
+        assert!((stats.efficiency - 1.0 / 3.0).abs() < 1e-9);
+    }
+}
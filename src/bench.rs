@@ -0,0 +1,569 @@
+// Copyright 2022 nitepone <luna@night.horse>
+//
+// Playing many seeded games with a strategy, to measure how well it does.
+//
+// A bot run as an external program is started once per thread, and plays
+// every game that thread is given. Before each move it is sent the board as
+// the headless protocol's `board` reply:
+//
+//     ok <width> <height>
+//     <a line per row>
+//
+// and answers with a single line, `open <x> <y>`, `flag <x> <y>` or
+// `chord <x> <y>`. A board with every tile covered is a new game.
+
+use crate::analysis;
+use crate::difficulty::GameDifficulty;
+use crate::error::{MinrsError, MinrsResult};
+use crate::game::{parse_word, MinrsGame, Position, StdMinrsGame, TileContents, TileState};
+use crate::probability;
+use crate::protocol::draw_board;
+use crate::replay::Action;
+use crate::solver;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// Picks the moves of a benchmarked game.
+pub trait Strategy {
+    /// Forget anything about the last game, before the first move of the
+    /// next one, which is played on the board generated from `seed`.
+    fn start_game(&mut self, _seed: u64) {}
+    /// Choose a move for a game that is still being played.
+    fn next_move(&mut self, game: &StdMinrsGame) -> MinrsResult<Action>;
+}
+
+/// Uncovers a tile the solver proves safe, or else the tile least likely to
+/// hold a mine.
+#[derive(Debug, Default)]
+pub struct SolverStrategy {
+    /// Tiles proven safe but not uncovered yet. A tile stays safe however
+    /// much more of the board is seen, so these are used up before solving
+    /// again.
+    safe: Vec<Position>,
+}
+
+impl SolverStrategy {
+    pub fn new() -> SolverStrategy {
+        SolverStrategy::default()
+    }
+}
+
+impl Strategy for SolverStrategy {
+    fn start_game(&mut self, _seed: u64) {
+        self.safe.clear();
+    }
+
+    fn next_move(&mut self, game: &StdMinrsGame) -> MinrsResult<Action> {
+        if self.safe.is_empty() {
+            self.safe = solver::solve(game)?.safe.into_iter().collect();
+            // uncover in a fixed order, so games play out the same every run
+            self.safe
+                .sort_by_key(|pos| std::cmp::Reverse((pos.z, pos.x, pos.y)));
+        }
+        while let Some(pos) = self.safe.pop() {
+            if let TileState::Covered(_) = game.get_tile_state(&pos)? {
+                return Ok(Action::Uncover(pos));
+            }
+        }
+
+        // working out the odds is much slower, and only needed for a guess
+        let odds = probability::mine_probabilities(game)?;
+        odds.iter()
+            // break ties by position, so games play out the same every run
+            .min_by(|(a_pos, a), (b_pos, b)| {
+                a.total_cmp(b)
+                    .then((a_pos.z, a_pos.x, a_pos.y).cmp(&(b_pos.z, b_pos.x, b_pos.y)))
+            })
+            .map(|(pos, _)| Action::Uncover(*pos))
+            .ok_or(MinrsError::InvalidArgument)
+    }
+}
+
+/// Uncovers any covered tile.
+///
+/// The choices are seeded from each game's seed, so a game plays out the
+/// same whichever thread it lands on.
+pub struct RandomStrategy {
+    rng: StdRng,
+}
+
+impl RandomStrategy {
+    pub fn new() -> RandomStrategy {
+        RandomStrategy {
+            rng: StdRng::seed_from_u64(0),
+        }
+    }
+}
+
+impl Default for RandomStrategy {
+    fn default() -> RandomStrategy {
+        RandomStrategy::new()
+    }
+}
+
+impl Strategy for RandomStrategy {
+    fn start_game(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    fn next_move(&mut self, game: &StdMinrsGame) -> MinrsResult<Action> {
+        let covered: Vec<Position> = game
+            .get_positions()
+            .into_iter()
+            .filter(|pos| matches!(game.get_tile_state(pos), Ok(TileState::Covered(_))))
+            .collect();
+        covered
+            .choose(&mut self.rng)
+            .map(|pos| Action::Uncover(*pos))
+            .ok_or(MinrsError::InvalidArgument)
+    }
+}
+
+/// Asks a bot running as another program for each move.
+pub struct ProcessStrategy {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl ProcessStrategy {
+    /// Start the bot, from a program and its arguments split on whitespace.
+    ///
+    /// throws InvalidArgument if the program could not be started.
+    pub fn new(command: &str) -> MinrsResult<ProcessStrategy> {
+        let mut words = command.split_whitespace();
+        let program = words.next().ok_or(MinrsError::InvalidArgument)?;
+        let mut child = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|_| MinrsError::InvalidArgument)?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        Ok(ProcessStrategy {
+            child,
+            stdin,
+            stdout,
+        })
+    }
+}
+
+impl Strategy for ProcessStrategy {
+    /// throws InvalidData if the bot went away, or answered with something
+    ///        other than a move.
+    fn next_move(&mut self, game: &StdMinrsGame) -> MinrsResult<Action> {
+        writeln!(self.stdin, "{}", draw_board(game)).map_err(|_| MinrsError::InvalidData)?;
+        self.stdin.flush().map_err(|_| MinrsError::InvalidData)?;
+        let mut line = String::new();
+        self.stdout
+            .read_line(&mut line)
+            .map_err(|_| MinrsError::InvalidData)?;
+        let mut words = line.split_whitespace();
+        let command = words.next();
+        let pos = Position::new(parse_word(words.next())?, parse_word(words.next())?);
+        match command {
+            Some("open") => Ok(Action::Uncover(pos)),
+            Some("flag") => Ok(Action::CycleFlag(pos)),
+            Some("chord") => Ok(Action::Chord(pos)),
+            _ => Err(MinrsError::InvalidData),
+        }
+    }
+}
+
+impl Drop for ProcessStrategy {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// How a single benchmarked game went.
+#[derive(Debug, PartialEq, Clone)]
+pub struct GameResult {
+    pub seed: u64,
+    pub won: bool,
+    /// Moves that uncovered a tile the solver couldn't prove safe.
+    pub guesses: u32,
+    /// Only for won games.
+    pub bbbv_per_second: Option<f64>,
+    /// Where the mine went off, for lost games.
+    pub lost_at: Option<Position>,
+    /// The strategy gave up, made a move the game refused, or tried to take
+    /// a move back.
+    pub stalled: bool,
+}
+
+/// What to benchmark.
+#[derive(Debug, Clone, Copy)]
+pub struct BenchConfig {
+    pub difficulty: GameDifficulty,
+    pub games: u32,
+    /// Games are played with this seed and the ones after it.
+    pub first_seed: u64,
+    pub threads: usize,
+}
+
+/// Play one seeded game to the end.
+pub fn play_game(
+    difficulty: GameDifficulty,
+    seed: u64,
+    strategy: &mut dyn Strategy,
+) -> MinrsResult<GameResult> {
+    let mut game = StdMinrsGame::new_seeded(
+        difficulty.get_width(),
+        difficulty.get_height(),
+        difficulty.get_mines(),
+        seed,
+    )?;
+    let mut result = GameResult {
+        seed,
+        won: false,
+        guesses: 0,
+        bbbv_per_second: None,
+        lost_at: None,
+        stalled: false,
+    };
+    // every move changes at least one tile, so this is only hit by a strategy
+    // stuck toggling flags
+    let move_limit = game.get_positions().len() * 4;
+    let mut known_safe = HashSet::new();
+    strategy.start_game(seed);
+    for _ in 0..move_limit {
+        if game.game_over() || game.victory() {
+            break;
+        }
+        let action = match strategy.next_move(&game) {
+            Ok(Action::Undo | Action::Redo) | Err(_) => break,
+            Ok(action) => action,
+        };
+        // a move off the board isn't a guess, and the game refuses it below
+        if is_guess(&game, &action, &mut known_safe).unwrap_or(false) {
+            result.guesses += 1;
+        }
+        if action.apply(&mut game).is_err() {
+            break;
+        }
+    }
+
+    if game.victory() && !game.game_over() {
+        result.won = true;
+        result.bbbv_per_second = Some(analysis::game_stats(&game)?.bbbv_per_second);
+    } else if game.game_over() {
+        result.lost_at = game.get_positions().into_iter().find(|pos| {
+            matches!(
+                game.get_tile_state(pos),
+                Ok(TileState::Uncovered(TileContents::Mines(_)))
+            )
+        });
+    } else {
+        result.stalled = true;
+    }
+    Ok(result)
+}
+
+/// Check if a move uncovers anything the solver can't prove safe.
+///
+/// The first uncover always opens an empty area, so is never a guess.
+/// `known_safe` keeps what the solver proved for earlier moves, which only
+/// needs solving again once a move goes beyond it.
+fn is_guess(
+    game: &StdMinrsGame,
+    action: &Action,
+    known_safe: &mut HashSet<Position>,
+) -> MinrsResult<bool> {
+    let clicks = game.get_clicks();
+    if clicks.uncovers + clicks.chords == 0 {
+        return Ok(false);
+    }
+    let uncovering = match action {
+        Action::Uncover(pos) => vec![*pos],
+        Action::Chord(pos) => game
+            .get_neighbors_pos(pos)?
+            .into_iter()
+            .filter(|n_pos| game.get_tile_state(n_pos).ok() == Some(TileState::Covered(None)))
+            .collect(),
+        _ => return Ok(false),
+    };
+    if uncovering.iter().all(|pos| known_safe.contains(pos)) {
+        return Ok(false);
+    }
+    *known_safe = solver::solve(game)?.safe;
+    Ok(uncovering.iter().any(|pos| !known_safe.contains(pos)))
+}
+
+/// Play every game of the benchmark, spread over `config.threads` threads.
+///
+/// Each thread gets its own strategy from `make_strategy`. Games are handed
+/// out one at a time, and the results sorted by seed, so the report doesn't
+/// depend on the thread count.
+pub fn run<F>(config: &BenchConfig, make_strategy: F) -> MinrsResult<BenchReport>
+where
+    F: Fn() -> MinrsResult<Box<dyn Strategy>> + Sync,
+{
+    let next_game = AtomicU32::new(0);
+    let results = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..config.threads.max(1))
+            .map(|_| {
+                scope.spawn(|| -> MinrsResult<()> {
+                    let mut strategy = make_strategy()?;
+                    loop {
+                        let idx = next_game.fetch_add(1, Ordering::Relaxed);
+                        if idx >= config.games {
+                            return Ok(());
+                        }
+                        let seed = config.first_seed.wrapping_add(idx as u64);
+                        let result = play_game(config.difficulty, seed, strategy.as_mut())?;
+                        results.lock().unwrap().push(result);
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .try_for_each(|worker| worker.join().unwrap())
+    })?;
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|result| result.seed);
+    Ok(BenchReport {
+        difficulty: config.difficulty,
+        results,
+    })
+}
+
+/// Every game of a benchmark, and totals over them.
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    pub difficulty: GameDifficulty,
+    pub results: Vec<GameResult>,
+}
+
+impl BenchReport {
+    pub fn get_wins(&self) -> usize {
+        self.results.iter().filter(|result| result.won).count()
+    }
+
+    pub fn get_win_rate(&self) -> f64 {
+        if self.results.is_empty() {
+            return 0.0;
+        }
+        self.get_wins() as f64 / self.results.len() as f64
+    }
+
+    /// The mean 3BV/s of the won games.
+    pub fn get_mean_bbbv_per_second(&self) -> f64 {
+        let rates: Vec<f64> = self
+            .results
+            .iter()
+            .filter_map(|result| result.bbbv_per_second)
+            .collect();
+        if rates.is_empty() {
+            return 0.0;
+        }
+        rates.iter().sum::<f64>() / rates.len() as f64
+    }
+
+    pub fn get_mean_guesses(&self) -> f64 {
+        if self.results.is_empty() {
+            return 0.0;
+        }
+        let guesses: u32 = self.results.iter().map(|result| result.guesses).sum();
+        guesses as f64 / self.results.len() as f64
+    }
+
+    pub fn get_stalled(&self) -> usize {
+        self.results.iter().filter(|result| result.stalled).count()
+    }
+
+    /// Get how many games were lost at each position, most first.
+    pub fn get_failures(&self) -> Vec<(Position, usize)> {
+        let mut counts: HashMap<Position, usize> = HashMap::new();
+        for pos in self.results.iter().filter_map(|result| result.lost_at) {
+            *counts.entry(pos).or_default() += 1;
+        }
+        let mut failures: Vec<(Position, usize)> = counts.into_iter().collect();
+        failures.sort_by_key(|(pos, count)| (std::cmp::Reverse(*count), pos.y, pos.x));
+        failures
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{}: {} games, {} won ({:.1}%), {:.2} 3BV/s, {:.2} guesses a game, {} stalled",
+            self.difficulty.get_name(),
+            self.results.len(),
+            self.get_wins(),
+            self.get_win_rate() * 100.0,
+            self.get_mean_bbbv_per_second(),
+            self.get_mean_guesses(),
+            self.get_stalled(),
+        )?;
+        let failures: Vec<String> = self
+            .get_failures()
+            .iter()
+            .take(5)
+            .map(|(pos, count)| format!("{},{} x{count}", pos.x, pos.y))
+            .collect();
+        if !failures.is_empty() {
+            writeln!(f, "  lost most at: {}", failures.join("  "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(threads: usize) -> BenchConfig {
+        BenchConfig {
+            difficulty: GameDifficulty::Easy,
+            games: 24,
+            first_seed: 100,
+            threads,
+        }
+    }
+
+    /// How each game went, leaving out the speed, which is wall clock time.
+    fn outcomes(report: &BenchReport) -> Vec<(u64, bool, u32, Option<Position>)> {
+        report
+            .results
+            .iter()
+            .map(|result| (result.seed, result.won, result.guesses, result.lost_at))
+            .collect()
+    }
+
+    #[test]
+    fn test_bench_is_independent_of_threads() {
+        let make_solver =
+            || -> MinrsResult<Box<dyn Strategy>> { Ok(Box::new(SolverStrategy::new())) };
+        let single = run(&config(1), make_solver).unwrap();
+        let many = run(&config(4), make_solver).unwrap();
+        assert_eq!(outcomes(&single), outcomes(&many));
+        assert_eq!(single.results.len(), 24);
+        assert_eq!(single.results[0].seed, 100);
+        assert_eq!(single.get_stalled(), 0);
+        // easy boards mostly fall to logic
+        assert!(single.get_win_rate() > 0.5);
+        assert!(single
+            .results
+            .iter()
+            .all(|result| result.won == result.bbbv_per_second.is_some()));
+    }
+
+    #[test]
+    fn test_random_strategy_guesses() {
+        let make_random =
+            || -> MinrsResult<Box<dyn Strategy>> { Ok(Box::new(RandomStrategy::new())) };
+        let report = run(&config(2), make_random).unwrap();
+        // the same games with more threads pick the same tiles
+        let many = run(&config(4), make_random).unwrap();
+        let single = run(&config(1), make_random).unwrap();
+        assert_eq!(outcomes(&report), outcomes(&many));
+        assert_eq!(outcomes(&report), outcomes(&single));
+        let losses = report.results.len() - report.get_wins();
+        assert!(losses > 0);
+        let failures: usize = report.get_failures().iter().map(|(_, count)| count).sum();
+        assert_eq!(failures, losses);
+        // only the first uncover is free
+        assert!(report.get_mean_guesses() >= 1.0);
+    }
+
+    #[test]
+    fn test_stalled_strategy() {
+        struct Undoer;
+        impl Strategy for Undoer {
+            fn next_move(&mut self, _game: &StdMinrsGame) -> MinrsResult<Action> {
+                Ok(Action::Undo)
+            }
+        }
+        let result = play_game(GameDifficulty::Easy, 0, &mut Undoer).unwrap();
+        assert!(result.stalled);
+        assert!(!result.won);
+        assert_eq!(result.lost_at, None);
+    }
+
+    /// Save a shell script bot, to run with `sh`.
+    fn write_bot(name: &str, script: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "min-rs-weeper-bot-{name}-{}.sh",
+            std::process::id()
+        ));
+        std::fs::write(&path, script).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_process_strategy() {
+        // a bot that opens the first covered tile, reading row by row
+        let script = r##"
+while read ok width height; do
+    y=0
+    move=""
+    while [ $y -lt $height ]; do
+        read row
+        case $row in
+            *"#"*) [ -z "$move" ] && before=${row%%#*} && move="open ${#before} $y" ;;
+        esac
+        y=$((y + 1))
+    done
+    echo "$move"
+done
+"##;
+        let path = write_bot("reader", script);
+        let mut bot = ProcessStrategy::new(&format!("sh {}", path.display())).unwrap();
+        // the same bot plays game after game
+        let results: Vec<GameResult> = (0..3)
+            .map(|seed| play_game(GameDifficulty::Easy, seed, &mut bot).unwrap())
+            .collect();
+        std::fs::remove_file(&path).unwrap();
+        for result in &results {
+            assert!(!result.stalled);
+            assert!(result.won || result.lost_at.is_some());
+        }
+        // the first move is free, and later opens in reading order mostly guess
+        assert!(results.iter().any(|result| result.guesses > 0));
+    }
+
+    /// A bot chording off the board only stalls its own game.
+    #[test]
+    fn test_process_strategy_off_board() {
+        let script = r##"
+moves=0
+while read ok width height; do
+    y=0
+    while [ $y -lt $height ]; do
+        read row
+        y=$((y + 1))
+    done
+    if [ $((moves % 2)) -eq 0 ]; then echo "open 4 4"; else echo "chord 99 99"; fi
+    moves=$((moves + 1))
+done
+"##;
+        let path = write_bot("off-board", script);
+        let command = format!("sh {}", path.display());
+        let config = BenchConfig {
+            games: 3,
+            ..config(1)
+        };
+        let report = run(&config, || -> MinrsResult<Box<dyn Strategy>> {
+            Ok(Box::new(ProcessStrategy::new(&command)?))
+        })
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(report.results.len(), 3);
+        assert!(report.get_stalled() > 0);
+    }
+}
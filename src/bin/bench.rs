@@ -0,0 +1,104 @@
+// Copyright 2022 nitepone <luna@night.horse>
+//
+// Play lots of seeded games with a strategy, and report how it did.
+
+use min_rs_weeper::bench::Strategy;
+use min_rs_weeper::bench::{self, BenchConfig, ProcessStrategy, RandomStrategy, SolverStrategy};
+use min_rs_weeper::difficulty::GameDifficulty;
+use min_rs_weeper::error::MinrsResult;
+use std::env;
+use std::process::ExitCode;
+use std::thread;
+
+const USAGE: &str = "usage: min-rs-weeper-bench [options]
+
+  --games <n>          games per difficulty (default 1000)
+  --seed <seed>        seed of the first game (default 0)
+  --threads <n>        threads to play on (default every core)
+  --difficulty <name>  easy, medium or hard, may be repeated (default all)
+  --strategy <name>    solver or random (default solver)
+  --bot <command>      ask another program for moves, see src/bench.rs";
+
+enum StrategyChoice {
+    Solver,
+    Random,
+    Bot(String),
+}
+
+struct Args {
+    games: u32,
+    seed: u64,
+    threads: usize,
+    difficulties: Vec<GameDifficulty>,
+    strategy: StrategyChoice,
+}
+
+fn parse_args() -> Option<Args> {
+    let mut args = Args {
+        games: 1000,
+        seed: 0,
+        threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        difficulties: Vec::new(),
+        strategy: StrategyChoice::Solver,
+    };
+    let mut words = env::args().skip(1);
+    while let Some(option) = words.next() {
+        let value = words.next()?;
+        match option.as_str() {
+            "--games" => args.games = value.parse().ok()?,
+            "--seed" => args.seed = value.parse().ok()?,
+            "--threads" => args.threads = value.parse().ok().filter(|&n| n > 0)?,
+            "--difficulty" => args.difficulties.push(GameDifficulty::from_name(&value)?),
+            "--strategy" => {
+                args.strategy = match value.as_str() {
+                    "solver" => StrategyChoice::Solver,
+                    "random" => StrategyChoice::Random,
+                    _ => return None,
+                }
+            }
+            "--bot" => args.strategy = StrategyChoice::Bot(value),
+            _ => return None,
+        }
+    }
+    if args.difficulties.is_empty() {
+        args.difficulties = vec![
+            GameDifficulty::Easy,
+            GameDifficulty::Medium,
+            GameDifficulty::Hard,
+        ];
+    }
+    Some(args)
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Some(args) => args,
+        None => {
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let make_strategy = || -> MinrsResult<Box<dyn Strategy>> {
+        Ok(match &args.strategy {
+            StrategyChoice::Solver => Box::new(SolverStrategy::new()),
+            StrategyChoice::Random => Box::new(RandomStrategy::new()),
+            StrategyChoice::Bot(command) => Box::new(ProcessStrategy::new(command)?),
+        })
+    };
+    for difficulty in &args.difficulties {
+        let config = BenchConfig {
+            difficulty: *difficulty,
+            games: args.games,
+            first_seed: args.seed,
+            threads: args.threads,
+        };
+        match bench::run(&config, make_strategy) {
+            Ok(report) => print!("{report}"),
+            Err(e) => {
+                eprintln!("min-rs-weeper-bench: {e:?}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,22 @@
+// Copyright 2022 nitepone <luna@night.horse>
+//
+// Play over stdin and stdout, for bots. See `protocol` for the commands.
+
+use min_rs_weeper::protocol::Session;
+use std::io::{self, BufRead, Write};
+
+fn main() -> io::Result<()> {
+    let mut session = Session::new();
+    let mut out = io::stdout().lock();
+    for line in io::stdin().lock().lines() {
+        if let Some(reply) = session.handle(&line?) {
+            writeln!(out, "{reply}")?;
+            // the other side waits for each reply before sending more
+            out.flush()?;
+        }
+        if session.is_finished() {
+            break;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,78 @@
+// Copyright 2022 nitepone <luna@night.horse>
+//
+// Host a board for several players, either shared or raced on. See `coop` and
+// `race` for what goes over the wire.
+
+use min_rs_weeper::coop::CoopServer;
+use min_rs_weeper::difficulty::GameDifficulty;
+use min_rs_weeper::race::RaceServer;
+use std::env;
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: min-rs-weeper-server [options]
+
+  --address <addr>     address to listen on (default 0.0.0.0)
+  --port <port>        port to listen on (default 7373)
+  --difficulty <name>  easy, medium or hard (default medium)
+  --race               give everyone their own copy of the board to race on";
+
+struct Args {
+    address: String,
+    port: u16,
+    difficulty: GameDifficulty,
+    race: bool,
+}
+
+fn parse_args() -> Option<Args> {
+    let mut args = Args {
+        address: "0.0.0.0".to_string(),
+        port: 7373,
+        difficulty: GameDifficulty::Medium,
+        race: false,
+    };
+    let mut words = env::args().skip(1);
+    while let Some(option) = words.next() {
+        if option == "--race" {
+            args.race = true;
+            continue;
+        }
+        let value = words.next()?;
+        match option.as_str() {
+            "--address" => args.address = value,
+            "--port" => args.port = value.parse().ok()?,
+            "--difficulty" => args.difficulty = GameDifficulty::from_name(&value)?,
+            _ => return None,
+        }
+    }
+    Some(args)
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Some(args) => args,
+        None => {
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let addr = (args.address.as_str(), args.port);
+    let name = args.difficulty.get_name();
+    let result = if args.race {
+        RaceServer::bind(addr, args.difficulty).and_then(|server| {
+            eprintln!("hosting {name} races on {}", server.local_addr()?);
+            server.run()
+        })
+    } else {
+        CoopServer::bind(addr, args.difficulty).and_then(|server| {
+            eprintln!("hosting {name} on {}", server.local_addr()?);
+            server.run()
+        })
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("min-rs-weeper-server: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
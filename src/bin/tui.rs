@@ -0,0 +1,445 @@
+// Copyright 2022 nitepone <luna@night.horse>
+//
+// Play in a terminal, for when there's no window to open, either alone or
+// on a co-op server.
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{
+    self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, MouseButton,
+    MouseEvent, MouseEventKind,
+};
+use crossterm::style::{Attribute, Color, Print, ResetColor, SetAttribute, SetForegroundColor};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use min_rs_weeper::coop::{self, ClientMessage, CoopBoard, CoopClient, ServerMessage};
+use min_rs_weeper::difficulty::GameDifficulty;
+use min_rs_weeper::game::{
+    FlagState, GameStatus, MinrsGame, Position, StdMinrsGame, TileContents, TileState,
+};
+use std::env;
+use std::io::{self, Write};
+use std::process::ExitCode;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::Duration;
+
+/// The board starts on this row, below the status line.
+const BOARD_TOP: u16 = 2;
+/// Each tile takes this many columns, so the board isn't squashed.
+const TILE_COLUMNS: u16 = 2;
+/// How often the clock is redrawn while waiting for input.
+const TICK: Duration = Duration::from_millis(100);
+const HELP: &str = "arrows/hjkl move  space uncover  f flag  c chord  u undo  r redo  \
+                    1/2/3 new easy/medium/hard  n new  q quit";
+const COOP_HELP: &str = "arrows/hjkl move  space uncover  f flag  c chord  n new  q quit";
+const USAGE: &str = "usage: min-rs-weeper-tui [easy|medium|hard]
+       min-rs-weeper-tui --join <host:port>";
+
+/// Puts the terminal back the way it was, however the game ends.
+struct RawTerminal;
+
+impl RawTerminal {
+    fn enter() -> io::Result<RawTerminal> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen, EnableMouseCapture, Hide)?;
+        Ok(RawTerminal)
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let _ = execute!(
+            io::stdout(),
+            Show,
+            DisableMouseCapture,
+            LeaveAlternateScreen
+        );
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Move a position by `x` and `y`, stopping at the edges of the board.
+///
+/// A co-op board is empty until the server sends it, so there's nowhere to
+/// move to and this gives None.
+fn moved(pos: &Position, x: i32, y: i32, width: u16, height: u16) -> Option<Position> {
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let clamp = |value: u16, by: i32, size: u16| (value as i32 + by).clamp(0, size as i32 - 1);
+    Some(Position::new(
+        clamp(pos.x, x, width) as u16,
+        clamp(pos.y, y, height) as u16,
+    ))
+}
+
+/// Where the board being shown lives.
+enum Play {
+    Local {
+        difficulty: GameDifficulty,
+        game: Box<StdMinrsGame>,
+    },
+    /// A copy of a board on a co-op server.
+    Coop {
+        client: CoopClient,
+        board: CoopBoard,
+        inbox: Receiver<ServerMessage>,
+        /// What went wrong last, to show until the next move.
+        problem: Option<String>,
+    },
+}
+
+struct Tui {
+    play: Play,
+    cursor: Position,
+}
+
+impl Tui {
+    fn new(difficulty: GameDifficulty) -> Tui {
+        Tui {
+            play: Play::Local {
+                difficulty,
+                game: Box::new(difficulty.new_game().unwrap()),
+            },
+            cursor: Position::new(0, 0),
+        }
+    }
+
+    /// Join a co-op server, which sends the board once we're connected.
+    fn join(addr: &str) -> io::Result<Tui> {
+        let client = CoopClient::connect(addr)?;
+        let (sender, inbox) = mpsc::channel();
+        client.listen(move |message| {
+            let _ = sender.send(message);
+        })?;
+        Ok(Tui {
+            play: Play::Coop {
+                client,
+                board: CoopBoard::new(),
+                inbox,
+                problem: None,
+            },
+            cursor: Position::new(0, 0),
+        })
+    }
+
+    fn restart(&mut self, new_difficulty: GameDifficulty) {
+        if let Play::Local { .. } = self.play {
+            *self = Tui::new(new_difficulty);
+        }
+    }
+
+    fn get_width(&self) -> u16 {
+        match &self.play {
+            Play::Local { game, .. } => game.get_width(),
+            Play::Coop { board, .. } => board.get_width(),
+        }
+    }
+
+    fn get_height(&self) -> u16 {
+        match &self.play {
+            Play::Local { game, .. } => game.get_height(),
+            Play::Coop { board, .. } => board.get_height(),
+        }
+    }
+
+    fn get_tile_state(&self, pos: &Position) -> TileState {
+        match &self.play {
+            Play::Local { game, .. } => game.get_tile_state(pos).unwrap(),
+            Play::Coop { board, .. } => board.get_tile_state(pos),
+        }
+    }
+
+    /// Make a move, here or on the server.
+    fn play_move(&mut self, message: ClientMessage) {
+        match &mut self.play {
+            Play::Local { difficulty, game } => {
+                let _ = match message {
+                    ClientMessage::Open(pos) => game.uncover_tile(&pos),
+                    ClientMessage::Flag(pos) => game.cycle_flag(&pos),
+                    ClientMessage::Chord(pos) => game.uncover_neighbors(&pos),
+                    ClientMessage::NewGame => {
+                        let difficulty = *difficulty;
+                        self.restart(difficulty);
+                        Ok(())
+                    }
+                };
+            }
+            Play::Coop {
+                client, problem, ..
+            } => {
+                *problem = client.send(message).err().map(|e| e.to_string());
+            }
+        }
+    }
+
+    /// Catch up with what the server sent since the last frame.
+    fn receive(&mut self) {
+        if let Play::Coop {
+            board,
+            inbox,
+            problem,
+            ..
+        } = &mut self.play
+        {
+            loop {
+                match inbox.try_recv() {
+                    Ok(ServerMessage::Error(code)) => *problem = Some(code),
+                    Ok(message) => board.apply(&message),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        *problem = Some("disconnected".to_string());
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    fn move_cursor(&mut self, x: i32, y: i32) {
+        if let Some(cursor) = moved(&self.cursor, x, y, self.get_width(), self.get_height()) {
+            self.cursor = cursor;
+        }
+    }
+
+    /// Uncover a covered tile, or chord a number.
+    fn open(&mut self, pos: &Position) {
+        match self.get_tile_state(pos) {
+            TileState::Covered(_) => self.play_move(ClientMessage::Open(*pos)),
+            TileState::Uncovered(_) => self.play_move(ClientMessage::Chord(*pos)),
+        }
+    }
+
+    /// Flag a covered tile, or chord a number, like a right click in the
+    /// window.
+    fn mark(&mut self, pos: &Position) {
+        match self.get_tile_state(pos) {
+            TileState::Covered(_) => self.play_move(ClientMessage::Flag(*pos)),
+            TileState::Uncovered(_) => self.play_move(ClientMessage::Chord(*pos)),
+        }
+    }
+
+    /// Get the tile under a terminal cell, if there is one.
+    fn tile_at(&self, column: u16, row: u16) -> Option<Position> {
+        let x = column / TILE_COLUMNS;
+        let y = row.checked_sub(BOARD_TOP)?;
+        if x >= self.get_width() || y >= self.get_height() {
+            return None;
+        }
+        Some(Position::new(x, y))
+    }
+
+    /// Handle a key, returning false to quit.
+    fn key(&mut self, key: KeyEvent) -> bool {
+        let cursor = self.cursor;
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Left | KeyCode::Char('h') => self.move_cursor(-1, 0),
+            KeyCode::Right | KeyCode::Char('l') => self.move_cursor(1, 0),
+            KeyCode::Up | KeyCode::Char('k') => self.move_cursor(0, -1),
+            KeyCode::Down | KeyCode::Char('j') => self.move_cursor(0, 1),
+            KeyCode::Char(' ') | KeyCode::Enter => self.open(&cursor),
+            KeyCode::Char('f') => self.play_move(ClientMessage::Flag(cursor)),
+            KeyCode::Char('c') => self.play_move(ClientMessage::Chord(cursor)),
+            // there's no taking back a move someone else may have built on
+            KeyCode::Char('u') => {
+                if let Play::Local { game, .. } = &mut self.play {
+                    let _ = game.undo();
+                }
+            }
+            KeyCode::Char('r') => {
+                if let Play::Local { game, .. } = &mut self.play {
+                    let _ = game.redo();
+                }
+            }
+            KeyCode::Char('n') => self.play_move(ClientMessage::NewGame),
+            KeyCode::Char('1') => self.restart(GameDifficulty::Easy),
+            KeyCode::Char('2') => self.restart(GameDifficulty::Medium),
+            KeyCode::Char('3') => self.restart(GameDifficulty::Hard),
+            _ => {}
+        }
+        true
+    }
+
+    fn mouse(&mut self, mouse: MouseEvent) {
+        let pos = match self.tile_at(mouse.column, mouse.row) {
+            Some(pos) => pos,
+            None => return,
+        };
+        self.cursor = pos;
+        match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) => self.open(&pos),
+            MouseEventKind::Down(MouseButton::Right) => self.mark(&pos),
+            _ => {}
+        }
+    }
+
+    fn draw(&self, out: &mut impl Write) -> io::Result<()> {
+        // draw over the last frame rather than clearing it, which flickers
+        queue!(out, MoveTo(0, 0))?;
+        let (line, help) = match &self.play {
+            Play::Local { difficulty, game } => {
+                let status = if game.game_over() {
+                    "BOOM! u to undo, n for a new game"
+                } else if game.victory() {
+                    "Cleared! n for a new game"
+                } else if game.is_no_guess_failed() {
+                    "no guess-free board found, this one may need a guess"
+                } else {
+                    ""
+                };
+                let elapsed = game.get_elapsed();
+                let line = format!(
+                    "{}  mines {}  time {}:{:02}  {status}",
+                    difficulty.get_name(),
+                    game.get_mine_count() as i64 - self.flag_count() as i64,
+                    elapsed.as_secs() / 60,
+                    elapsed.as_secs() % 60,
+                );
+                (line, HELP)
+            }
+            Play::Coop { board, problem, .. } => {
+                let status = match board.get_status() {
+                    GameStatus::Lost => "BOOM! n for a new game",
+                    GameStatus::Won => "Cleared! n for a new game",
+                    GameStatus::Playing => "",
+                };
+                let player = match board.get_player() {
+                    Some(player) => format!("player {player}"),
+                    None => "joining".to_string(),
+                };
+                let line = format!(
+                    "co-op {player}  mines {}  {status}  {}",
+                    board.get_mine_count() as i64 - self.flag_count() as i64,
+                    problem.as_deref().unwrap_or_default(),
+                );
+                (line, COOP_HELP)
+            }
+        };
+        queue!(out, Print(line), Clear(ClearType::UntilNewLine))?;
+        for y in 0..self.get_height() {
+            queue!(out, MoveTo(0, BOARD_TOP + y))?;
+            for x in 0..self.get_width() {
+                let pos = Position::new(x, y);
+                if pos == self.cursor {
+                    queue!(out, SetAttribute(Attribute::Reverse))?;
+                }
+                let state = self.get_tile_state(&pos);
+                queue!(
+                    out,
+                    SetForegroundColor(self.tile_color(&pos, state)),
+                    Print(state.to_char()),
+                    ResetColor
+                )?;
+                queue!(out, SetAttribute(Attribute::Reset), Print(" "))?;
+            }
+            queue!(out, Clear(ClearType::UntilNewLine))?;
+        }
+        // a smaller board leaves the last one's rows below it
+        queue!(
+            out,
+            MoveTo(0, BOARD_TOP + self.get_height()),
+            Clear(ClearType::FromCursorDown),
+            MoveTo(0, BOARD_TOP + self.get_height() + 1),
+            Print(help)
+        )?;
+        out.flush()
+    }
+
+    fn flag_count(&self) -> u32 {
+        (0..self.get_height())
+            .flat_map(|y| (0..self.get_width()).map(move |x| Position::new(x, y)))
+            .map(|pos| match self.get_tile_state(&pos) {
+                TileState::Covered(Some(FlagState::RedFlag(count))) => count as u32,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// Get the colour of a tile, giving each co-op player's flags their own.
+    fn tile_color(&self, pos: &Position, state: TileState) -> Color {
+        if let Play::Coop { board, .. } = &self.play {
+            if let (TileState::Covered(Some(_)), Some(owner)) = (state, board.get_owner(pos)) {
+                let (r, g, b) = coop::player_color(owner);
+                return Color::Rgb { r, g, b };
+            }
+        }
+        tile_color(state)
+    }
+}
+
+/// Get the colour showing a tile, with numbers in the usual minesweeper
+/// colours.
+fn tile_color(state: TileState) -> Color {
+    match state {
+        TileState::Covered(None) => Color::DarkGrey,
+        TileState::Covered(Some(FlagState::RedFlag(_))) => Color::Red,
+        TileState::Covered(Some(FlagState::Questionable)) => Color::Yellow,
+        TileState::Uncovered(TileContents::Mines(_)) => Color::Red,
+        TileState::Uncovered(TileContents::MineCount(count)) => match count {
+            0 => Color::Reset,
+            1 => Color::Blue,
+            2 => Color::Green,
+            3 => Color::Red,
+            4 => Color::DarkBlue,
+            5 => Color::DarkRed,
+            6 => Color::Cyan,
+            7 => Color::Magenta,
+            _ => Color::Grey,
+        },
+    }
+}
+
+fn run(tui: &mut Tui) -> io::Result<()> {
+    let _terminal = RawTerminal::enter()?;
+    let mut out = io::stdout();
+    loop {
+        tui.receive();
+        tui.draw(&mut out)?;
+        // wake up now and then to move the clock along
+        if !event::poll(TICK)? {
+            continue;
+        }
+        match event::read()? {
+            Event::Key(key) if !tui.key(key) => return Ok(()),
+            Event::Mouse(mouse) => tui.mouse(mouse),
+            _ => {}
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let tui = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] => Ok(Tui::new(GameDifficulty::Easy)),
+        ["--join", addr] => Tui::join(addr),
+        [name] if GameDifficulty::from_name(name).is_some() => {
+            Ok(Tui::new(GameDifficulty::from_name(name).unwrap()))
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match tui.and_then(|mut tui| run(&mut tui)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("min-rs-weeper-tui: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_moved() {
+        let pos = Position::new(1, 1);
+        assert_eq!(moved(&pos, 1, 0, 9, 9), Some(Position::new(2, 1)));
+        assert_eq!(moved(&pos, -5, 20, 9, 9), Some(Position::new(0, 8)));
+        // a co-op board that hasn't arrived yet
+        assert_eq!(moved(&pos, 0, 1, 0, 0), None);
+        assert_eq!(moved(&pos, 1, 0, 9, 0), None);
+    }
+}
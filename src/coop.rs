@@ -0,0 +1,824 @@
+// Copyright 2022 nitepone <luna@night.horse>
+//
+// Several players clearing one board together over TCP.
+//
+// The server holds the only real game, and every client keeps a copy of
+// what can be seen of it. Messages are lines of words. Clients send moves:
+//
+//     open <x> <y>
+//     flag <x> <y>
+//     chord <x> <y>
+//     new                               (a new board of the same size, once
+//                                        this one is won or lost)
+//
+// and the server tells every client what changed:
+//
+//     player <id>                       (sent once, to the client it names)
+//     board <width> <height> <mines>    (a new board, with every tile covered)
+//     tile <x> <y> <char> [<id>]        (a tile, and who flagged it)
+//     state playing|won|lost
+//     err <code>                        (to the client whose move failed)
+//
+// Tiles are written with `TileState::to_char`. A client that joins late is
+// sent the board, then every tile that isn't plainly covered, then the state.
+
+use crate::difficulty::GameDifficulty;
+use crate::error::{MinrsError, MinrsResult};
+use crate::game::{
+    parse_word, GameEvent, GameStatus, MinrsGame, Position, StdMinrsGame, TileState,
+};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Colours that tell players' flags apart, as RGB. Player ids wrap around
+/// them.
+pub const PLAYER_COLORS: [(u8, u8, u8); 6] = [
+    (0xd0, 0x20, 0x20),
+    (0x20, 0x60, 0xd0),
+    (0x20, 0xa0, 0x40),
+    (0xc0, 0x70, 0x00),
+    (0x90, 0x30, 0xb0),
+    (0x00, 0x90, 0xa0),
+];
+
+pub fn player_color(player: u32) -> (u8, u8, u8) {
+    PLAYER_COLORS[player as usize % PLAYER_COLORS.len()]
+}
+
+/// A move sent by a client.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ClientMessage {
+    Open(Position),
+    Flag(Position),
+    Chord(Position),
+    NewGame,
+}
+
+impl fmt::Display for ClientMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClientMessage::Open(pos) => write!(f, "open {} {}", pos.x, pos.y),
+            ClientMessage::Flag(pos) => write!(f, "flag {} {}", pos.x, pos.y),
+            ClientMessage::Chord(pos) => write!(f, "chord {} {}", pos.x, pos.y),
+            ClientMessage::NewGame => write!(f, "new"),
+        }
+    }
+}
+
+impl FromStr for ClientMessage {
+    type Err = MinrsError;
+
+    fn from_str(line: &str) -> MinrsResult<ClientMessage> {
+        let mut words = line.split_whitespace();
+        let command = words.next();
+        if command == Some("new") {
+            return Ok(ClientMessage::NewGame);
+        }
+        let pos = Position::new(parse_word(words.next())?, parse_word(words.next())?);
+        match command {
+            Some("open") => Ok(ClientMessage::Open(pos)),
+            Some("flag") => Ok(ClientMessage::Flag(pos)),
+            Some("chord") => Ok(ClientMessage::Chord(pos)),
+            _ => Err(MinrsError::InvalidData),
+        }
+    }
+}
+
+/// Something the server tells clients.
+#[derive(Debug, PartialEq, Clone)]
+pub enum ServerMessage {
+    /// The id of the client receiving this.
+    Player(u32),
+    Board {
+        width: u16,
+        height: u16,
+        mines: u32,
+    },
+    Tile {
+        pos: Position,
+        state: TileState,
+        /// Who placed the flag, on a flagged tile.
+        owner: Option<u32>,
+    },
+    State(GameStatus),
+    /// The code of the MinrsError a move failed with.
+    Error(String),
+}
+
+impl fmt::Display for ServerMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ServerMessage::Player(id) => write!(f, "player {id}"),
+            ServerMessage::Board {
+                width,
+                height,
+                mines,
+            } => write!(f, "board {width} {height} {mines}"),
+            ServerMessage::Tile { pos, state, owner } => {
+                write!(f, "tile {} {} {}", pos.x, pos.y, state.to_char())?;
+                match owner {
+                    Some(owner) => write!(f, " {owner}"),
+                    None => Ok(()),
+                }
+            }
+            ServerMessage::State(status) => write!(f, "state {status}"),
+            ServerMessage::Error(code) => write!(f, "err {code}"),
+        }
+    }
+}
+
+impl FromStr for ServerMessage {
+    type Err = MinrsError;
+
+    fn from_str(line: &str) -> MinrsResult<ServerMessage> {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("player") => Ok(ServerMessage::Player(parse_word(words.next())?)),
+            Some("board") => Ok(ServerMessage::Board {
+                width: parse_word(words.next())?,
+                height: parse_word(words.next())?,
+                mines: parse_word(words.next())?,
+            }),
+            Some("tile") => {
+                let pos = Position::new(parse_word(words.next())?, parse_word(words.next())?);
+                let glyph: char = parse_word(words.next())?;
+                Ok(ServerMessage::Tile {
+                    pos,
+                    state: TileState::from_char(glyph).ok_or(MinrsError::InvalidData)?,
+                    owner: words
+                        .next()
+                        .map(|word| parse_word(Some(word)))
+                        .transpose()?,
+                })
+            }
+            Some("state") => Ok(ServerMessage::State(parse_word(words.next())?)),
+            Some("err") => Ok(ServerMessage::Error(parse_word(words.next())?)),
+            _ => Err(MinrsError::InvalidData),
+        }
+    }
+}
+
+/// A client's copy of the shared board.
+#[derive(Debug, Clone)]
+pub struct CoopBoard {
+    player: Option<u32>,
+    width: u16,
+    height: u16,
+    mines: u32,
+    tiles: HashMap<Position, TileState>,
+    owners: HashMap<Position, u32>,
+    status: GameStatus,
+}
+
+impl Default for CoopBoard {
+    fn default() -> CoopBoard {
+        CoopBoard {
+            player: None,
+            width: 0,
+            height: 0,
+            mines: 0,
+            tiles: HashMap::new(),
+            owners: HashMap::new(),
+            status: GameStatus::Playing,
+        }
+    }
+}
+
+impl CoopBoard {
+    pub fn new() -> CoopBoard {
+        CoopBoard::default()
+    }
+
+    /// Bring the board up to date with a message from the server.
+    pub fn apply(&mut self, message: &ServerMessage) {
+        match message {
+            ServerMessage::Player(id) => self.player = Some(*id),
+            ServerMessage::Board {
+                width,
+                height,
+                mines,
+            } => {
+                self.width = *width;
+                self.height = *height;
+                self.mines = *mines;
+                self.tiles.clear();
+                self.owners.clear();
+                self.status = GameStatus::Playing;
+            }
+            ServerMessage::Tile { pos, state, owner } => {
+                self.tiles.insert(*pos, *state);
+                match owner {
+                    Some(owner) => self.owners.insert(*pos, *owner),
+                    None => self.owners.remove(pos),
+                };
+            }
+            ServerMessage::State(status) => self.status = *status,
+            ServerMessage::Error(_) => {}
+        }
+    }
+
+    /// Get the id the server gave us.
+    pub fn get_player(&self) -> Option<u32> {
+        self.player
+    }
+
+    pub fn get_width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn get_height(&self) -> u16 {
+        self.height
+    }
+
+    pub fn get_mine_count(&self) -> u32 {
+        self.mines
+    }
+
+    pub fn get_status(&self) -> GameStatus {
+        self.status
+    }
+
+    pub fn get_tile_state(&self, pos: &Position) -> TileState {
+        self.tiles
+            .get(pos)
+            .copied()
+            .unwrap_or(TileState::Covered(None))
+    }
+
+    /// Get who flagged a tile.
+    pub fn get_owner(&self, pos: &Position) -> Option<u32> {
+        self.owners.get(pos).copied()
+    }
+}
+
+/// A connection to a co-op server.
+pub struct CoopClient {
+    stream: TcpStream,
+}
+
+impl CoopClient {
+    pub fn connect(addr: impl ToSocketAddrs) -> io::Result<CoopClient> {
+        let stream = TcpStream::connect(addr)?;
+        // moves are tiny, don't hold them back
+        stream.set_nodelay(true)?;
+        Ok(CoopClient { stream })
+    }
+
+    pub fn send(&mut self, message: ClientMessage) -> io::Result<()> {
+        writeln!(self.stream, "{message}")
+    }
+
+    /// Leave the game, which also stops the thread reading messages.
+    pub fn disconnect(&self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+
+    /// Read messages from the server on a new thread, handing each to
+    /// `handler` until the connection closes.
+    ///
+    /// Lines that can't be read are skipped.
+    pub fn listen<F>(&self, mut handler: F) -> io::Result<thread::JoinHandle<()>>
+    where
+        F: FnMut(ServerMessage) + Send + 'static,
+    {
+        let reader = BufReader::new(self.stream.try_clone()?);
+        Ok(thread::spawn(move || {
+            for line in reader.lines() {
+                match line {
+                    Ok(line) => {
+                        if let Ok(message) = line.parse() {
+                            handler(message);
+                        }
+                    }
+                    Err(_) => return,
+                }
+            }
+        }))
+    }
+}
+
+/// Longest line a server reads from a client. Messages are a few words, so
+/// anything longer is a client gone wrong.
+pub(crate) const MAX_LINE: usize = 256;
+/// Writes that may wait for a slow client before it is dropped.
+const MAX_QUEUED: usize = 256;
+
+/// Read a line from a client, without its newline. Gives false once the
+/// client is gone.
+///
+/// throws InvalidData for a line longer than MAX_LINE, so a client can't
+/// have the server hold any amount of text.
+pub(crate) fn read_line(reader: &mut impl BufRead, line: &mut String) -> io::Result<bool> {
+    line.clear();
+    if reader.by_ref().take(MAX_LINE as u64 + 1).read_line(line)? == 0 {
+        return Ok(false);
+    }
+    if !line.ends_with('\n') && line.len() > MAX_LINE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "line too long"));
+    }
+    line.truncate(line.trim_end_matches(['\r', '\n']).len());
+    Ok(true)
+}
+
+/// Writes to one client on a thread of its own, so a client that stops
+/// reading only holds up itself.
+pub(crate) struct Outbox {
+    queue: SyncSender<String>,
+    stream: TcpStream,
+}
+
+impl Outbox {
+    pub(crate) fn new(stream: &TcpStream) -> io::Result<Outbox> {
+        let (queue, waiting) = mpsc::sync_channel::<String>(MAX_QUEUED);
+        let mut writer = stream.try_clone()?;
+        thread::spawn(move || {
+            for text in waiting {
+                if writer.write_all(text.as_bytes()).is_err() {
+                    let _ = writer.shutdown(Shutdown::Both);
+                    return;
+                }
+            }
+        });
+        Ok(Outbox {
+            queue,
+            stream: stream.try_clone()?,
+        })
+    }
+
+    /// Queue text to be written. If too much is waiting already, the client
+    /// is hung up on, which also ends the thread reading from it.
+    ///
+    /// Returns false once the client is gone.
+    pub(crate) fn send(&self, text: String) -> bool {
+        if self.queue.try_send(text).is_ok() {
+            return true;
+        }
+        let _ = self.stream.shutdown(Shutdown::Both);
+        false
+    }
+}
+
+/// Collect what a game reports into `events`.
+fn listen_to(game: &mut StdMinrsGame, events: &Arc<Mutex<Vec<GameEvent>>>) {
+    let events = events.clone();
+    game.subscribe(Box::new(move |event| events.lock().unwrap().push(*event)));
+}
+
+/// What every connection's thread shares.
+struct Shared {
+    game: StdMinrsGame,
+    /// What the game reported during the current move.
+    events: Arc<Mutex<Vec<GameEvent>>>,
+    owners: HashMap<Position, u32>,
+    clients: HashMap<u32, Outbox>,
+    next_player: u32,
+}
+
+impl Shared {
+    fn new_game(&mut self, mut game: StdMinrsGame) {
+        self.events.lock().unwrap().clear();
+        listen_to(&mut game, &self.events);
+        self.game = game;
+        self.owners.clear();
+    }
+
+    /// Everything a client needs to catch up with the game.
+    fn snapshot(&self) -> Vec<ServerMessage> {
+        let mut messages = vec![ServerMessage::Board {
+            width: self.game.get_width(),
+            height: self.game.get_height(),
+            mines: self.game.get_mine_count(),
+        }];
+        for pos in self.game.get_positions() {
+            let state = self.game.get_tile_state(&pos).unwrap();
+            if state != TileState::Covered(None) {
+                messages.push(ServerMessage::Tile {
+                    pos,
+                    state,
+                    owner: self.owners.get(&pos).copied(),
+                });
+            }
+        }
+        messages.push(ServerMessage::State(GameStatus::of(&self.game)));
+        messages
+    }
+
+    /// Turn what the last move did into messages, noting who placed flags.
+    fn drain_events(&mut self, player: u32) -> Vec<ServerMessage> {
+        let events: Vec<GameEvent> = self.events.lock().unwrap().drain(..).collect();
+        let mut messages = Vec::new();
+        for event in events {
+            let (pos, state) = match event {
+                GameEvent::TileUncovered(pos, contents) => (pos, TileState::Uncovered(contents)),
+                GameEvent::TileCovered(pos) => (pos, TileState::Covered(None)),
+                GameEvent::FlagChanged(pos, flag) => (pos, TileState::Covered(flag)),
+                GameEvent::GameWon | GameEvent::GameLost(_) => {
+                    messages.push(ServerMessage::State(GameStatus::of(&self.game)));
+                    continue;
+                }
+                GameEvent::CascadeStarted(_) | GameEvent::CascadeFinished(_) => continue,
+            };
+            if let TileState::Covered(Some(_)) = state {
+                self.owners.insert(pos, player);
+            } else {
+                self.owners.remove(&pos);
+            }
+            messages.push(ServerMessage::Tile {
+                pos,
+                state,
+                owner: self.owners.get(&pos).copied(),
+            });
+        }
+        messages
+    }
+
+    /// Send messages to every client, dropping any that went away.
+    fn broadcast(&mut self, messages: &[ServerMessage]) {
+        let text: String = messages
+            .iter()
+            .map(|message| format!("{message}\n"))
+            .collect();
+        self.clients.retain(|_, outbox| outbox.send(text.clone()));
+    }
+}
+
+/// Hosts one shared game for any number of clients.
+pub struct CoopServer {
+    listener: TcpListener,
+    difficulty: GameDifficulty,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl CoopServer {
+    /// Listen for players, starting a random board of the given size.
+    pub fn bind(addr: impl ToSocketAddrs, difficulty: GameDifficulty) -> io::Result<CoopServer> {
+        let game = difficulty.new_game().unwrap();
+        CoopServer::with_game(addr, difficulty, game)
+    }
+
+    /// Listen for players of a game that was already set up. Later boards
+    /// are random ones of the same difficulty.
+    pub fn with_game(
+        addr: impl ToSocketAddrs,
+        difficulty: GameDifficulty,
+        mut game: StdMinrsGame,
+    ) -> io::Result<CoopServer> {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        listen_to(&mut game, &events);
+        let shared = Shared {
+            game,
+            events,
+            owners: HashMap::new(),
+            clients: HashMap::new(),
+            next_player: 0,
+        };
+        Ok(CoopServer {
+            listener: TcpListener::bind(addr)?,
+            difficulty,
+            shared: Arc::new(Mutex::new(shared)),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accept players until the listener fails, with a thread for each.
+    pub fn run(self) -> io::Result<()> {
+        for stream in self.listener.incoming() {
+            let stream = stream?;
+            let shared = self.shared.clone();
+            let difficulty = self.difficulty;
+            thread::spawn(move || {
+                // a player leaving is no trouble to anyone else
+                let _ = serve_client(stream, &shared, difficulty);
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Catch a new player up, then play their moves until they leave.
+fn serve_client(
+    stream: TcpStream,
+    shared: &Mutex<Shared>,
+    difficulty: GameDifficulty,
+) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let player = {
+        let mut shared = shared.lock().unwrap();
+        let player = shared.next_player;
+        shared.next_player += 1;
+        let mut messages = vec![ServerMessage::Player(player)];
+        messages.extend(shared.snapshot());
+        let text = messages
+            .iter()
+            .map(|message| format!("{message}\n"))
+            .collect();
+        let outbox = Outbox::new(&stream)?;
+        outbox.send(text);
+        shared.clients.insert(player, outbox);
+        player
+    };
+
+    let played = play_moves(&mut reader, shared, difficulty, player);
+    shared.lock().unwrap().clients.remove(&player);
+    let _ = stream.shutdown(Shutdown::Both);
+    played
+}
+
+/// Play a player's moves as they come in.
+fn play_moves(
+    reader: &mut impl BufRead,
+    shared: &Mutex<Shared>,
+    difficulty: GameDifficulty,
+    player: u32,
+) -> io::Result<()> {
+    let mut line = String::new();
+    while read_line(reader, &mut line)? {
+        let mut shared = shared.lock().unwrap();
+        let result = match line.parse() {
+            Ok(ClientMessage::Open(pos)) => shared.game.uncover_tile(&pos),
+            Ok(ClientMessage::Flag(pos)) => shared.game.cycle_flag(&pos),
+            Ok(ClientMessage::Chord(pos)) => shared.game.uncover_neighbors(&pos),
+            // the board is everyone's, so it is only replaced once it's done
+            Ok(ClientMessage::NewGame) if GameStatus::of(&shared.game) == GameStatus::Playing => {
+                Err(MinrsError::InvalidArgument)
+            }
+            Ok(ClientMessage::NewGame) => difficulty.new_game().map(|game| {
+                shared.new_game(game);
+                let messages = shared.snapshot();
+                shared.broadcast(&messages);
+            }),
+            Err(e) => Err(e),
+        };
+        let messages = shared.drain_events(player);
+        shared.broadcast(&messages);
+        if let (Err(e), Some(outbox)) = (result, shared.clients.get(&player)) {
+            outbox.send(format!("{}\n", ServerMessage::Error(e.code().to_string())));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{FlagState, TileContents};
+    use std::sync::mpsc::Receiver;
+    use std::time::Duration;
+
+    fn join(addr: SocketAddr) -> (CoopClient, Receiver<ServerMessage>) {
+        let client = CoopClient::connect(addr).unwrap();
+        let (sender, inbox) = mpsc::channel();
+        client
+            .listen(move |message| {
+                let _ = sender.send(message);
+            })
+            .unwrap();
+        (client, inbox)
+    }
+
+    /// Apply messages until one matches, failing if none comes.
+    fn wait_for<F>(board: &mut CoopBoard, inbox: &Receiver<ServerMessage>, done: F)
+    where
+        F: Fn(&ServerMessage) -> bool,
+    {
+        loop {
+            let message = inbox.recv_timeout(Duration::from_secs(5)).unwrap();
+            board.apply(&message);
+            if done(&message) {
+                return;
+            }
+        }
+    }
+
+    #[test]
+    fn test_message_round_trip() {
+        let messages = [
+            ServerMessage::Player(3),
+            ServerMessage::Board {
+                width: 30,
+                height: 16,
+                mines: 99,
+            },
+            ServerMessage::Tile {
+                pos: Position::new(4, 5),
+                state: TileState::Covered(Some(FlagState::RedFlag(1))),
+                owner: Some(2),
+            },
+            ServerMessage::Tile {
+                pos: Position::new(0, 1),
+                state: TileState::Uncovered(TileContents::MineCount(3)),
+                owner: None,
+            },
+            ServerMessage::State(GameStatus::Lost),
+            ServerMessage::Error("game_over".to_string()),
+        ];
+        for message in messages {
+            assert_eq!(
+                message.to_string().parse::<ServerMessage>().unwrap(),
+                message
+            );
+        }
+        let moves = [
+            ClientMessage::Open(Position::new(1, 2)),
+            ClientMessage::Flag(Position::new(3, 4)),
+            ClientMessage::Chord(Position::new(5, 6)),
+            ClientMessage::NewGame,
+        ];
+        for message in moves {
+            assert_eq!(
+                message.to_string().parse::<ClientMessage>().unwrap(),
+                message
+            );
+        }
+        assert!("tile 1 2 %".parse::<ServerMessage>().is_err());
+        assert!("undo".parse::<ClientMessage>().is_err());
+    }
+
+    /// Two players on loopback see each other's moves, with flags marked by
+    /// who placed them.
+    #[test]
+    fn test_coop_over_loopback() {
+        let mine = Position::new(0, 0);
+        let game = StdMinrsGame::from_mines(8, 8, &[mine, Position::new(7, 7)]).unwrap();
+        let server = CoopServer::with_game("127.0.0.1:0", GameDifficulty::Easy, game).unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+
+        let (mut alice, alice_inbox) = join(addr);
+        let mut alice_board = CoopBoard::new();
+        wait_for(&mut alice_board, &alice_inbox, |message| {
+            matches!(message, ServerMessage::State(_))
+        });
+        assert_eq!(alice_board.get_player(), Some(0));
+        assert_eq!(alice_board.get_width(), 8);
+
+        alice
+            .send(ClientMessage::Open(Position::new(4, 4)))
+            .unwrap();
+        wait_for(
+            &mut alice_board,
+            &alice_inbox,
+            |message| matches!(message, ServerMessage::Tile { pos, .. } if *pos == Position::new(4, 4)),
+        );
+
+        // a late joiner is caught up on the opening, then sees alice's flag
+        let (mut bob, bob_inbox) = join(addr);
+        let mut bob_board = CoopBoard::new();
+        wait_for(&mut bob_board, &bob_inbox, |message| {
+            matches!(message, ServerMessage::State(_))
+        });
+        assert_eq!(bob_board.get_player(), Some(1));
+        assert_eq!(
+            bob_board.get_tile_state(&Position::new(4, 4)),
+            TileState::Uncovered(TileContents::MineCount(0))
+        );
+        alice.send(ClientMessage::Flag(mine)).unwrap();
+        let flagged = |message: &ServerMessage| matches!(message, ServerMessage::Tile { pos, .. } if *pos == mine);
+        wait_for(&mut bob_board, &bob_inbox, flagged);
+        assert_eq!(
+            bob_board.get_tile_state(&mine),
+            TileState::Covered(Some(FlagState::RedFlag(1)))
+        );
+        assert_eq!(bob_board.get_owner(&mine), Some(0));
+
+        // a failed move only comes back to the player who made it
+        bob.send(ClientMessage::Open(Position::new(4, 4))).unwrap();
+        wait_for(&mut bob_board, &bob_inbox, |message| {
+            *message == ServerMessage::Error("invalid_argument".to_string())
+        });
+
+        // the mine in the far corner ends it for everyone
+        bob.send(ClientMessage::Open(Position::new(7, 7))).unwrap();
+        for (board, inbox) in [
+            (&mut alice_board, &alice_inbox),
+            (&mut bob_board, &bob_inbox),
+        ] {
+            wait_for(board, inbox, |message| {
+                *message == ServerMessage::State(GameStatus::Lost)
+            });
+        }
+
+        // and either player can start the next board
+        alice.send(ClientMessage::NewGame).unwrap();
+        wait_for(&mut bob_board, &bob_inbox, |message| {
+            *message == ServerMessage::State(GameStatus::Playing)
+        });
+        assert_eq!(bob_board.get_tile_state(&mine), TileState::Covered(None));
+        assert_eq!(bob_board.get_owner(&mine), None);
+    }
+
+    /// Nobody can throw the board away while it's being played.
+    #[test]
+    fn test_new_game_once_over() {
+        // the corner is walled in by mines, so the opening can't win
+        let mines = [
+            Position::new(1, 0),
+            Position::new(0, 1),
+            Position::new(1, 1),
+        ];
+        let game = StdMinrsGame::from_mines(8, 8, &mines).unwrap();
+        let server = CoopServer::with_game("127.0.0.1:0", GameDifficulty::Easy, game).unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+
+        let (mut alice, alice_inbox) = join(addr);
+        let mut board = CoopBoard::new();
+        let opened = Position::new(4, 4);
+        alice.send(ClientMessage::Open(opened)).unwrap();
+        wait_for(
+            &mut board,
+            &alice_inbox,
+            |message| matches!(message, ServerMessage::Tile { pos, .. } if *pos == opened),
+        );
+        alice.send(ClientMessage::NewGame).unwrap();
+        wait_for(&mut board, &alice_inbox, |message| {
+            *message == ServerMessage::Error("invalid_argument".to_string())
+        });
+        assert_eq!(
+            board.get_tile_state(&opened),
+            TileState::Uncovered(TileContents::MineCount(0))
+        );
+
+        alice.send(ClientMessage::Open(mines[2])).unwrap();
+        wait_for(&mut board, &alice_inbox, |message| {
+            *message == ServerMessage::State(GameStatus::Lost)
+        });
+        alice.send(ClientMessage::NewGame).unwrap();
+        wait_for(&mut board, &alice_inbox, |message| {
+            *message == ServerMessage::State(GameStatus::Playing)
+        });
+        assert_eq!(board.get_tile_state(&opened), TileState::Covered(None));
+    }
+
+    #[test]
+    fn test_read_line() {
+        let long = "x".repeat(MAX_LINE + 1);
+        let text = format!("open 1 2\r\n{}\nflag 3 4", "x".repeat(MAX_LINE));
+        let mut reader = text.as_bytes();
+        let mut line = String::new();
+        assert!(read_line(&mut reader, &mut line).unwrap());
+        assert_eq!(line, "open 1 2");
+        assert!(read_line(&mut reader, &mut line).unwrap());
+        assert_eq!(line.len(), MAX_LINE);
+        assert!(read_line(&mut reader, &mut line).unwrap());
+        assert_eq!(line, "flag 3 4");
+        assert!(!read_line(&mut reader, &mut line).unwrap());
+        assert!(read_line(&mut long.as_bytes(), &mut line).is_err());
+    }
+
+    /// A client that never reads is hung up on, rather than holding up
+    /// whoever is writing to it.
+    #[test]
+    fn test_stalled_client_dropped() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let _stalled = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let outbox = Outbox::new(&stream).unwrap();
+        let chunk = "x".repeat(1 << 16);
+        let sent = (0..4096).take_while(|_| outbox.send(chunk.clone())).count();
+        assert!(sent < 4096);
+    }
+
+    /// A client sending an endless line is dropped, and the others play on.
+    #[test]
+    fn test_long_line_dropped() {
+        let game = StdMinrsGame::from_mines(8, 8, &[Position::new(0, 0)]).unwrap();
+        let server = CoopServer::with_game("127.0.0.1:0", GameDifficulty::Easy, game).unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+
+        let mut spammer = TcpStream::connect(addr).unwrap();
+        spammer
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        spammer
+            .write_all("x".repeat(MAX_LINE * 4).as_bytes())
+            .unwrap();
+        // the server hangs up, rather than the read timing out
+        let mut sent = String::new();
+        if let Err(e) = spammer.read_to_string(&mut sent) {
+            assert_eq!(e.kind(), io::ErrorKind::ConnectionReset);
+        }
+
+        let (mut alice, alice_inbox) = join(addr);
+        let mut alice_board = CoopBoard::new();
+        alice
+            .send(ClientMessage::Open(Position::new(4, 4)))
+            .unwrap();
+        wait_for(
+            &mut alice_board,
+            &alice_inbox,
+            |message| matches!(message, ServerMessage::Tile { pos, .. } if *pos == Position::new(4, 4)),
+        );
+    }
+}
@@ -0,0 +1,63 @@
+// Copyright 2022 nitepone <luna@night.horse>
+//
+// The board sizes every frontend offers.
+
+use crate::error::MinrsResult;
+use crate::game::StdMinrsGame;
+
+/// The usual board presets.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum GameDifficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+impl GameDifficulty {
+    pub fn get_width(&self) -> u16 {
+        match self {
+            GameDifficulty::Easy => 8,
+            GameDifficulty::Medium => 15,
+            GameDifficulty::Hard => 30,
+        }
+    }
+
+    pub fn get_height(&self) -> u16 {
+        match self {
+            GameDifficulty::Easy => 8,
+            GameDifficulty::Medium => 15,
+            GameDifficulty::Hard => 30,
+        }
+    }
+
+    pub fn get_mines(&self) -> u32 {
+        match self {
+            GameDifficulty::Easy => 10,
+            GameDifficulty::Medium => 40,
+            GameDifficulty::Hard => 99,
+        }
+    }
+
+    /// Start a random flat board of this size.
+    pub fn new_game(&self) -> MinrsResult<StdMinrsGame> {
+        StdMinrsGame::new(self.get_width(), self.get_height(), self.get_mines())
+    }
+
+    /// Get the name used for this preset in saves and on the command line.
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            GameDifficulty::Easy => "easy",
+            GameDifficulty::Medium => "medium",
+            GameDifficulty::Hard => "hard",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<GameDifficulty> {
+        match name {
+            "easy" => Some(GameDifficulty::Easy),
+            "medium" => Some(GameDifficulty::Medium),
+            "hard" => Some(GameDifficulty::Hard),
+            _ => None,
+        }
+    }
+}
@@ -14,4 +14,23 @@ pub enum MinrsError {
     GameOver,
     /// Invalid argument. (likely bad controller code?)
     InvalidArgument,
+    /// There is no move left to undo or redo.
+    NoHistory,
+    /// Saved game data (like a replay) could not be understood.
+    InvalidData,
+}
+
+impl MinrsError {
+    /// Get a short name for the error, for text protocols.
+    pub fn code(&self) -> &'static str {
+        match self {
+            MinrsError::BlockedByFlag => "blocked_by_flag",
+            MinrsError::InvalidPosition => "invalid_position",
+            MinrsError::OobPosition => "oob_position",
+            MinrsError::GameOver => "game_over",
+            MinrsError::InvalidArgument => "invalid_argument",
+            MinrsError::NoHistory => "no_history",
+            MinrsError::InvalidData => "invalid_data",
+        }
+    }
 }
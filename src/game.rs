@@ -1,27 +1,188 @@
 // Copyright 2022 nitepone <luna@night.horse>
 
+use crate::clock::{Clock, SystemClock};
 use crate::error::{MinrsError, MinrsResult};
-use rand::Rng;
-use std::collections::HashSet;
+use crate::solver;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-const MIN_BOARD_DIMENSION: u8 = 8;
+const MIN_BOARD_DIMENSION: u16 = 8;
+/// How many layouts no-guess generation may try before settling for a
+/// regular opening.
+const NO_GUESS_ATTEMPTS: u32 = 1000;
+/// How many layouts the first uncover may try to land on an empty tile,
+/// before keeping mines away from it on purpose.
+const FIRST_MOVE_ATTEMPTS: u32 = 1000;
+const SAVE_MAGIC: &str = "min-rs-weeper game";
+const SAVE_VERSION: u32 = 1;
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+/// Parse one word of saved game data.
+pub(crate) fn parse_word<T: FromStr>(word: Option<&str>) -> MinrsResult<T> {
+    word.ok_or(MinrsError::InvalidData)?
+        .parse()
+        .map_err(|_| MinrsError::InvalidData)
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub struct Position {
-    pub x: u8,
-    pub y: u8,
+    pub x: u16,
+    pub y: u16,
+    /// The layer of a 3D board, always 0 on a flat one.
+    pub z: u16,
+}
+
+impl Position {
+    /// Get a position on the first (or only) layer.
+    pub fn new(x: u16, y: u16) -> Position {
+        Position { x, y, z: 0 }
+    }
+
+    pub fn new_3d(x: u16, y: u16, z: u16) -> Position {
+        Position { x, y, z }
+    }
+}
+
+/// Which tiles count as touching each other.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub enum Neighborhood {
+    /// The 8 tiles around a square tile.
+    #[default]
+    Square,
+    /// The 6 tiles around a hex tile, laid out in rows where every odd row
+    /// sits half a tile further right.
+    Hex,
+    /// Only the 4 tiles sharing an edge.
+    Cross,
+    /// The 8 tiles a chess knight could move to.
+    Knight,
+    /// Every tile at most this many steps away, diagonals included.
+    Radius(u8),
+    /// Any list of (x, y) offsets.
+    Custom(Vec<(i8, i8)>),
+}
+
+impl Neighborhood {
+    /// Get the (x, y) offsets from `pos` to each of its neighbors.
+    fn offsets(&self, pos: &Position) -> Vec<(i32, i32)> {
+        match self {
+            Neighborhood::Square => (-1..=1)
+                .flat_map(|x| (-1..=1).map(move |y| (x, y)))
+                .filter(|&offset| offset != (0, 0))
+                .collect(),
+            Neighborhood::Hex => {
+                // the rows above and below lean towards the side this row
+                // is shifted to
+                let lean = (pos.y % 2) as i32;
+                vec![
+                    (-1, 0),
+                    (1, 0),
+                    (lean - 1, -1),
+                    (lean, -1),
+                    (lean - 1, 1),
+                    (lean, 1),
+                ]
+            }
+            Neighborhood::Cross => vec![(-1, 0), (1, 0), (0, -1), (0, 1)],
+            Neighborhood::Knight => vec![
+                (-2, -1),
+                (-2, 1),
+                (-1, -2),
+                (-1, 2),
+                (1, -2),
+                (1, 2),
+                (2, -1),
+                (2, 1),
+            ],
+            Neighborhood::Radius(radius) => {
+                let radius = *radius as i32;
+                (-radius..=radius)
+                    .flat_map(|x| (-radius..=radius).map(move |y| (x, y)))
+                    .filter(|&offset| offset != (0, 0))
+                    .collect()
+            }
+            Neighborhood::Custom(offsets) => {
+                offsets.iter().map(|&(x, y)| (x as i32, y as i32)).collect()
+            }
+        }
+    }
+
+    /// Get the most neighbors a tile can have, on a board of `depth` layers.
+    fn max_neighbors(&self, depth: u16) -> usize {
+        let flat = self.offsets(&Position::new(0, 0)).len();
+        if depth > 1 {
+            // the layers above and below also hold the tile itself
+            flat + 2 * (flat + 1)
+        } else {
+            flat
+        }
+    }
+}
+
+impl fmt::Display for Neighborhood {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Neighborhood::Square => write!(f, "square"),
+            Neighborhood::Hex => write!(f, "hex"),
+            Neighborhood::Cross => write!(f, "cross"),
+            Neighborhood::Knight => write!(f, "knight"),
+            Neighborhood::Radius(radius) => write!(f, "radius-{radius}"),
+            Neighborhood::Custom(offsets) => {
+                let offsets: Vec<String> =
+                    offsets.iter().map(|(x, y)| format!("{x},{y}")).collect();
+                write!(f, "custom:{}", offsets.join(";"))
+            }
+        }
+    }
+}
+
+impl FromStr for Neighborhood {
+    type Err = MinrsError;
+
+    /// Read a neighborhood written by its Display impl, such as `cross`,
+    /// `radius-2` or `custom:1,0;-1,2`.
+    fn from_str(name: &str) -> MinrsResult<Neighborhood> {
+        if let Some(radius) = name.strip_prefix("radius-") {
+            return Ok(Neighborhood::Radius(parse_word(Some(radius))?));
+        }
+        if let Some(offsets) = name.strip_prefix("custom:") {
+            return offsets
+                .split(';')
+                .filter(|offset| !offset.is_empty())
+                .map(|offset| {
+                    let mut coords = offset.split(',');
+                    Ok((parse_word(coords.next())?, parse_word(coords.next())?))
+                })
+                .collect::<MinrsResult<Vec<(i8, i8)>>>()
+                .map(Neighborhood::Custom);
+        }
+        match name {
+            "square" => Ok(Neighborhood::Square),
+            "hex" => Ok(Neighborhood::Hex),
+            "cross" => Ok(Neighborhood::Cross),
+            "knight" => Ok(Neighborhood::Knight),
+            _ => Err(MinrsError::InvalidData),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum FlagState {
     Questionable,
-    RedFlag,
+    /// The player thinks the tile holds this many mines, 1 unless tiles can
+    /// hold more than one.
+    RedFlag(u8),
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum TileContents {
     MineCount(u8),
-    Mine,
+    /// How many mines the tile holds.
+    Mines(u8),
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -30,33 +191,245 @@ pub enum TileState {
     Uncovered(TileContents),
 }
 
+impl TileState {
+    /// Get a character showing what the player can see of a tile, for text
+    /// frontends.
+    ///
+    /// Covered tiles are `#`, or `!` and `?` when flagged. Uncovered tiles
+    /// are `*` for mines, `.` for no mines around, and otherwise their count
+    /// as a base 36 digit, since large neighborhoods count past 9.
+    pub fn to_char(&self) -> char {
+        match self {
+            TileState::Covered(None) => '#',
+            TileState::Covered(Some(FlagState::RedFlag(_))) => '!',
+            TileState::Covered(Some(FlagState::Questionable)) => '?',
+            TileState::Uncovered(TileContents::Mines(_)) => '*',
+            TileState::Uncovered(TileContents::MineCount(0)) => '.',
+            TileState::Uncovered(TileContents::MineCount(count)) => {
+                char::from_digit(*count as u32, 36).unwrap_or('+')
+            }
+        }
+    }
+
+    /// Read a character written by `to_char`.
+    ///
+    /// Flags and mines read back as single ones, and `+` isn't read at all.
+    pub fn from_char(glyph: char) -> Option<TileState> {
+        Some(match glyph {
+            '#' => TileState::Covered(None),
+            '!' => TileState::Covered(Some(FlagState::RedFlag(1))),
+            '?' => TileState::Covered(Some(FlagState::Questionable)),
+            '*' => TileState::Uncovered(TileContents::Mines(1)),
+            '.' => TileState::Uncovered(TileContents::MineCount(0)),
+            _ => TileState::Uncovered(TileContents::MineCount(glyph.to_digit(36)? as u8)),
+        })
+    }
+}
+
+/// Whether a game is still being played.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum GameStatus {
+    Playing,
+    Won,
+    Lost,
+}
+
+impl GameStatus {
+    pub fn of(game: &dyn MinrsGame) -> GameStatus {
+        if game.game_over() {
+            GameStatus::Lost
+        } else if game.victory() {
+            GameStatus::Won
+        } else {
+            GameStatus::Playing
+        }
+    }
+}
+
+impl fmt::Display for GameStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameStatus::Playing => write!(f, "playing"),
+            GameStatus::Won => write!(f, "won"),
+            GameStatus::Lost => write!(f, "lost"),
+        }
+    }
+}
+
+impl FromStr for GameStatus {
+    type Err = MinrsError;
+
+    fn from_str(name: &str) -> MinrsResult<GameStatus> {
+        match name {
+            "playing" => Ok(GameStatus::Playing),
+            "won" => Ok(GameStatus::Won),
+            "lost" => Ok(GameStatus::Lost),
+            _ => Err(MinrsError::InvalidData),
+        }
+    }
+}
+
+/// Something that happened in a game.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum GameEvent {
+    TileUncovered(Position, TileContents),
+    /// A tile was covered again by an undo.
+    TileCovered(Position),
+    FlagChanged(Position, Option<FlagState>),
+    /// An empty tile is about to be uncovered, opening the tiles around it.
+    CascadeStarted(Position),
+    /// Every tile opened by the cascade from this tile is uncovered.
+    CascadeFinished(Position),
+    GameWon,
+    /// A mine went off at this tile.
+    GameLost(Position),
+}
+
+/// Gets called with every event of a game it subscribed to.
+pub type Listener = Box<dyn FnMut(&GameEvent) + Send>;
+
+/// A copy of a game starts without listeners, so scratch copies don't report
+/// moves that never happened.
+#[derive(Default)]
+pub(crate) struct Listeners(Vec<Listener>);
+
+impl Listeners {
+    pub(crate) fn push(&mut self, listener: Listener) {
+        self.0.push(listener);
+    }
+
+    pub(crate) fn emit(&mut self, event: GameEvent) {
+        for listener in self.0.iter_mut() {
+            listener(&event);
+        }
+    }
+}
+
+impl Clone for Listeners {
+    fn clone(&self) -> Listeners {
+        Listeners::default()
+    }
+}
+
+/// How many moves of each kind were made, counting only moves that changed
+/// the board.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct Clicks {
+    pub uncovers: u32,
+    pub chords: u32,
+    pub flags: u32,
+}
+
+impl Clicks {
+    pub fn total(&self) -> u32 {
+        self.uncovers + self.chords + self.flags
+    }
+}
+
+/// The parts of a tile a move can change.
+#[derive(Clone, Copy)]
+struct TileSnapshot {
+    covered: bool,
+    flag: Option<FlagState>,
+}
+
+#[derive(Clone)]
+struct TileChange {
+    pos: Position,
+    before: TileSnapshot,
+    after: TileSnapshot,
+}
+
+/// A single reversible move.
+#[derive(Clone)]
+struct Step {
+    tiles: Vec<TileChange>,
+    /// `started` before and after the move.
+    started: (bool, bool),
+    /// `game_over` before and after the move.
+    game_over: (bool, bool),
+}
+
+#[derive(Clone)]
 struct StdTile {
     covered: bool,
-    mine: bool,
+    mines: u8,
     flag: Option<FlagState>,
 }
 
 impl StdTile {
-    fn new(mine: bool) -> StdTile {
+    fn new(mines: u8) -> StdTile {
         StdTile {
             covered: true,
-            mine,
+            mines,
             flag: None,
         }
     }
 
-    fn set_mine(&mut self, mine: bool) {
-        self.mine = mine;
+    fn set_mines(&mut self, mines: u8) {
+        self.mines = mines;
+    }
+
+    /// Read a tile written by `glyph`.
+    fn from_glyph(glyph: char) -> Option<StdTile> {
+        let (covered, flag, mine) = match glyph {
+            '.' => (true, None, false),
+            '*' => (true, None, true),
+            'f' => (true, Some(FlagState::RedFlag(1)), false),
+            'F' => (true, Some(FlagState::RedFlag(1)), true),
+            'q' => (true, Some(FlagState::Questionable), false),
+            'Q' => (true, Some(FlagState::Questionable), true),
+            '_' => (false, None, false),
+            'X' => (false, None, true),
+            _ => return None,
+        };
+        Some(StdTile {
+            covered,
+            mines: mine as u8,
+            flag,
+        })
+    }
+
+    /// Get the single character used for this tile in saves and ASCII
+    /// layouts.
+    ///
+    /// This only tells if there are any mines or flags, not how many.
+    fn glyph(&self) -> char {
+        match (self.covered, self.flag, self.is_mine()) {
+            (true, None, false) => '.',
+            (true, None, true) => '*',
+            (true, Some(FlagState::RedFlag(_)), false) => 'f',
+            (true, Some(FlagState::RedFlag(_)), true) => 'F',
+            (true, Some(FlagState::Questionable), false) => 'q',
+            (true, Some(FlagState::Questionable), true) => 'Q',
+            (false, _, false) => '_',
+            (false, _, true) => 'X',
+        }
+    }
+
+    /// Get how many flags the player has placed here.
+    ///
+    /// A question mark still counts as one, so chording treats it like a
+    /// flag.
+    fn get_flag_count(&self) -> u8 {
+        match self.get_flag() {
+            Some(FlagState::RedFlag(count)) => count,
+            Some(FlagState::Questionable) => 1,
+            None => 0,
+        }
     }
 }
 
 trait Tile {
     fn is_covered(&self) -> bool;
     fn is_mine(&self) -> bool;
+    fn get_mines(&self) -> u8;
     fn get_flag(&self) -> Option<FlagState>;
     fn get_contents(&self, neighbors: Vec<&dyn Tile>) -> TileContents;
     fn get_state(&self, neighbors: Vec<&dyn Tile>) -> TileState;
-    fn toggle_flag(&mut self);
+    /// Cycle through 1 to `max_flags` flags, a question mark, and back to
+    /// no flag.
+    fn toggle_flag(&mut self, max_flags: u8);
     fn uncover(&mut self) -> MinrsResult<bool>;
 }
 
@@ -66,7 +439,11 @@ impl Tile for StdTile {
     }
 
     fn is_mine(&self) -> bool {
-        self.mine
+        self.mines > 0
+    }
+
+    fn get_mines(&self) -> u8 {
+        self.mines
     }
 
     fn get_flag(&self) -> Option<FlagState> {
@@ -78,41 +455,39 @@ impl Tile for StdTile {
 
     fn get_contents(&self, neighbors: Vec<&dyn Tile>) -> TileContents {
         // if we are mine, say so!
-        if self.mine {
-            return TileContents::Mine;
+        if self.is_mine() {
+            return TileContents::Mines(self.mines);
         }
 
         // finally, we are an empty number tile.
-        let count = neighbors.iter().fold(0, |count, n| {
-            if n.is_mine() {
-                return count + 1;
-            }
-            count
-        });
+        let count = neighbors.iter().fold(0, |count, n| count + n.get_mines());
         TileContents::MineCount(count)
     }
 
     fn get_state(&self, neighbors: Vec<&dyn Tile>) -> TileState {
         if self.covered {
-            return TileState::Covered(self.get_flag());
+            TileState::Covered(self.get_flag())
         } else {
-            return TileState::Uncovered(self.get_contents(neighbors));
+            TileState::Uncovered(self.get_contents(neighbors))
         }
     }
 
-    fn toggle_flag(&mut self) {
+    fn toggle_flag(&mut self, max_flags: u8) {
         if let Some(flag_state) = self.flag {
             match flag_state {
-                FlagState::RedFlag => self.flag = Some(FlagState::Questionable),
+                FlagState::RedFlag(count) if count < max_flags => {
+                    self.flag = Some(FlagState::RedFlag(count + 1))
+                }
+                FlagState::RedFlag(_) => self.flag = Some(FlagState::Questionable),
                 FlagState::Questionable => self.flag = None,
             }
         } else {
-            self.flag = Some(FlagState::RedFlag);
+            self.flag = Some(FlagState::RedFlag(1));
         }
     }
 
     fn uncover(&mut self) -> MinrsResult<bool> {
-        if self.covered == false {
+        if !self.covered {
             return Err(MinrsError::InvalidPosition);
         }
 
@@ -132,301 +507,1233 @@ pub trait MinrsGame {
     ///
     /// throws InvalidPosition on uncovered tiles.
     /// throws BlockedByFlag on red_flagged tiles.
+    /// throws GameOver once a mine went off.
     fn uncover_tile(&mut self, position: &Position) -> MinrsResult<()>;
     /// Uncovers all neighbors from an uncovered tile.
     ///
     /// throws InvalidPosition on covered tiles.
+    /// throws GameOver once a mine went off.
     /// throws BlockedByFlag iff there is not an equal flags to mine ratio for
     ///        the mines counted by the target tile. (Else, this move is self
     ///        destructive)
     fn uncover_neighbors(&mut self, position: &Position) -> MinrsResult<()>;
     /// Get the TileState of a tile at a position.
     fn get_tile_state(&self, position: &Position) -> MinrsResult<TileState>;
+    /// Get the positions of all tiles adjacent to a position.
+    fn get_neighbors_pos(&self, position: &Position) -> MinrsResult<Vec<Position>>;
     /// Get the width of the current game.
-    fn get_width(&self) -> u8;
+    fn get_width(&self) -> u16;
     /// Get the height of the current game.
-    fn get_height(&self) -> u8;
+    fn get_height(&self) -> u16;
+    /// Get the number of layers of the current game, 1 unless it is 3D.
+    fn get_depth(&self) -> u16;
+    /// Get the total number of mines on the board.
+    fn get_mine_count(&self) -> u32;
+    /// Get the most mines a single tile can hold.
+    fn get_mines_per_tile(&self) -> u8;
     /// Check if the game is won.
     fn victory(&self) -> bool;
+    /// Reverts the last move, including one that ended the game.
+    ///
+    /// throws NoHistory if there is no move to undo.
+    fn undo(&mut self) -> MinrsResult<()>;
+    /// Replays the last undone move.
+    ///
+    /// throws NoHistory if there is no undone move, or a new move was made
+    ///        since the last undo.
+    fn redo(&mut self) -> MinrsResult<()>;
+    /// Call `listener` with every event from now on, including those of
+    /// undo and redo.
+    fn subscribe(&mut self, listener: Listener);
+    /// Get every position on the board, layer by layer.
+    fn get_positions(&self) -> Vec<Position> {
+        let mut positions = Vec::new();
+        for z in 0..self.get_depth() {
+            for x in 0..self.get_width() {
+                for y in 0..self.get_height() {
+                    positions.push(Position::new_3d(x, y, z));
+                }
+            }
+        }
+        positions
+    }
 }
 
+#[derive(Clone)]
 pub struct StdMinrsGame {
     started: bool,
+    no_guess: bool,
+    /// No-guess generation gave up, and this board may need a guess.
+    no_guess_failed: bool,
+    /// The mine layout was given explicitly, and must never be regenerated.
+    fixed: bool,
     game_over: bool,
-    board: Vec<Vec<StdTile>>,
-    width: u8,
-    height: u8,
-    mine_count: u16,
+    neighborhood: Neighborhood,
+    /// Opposite edges of the board touch.
+    wrap: bool,
+    mines_per_tile: u8,
+    /// Every tile, indexed as described by `get_index`.
+    board: Vec<StdTile>,
+    width: u16,
+    height: u16,
+    depth: u16,
+    mine_count: u32,
+    seed: u64,
+    rng: StdRng,
+    history: Vec<Step>,
+    future: Vec<Step>,
+    /// The move being made, while one is in progress.
+    pending: Option<Step>,
+    clock: Arc<dyn Clock + Send + Sync>,
+    /// Time played up to `running_since`.
+    elapsed: Duration,
+    /// When the timer was last started, while it is running.
+    running_since: Option<Instant>,
+    /// Moves made so far, including any since undone.
+    clicks: Clicks,
+    listeners: Listeners,
 }
 
 impl StdMinrsGame {
-    pub fn new(width: u8, height: u8, mine_count: u16) -> MinrsResult<StdMinrsGame> {
+    pub fn new(width: u16, height: u16, mine_count: u32) -> MinrsResult<StdMinrsGame> {
+        StdMinrsGame::new_seeded(width, height, mine_count, rand::thread_rng().gen())
+    }
+
+    /// Create a game whose mine layout is fully determined by `seed`.
+    ///
+    /// The same seed, size, mine count and sequence of moves will always
+    /// produce the same board, including the regeneration done on the first
+    /// uncover.
+    pub fn new_seeded(
+        width: u16,
+        height: u16,
+        mine_count: u32,
+        seed: u64,
+    ) -> MinrsResult<StdMinrsGame> {
+        StdMinrsGame::new_3d_seeded(width, height, 1, mine_count, seed)
+    }
+
+    /// Create a game of `depth` stacked layers, where each tile also touches
+    /// the tiles above and below it.
+    pub fn new_3d(
+        width: u16,
+        height: u16,
+        depth: u16,
+        mine_count: u32,
+    ) -> MinrsResult<StdMinrsGame> {
+        StdMinrsGame::new_3d_seeded(width, height, depth, mine_count, rand::thread_rng().gen())
+    }
+
+    /// Create a layered game whose mine layout is fully determined by `seed`.
+    pub fn new_3d_seeded(
+        width: u16,
+        height: u16,
+        depth: u16,
+        mine_count: u32,
+        seed: u64,
+    ) -> MinrsResult<StdMinrsGame> {
         let mut new_game = StdMinrsGame {
             started: false,
+            no_guess: false,
+            no_guess_failed: false,
+            fixed: false,
             game_over: false,
+            neighborhood: Neighborhood::Square,
+            wrap: false,
+            mines_per_tile: 1,
             board: Vec::new(),
             width,
             height,
+            depth,
             mine_count,
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            history: Vec::new(),
+            future: Vec::new(),
+            pending: None,
+            clock: Arc::new(SystemClock),
+            elapsed: Duration::ZERO,
+            running_since: None,
+            clicks: Clicks::default(),
+            listeners: Listeners::default(),
         };
 
-        if width < MIN_BOARD_DIMENSION || height < MIN_BOARD_DIMENSION {
+        if width < MIN_BOARD_DIMENSION || height < MIN_BOARD_DIMENSION || depth == 0 {
             return Err(MinrsError::InvalidArgument);
         }
 
-        let tile_count: u16 = width as u16 * height as u16;
-        if mine_count >= tile_count {
+        let tile_count = new_game.get_tile_count();
+        if mine_count as usize >= tile_count {
             return Err(MinrsError::InvalidArgument);
         }
 
         // create the board
-        for _col in 0..width {
-            let mut cur_col: Vec<StdTile> = Vec::new();
-            for _row in 0..height {
-                cur_col.push(StdTile::new(false));
-            }
-            new_game.board.push(cur_col);
-        }
+        new_game.board = vec![StdTile::new(0); tile_count];
 
-        new_game.generate_mines(mine_count)?;
+        new_game.generate_mines(mine_count, &[])?;
 
-        return Ok(new_game);
+        Ok(new_game)
     }
 
-    fn generate_mines(&mut self, mine_count: u16) -> MinrsResult<()> {
-        let mut rng_vec = HashSet::new();
-        let tile_count: u16 = self.get_width() as u16 * self.get_height() as u16;
-        let mut rng = rand::thread_rng();
-        // create a unique set of random numbers indexing the tiles as:
-        // col * width + row
-        // XXX I feel like there might be a nicer way to do this?
-        //     Perhaps we would prefer to modify existing tiles. The rand crate
-        //     would allow us to nicely select a subset of elements from our
-        //     board if it implements the Collections trait.
-        //     Further, it would be more efficient to be able to regenerate a
-        //     single mine tile if it is chosen first.
-        for _i in 0..mine_count {
-            while !rng_vec.insert(rng.gen_range(0..tile_count)) {}
-        }
+    /// Create a game with mines at exactly the given positions.
+    ///
+    /// Unlike a generated board, this layout is kept as is. So the first
+    /// uncover may hit a mine.
+    pub fn from_mines(width: u16, height: u16, mines: &[Position]) -> MinrsResult<StdMinrsGame> {
+        StdMinrsGame::from_mines_3d(width, height, 1, mines)
+    }
 
-        // create the board
-        let mut idx = 0;
-        for x in 0..self.get_width() {
-            for y in 0..self.get_height() {
-                let mine = rng_vec.contains(&idx);
-                let pos = Position { x, y };
-                self.mod_tile(&pos, |t| t.set_mine(mine))?;
-                idx += 1;
-            }
+    /// Create a layered game with mines at exactly the given positions.
+    pub fn from_mines_3d(
+        width: u16,
+        height: u16,
+        depth: u16,
+        mines: &[Position],
+    ) -> MinrsResult<StdMinrsGame> {
+        let mut game = StdMinrsGame::new_3d_seeded(width, height, depth, 0, 0)?;
+        let unique: HashSet<&Position> = mines.iter().collect();
+        if unique.len() != mines.len() || mines.len() >= game.get_tile_count() {
+            return Err(MinrsError::InvalidArgument);
         }
-
-        Ok(())
+        for pos in mines {
+            game.mod_tile(pos, |tile| tile.set_mines(1))?;
+        }
+        game.mine_count = mines.len() as u32;
+        game.fixed = true;
+        Ok(game)
     }
 
-    fn mod_tile<B, F>(&mut self, pos: &Position, mut f: F) -> MinrsResult<B>
-    where
-        F: FnMut(&mut StdTile) -> B,
-    {
-        Ok(f(self
-            .board
-            .get_mut(pos.x as usize)
-            .ok_or(MinrsError::OobPosition)?
-            .get_mut(pos.y as usize)
-            .ok_or(MinrsError::OobPosition)?))
+    /// Get the seed this game's mine layout was generated from.
+    ///
+    /// Games built from an explicit layout have no seed.
+    pub fn get_seed(&self) -> Option<u64> {
+        if self.fixed {
+            return None;
+        }
+        Some(self.seed)
     }
 
-    fn get_tile(&self, pos: &Position) -> MinrsResult<&dyn Tile> {
-        Ok(self
-            .board
-            .get(pos.x as usize)
-            .ok_or(MinrsError::OobPosition)?
-            .get(pos.y as usize)
-            .ok_or(MinrsError::OobPosition)?)
+    /// Check if this game only generates boards solvable without guessing.
+    pub fn is_no_guess(&self) -> bool {
+        self.no_guess
     }
 
-    fn get_neighbors_pos(&self, pos: &Position) -> MinrsResult<Vec<Position>> {
-        let mut neighbors: Vec<Position> = Vec::new();
-        let x_max = self.board.len() as i32;
-        for x_mod in -1..=1 {
-            let x = (pos.x as i32) + x_mod;
-            if x < 0 || x >= x_max {
-                continue;
-            }
-            let y_max = self
-                .board
-                .get(x as usize)
-                .ok_or(MinrsError::InvalidArgument)?
-                .len() as i32;
-            for y_mod in -1..=1 {
-                let y = (pos.y as i32) + y_mod;
-                if y < 0 || y >= y_max || (y_mod == 0 && x_mod == 0) {
-                    continue;
-                }
-                neighbors.push(Position {
-                    x: x as u8,
-                    y: y as u8,
-                });
-            }
-        }
-        Ok(neighbors)
+    /// Check if no-guess generation was asked for but couldn't find a board
+    /// that can be cleared by logic, so the player may have to guess.
+    ///
+    /// `is_no_guess` is false for such a board.
+    pub fn is_no_guess_failed(&self) -> bool {
+        self.no_guess_failed
     }
 
-    fn get_neighbors(&self, pos: &Position) -> MinrsResult<Vec<&dyn Tile>> {
-        Ok(self
-            .get_neighbors_pos(pos)?
-            .iter()
-            .map(|pos| -> &dyn Tile {
-                // since positions are validated by self.get_neighbors_pos..
-                // we assume we can unwrap (or panic)
-                self.get_tile(pos).unwrap()
-            })
-            .collect())
+    pub fn get_neighborhood(&self) -> &Neighborhood {
+        &self.neighborhood
     }
-}
 
-impl MinrsGame for StdMinrsGame {
-    fn game_over(&self) -> bool {
-        self.game_over
+    /// Pick which tiles count as neighbors, for mine counts, cascades and
+    /// chording alike.
+    ///
+    /// Neighborhoods with more than 255 neighbors per tile are rejected.
+    ///
+    /// This must be set before the first move. A layout from `from_ascii`
+    /// may already show uncovered tiles, and can still be changed until a
+    /// move is made on it.
+    pub fn set_neighborhood(&mut self, neighborhood: Neighborhood) -> MinrsResult<()> {
+        self.check_rules(&neighborhood, self.wrap, self.mines_per_tile)?;
+        self.neighborhood = neighborhood;
+        Ok(())
     }
 
-    fn cycle_flag(&mut self, position: &Position) -> MinrsResult<()> {
-        if self.game_over {
-            return Err(MinrsError::GameOver);
-        }
+    pub fn is_wrapping(&self) -> bool {
+        self.wrap
+    }
 
-        self.mod_tile(position, |tile| tile.toggle_flag())?;
+    /// Make the board wrap around, so tiles on one edge touch the tiles on
+    /// the opposite edge.
+    ///
+    /// Like `set_neighborhood`, this must be set before the first move.
+    /// Wrapping hex boards need an even height, so the row offsets line up
+    /// across the seam.
+    pub fn set_wrap(&mut self, wrap: bool) -> MinrsResult<()> {
+        self.check_rules(&self.neighborhood, wrap, self.mines_per_tile)?;
+        self.wrap = wrap;
         Ok(())
     }
 
-    fn uncover_tile(&mut self, pos: &Position) -> MinrsResult<()> {
-        let self_tile = self.get_tile(pos)?;
-        if !self_tile.is_covered() {
+    /// Let a single tile hold up to `mines_per_tile` mines.
+    ///
+    /// The mine count stays the total number of mines, so stacking them
+    /// leaves more tiles clear. A generated board is dealt again from its
+    /// seed, while an explicit layout is kept as is. This must be set before
+    /// the first move, and can not be combined with no-guess generation,
+    /// since the solver only handles one mine per tile.
+    pub fn set_mines_per_tile(&mut self, mines_per_tile: u8) -> MinrsResult<()> {
+        if mines_per_tile == 0 || (self.no_guess && mines_per_tile > 1) {
             return Err(MinrsError::InvalidArgument);
         }
-
-        let neighbors_pos = self.get_neighbors_pos(pos)?;
-        match self_tile.get_contents(self.get_neighbors(pos)?) {
-            TileContents::MineCount(mine_count) => {
-                if mine_count == 0 {
-                    self.started = true; // enforce started game
-                    self.mod_tile(pos, |tile| tile.uncover())??;
-                    for n_pos in neighbors_pos {
-                        let n = self.get_tile(&n_pos)?;
-                        if !n.is_covered() {
-                            continue;
-                        }
-                        self.uncover_tile(&n_pos)?;
-                    }
-                    return Ok(());
-                }
-            }
-            TileContents::Mine => {
-                // don't game over on first move..
-                if self.started == true {
-                    self.game_over = true;
-                }
-            }
+        self.check_rules(&self.neighborhood, self.wrap, mines_per_tile)?;
+        if self
+            .board
+            .iter()
+            .any(|tile| tile.get_mines() > mines_per_tile)
+        {
+            return Err(MinrsError::InvalidArgument);
         }
-
-        // If we are here on the first move, we didn't open a "sea" above.
-        // We want to ensure the player's first move opens a sea of empty
-        // tiles.
-        // So, regenerate the board and recurse uncovering the desired tile...
-        // And the player is none the wiser >:3c
-        if !self.started {
-            self.generate_mines(self.mine_count)?;
-            self.uncover_tile(pos)?;
-            return Ok(());
+        self.mines_per_tile = mines_per_tile;
+        if !self.fixed {
+            self.rng = StdRng::seed_from_u64(self.seed);
+            self.generate_mines(self.mine_count, &[])?;
         }
-
-        self.mod_tile(pos, |tile| tile.uncover())??;
         Ok(())
     }
 
-    fn uncover_neighbors(&mut self, pos: &Position) -> MinrsResult<()> {
-        let mine_count;
-        // only allow uncovered tiles with a minecount
-        match self.get_tile_state(pos)? {
-            TileState::Uncovered(contents) => match contents {
-                TileContents::MineCount(count) => {
-                    mine_count = count;
-                }
-                TileContents::Mine => {
-                    return Err(MinrsError::InvalidArgument);
-                }
-            },
-            TileState::Covered(_) => {
-                return Err(MinrsError::InvalidArgument);
-            }
+    /// Check the neighbor rules can be changed to these.
+    fn check_rules(
+        &self,
+        neighborhood: &Neighborhood,
+        wrap: bool,
+        mines_per_tile: u8,
+    ) -> MinrsResult<()> {
+        if (self.started && !self.fixed) || !self.history.is_empty() {
+            return Err(MinrsError::InvalidArgument);
         }
-
-        // enforce that the user has enough flags placed to make this move
-        let neighbors_flag_count =
-            self.get_neighbors(pos)?
-                .iter()
-                .fold(0, |mut flag_count, tile| -> u8 {
-                    if tile.get_flag().is_some() {
-                        flag_count += 1;
-                    }
-                    flag_count
-                });
-        if neighbors_flag_count < mine_count {
+        if wrap && *neighborhood == Neighborhood::Hex && !self.height.is_multiple_of(2) {
             return Err(MinrsError::InvalidArgument);
         }
-
-        // uncover unflagged neighbors
-        let neighbors_pos = self.get_neighbors_pos(pos)?;
-        for n_pos in neighbors_pos {
-            let tile_state = self.get_tile_state(&n_pos)?;
-            match tile_state {
-                TileState::Covered(flag_state) => {
-                    if flag_state.is_none() {
-                        self.uncover_tile(&n_pos)?;
-                    }
-                }
-                TileState::Uncovered(_) => {}
-            }
+        // numbers have to fit in a MineCount
+        if neighborhood.max_neighbors(self.depth) * mines_per_tile as usize > u8::MAX as usize {
+            return Err(MinrsError::InvalidArgument);
         }
+        Ok(())
+    }
 
-        return Ok(());
+    /// Time the game with a different clock, like one a test controls.
+    ///
+    /// This should be set before the first move.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock + Send + Sync>) {
+        self.clock = clock;
     }
 
-    fn get_tile_state(&self, position: &Position) -> MinrsResult<TileState> {
-        Ok(self
-            .get_tile(position)?
-            .get_state(self.get_neighbors(position)?))
+    /// Get how long the game has been played, from the first uncover until
+    /// it was won or lost.
+    ///
+    /// The timer is paused while a game is over, and a loaded game only
+    /// picks it up again on the next move.
+    pub fn get_elapsed(&self) -> Duration {
+        match self.running_since {
+            Some(since) => self.elapsed + self.clock.now().saturating_duration_since(since),
+            None => self.elapsed,
+        }
     }
 
-    fn get_width(&self) -> u8 {
-        self.width
+    pub fn get_clicks(&self) -> Clicks {
+        self.clicks
     }
 
-    fn get_height(&self) -> u8 {
-        self.height
+    /// Start or stop the timer to match the game, after a move or undo.
+    fn update_timer(&mut self) {
+        let now = self.clock.now();
+        if !self.started {
+            self.elapsed = Duration::ZERO;
+            self.running_since = None;
+        } else if self.game_over || self.victory() {
+            self.elapsed = self.get_elapsed();
+            self.running_since = None;
+        } else {
+            self.running_since.get_or_insert(now);
+        }
     }
 
-    fn victory(&self) -> bool {
-        let in_progress = self.board.iter().all(|row| {
-            row.iter()
-                .all(|tile| !(tile.is_covered() && !tile.is_mine()))
-        });
-        return in_progress;
+    /// Get the positions of every mine on the board.
+    ///
+    /// A tile holding several mines is listed once for each.
+    pub(crate) fn get_mine_positions(&self) -> Vec<Position> {
+        self.get_positions()
+            .into_iter()
+            .flat_map(|pos| {
+                let mines = self.get_tile(&pos).map_or(0, |tile| tile.get_mines());
+                std::iter::repeat_n(pos, mines as usize)
+            })
+            .collect()
     }
-}
 
-#[cfg(test)]
-mod tests {
+    /// Stack one more mine onto a tile of an explicit layout.
+    ///
+    /// throws InvalidArgument if the layout was generated, or the tile is
+    ///        already full.
+    pub(crate) fn add_mine(&mut self, pos: &Position) -> MinrsResult<()> {
+        let max = self.mines_per_tile;
+        if !self.fixed || self.get_tile(pos)?.get_mines() >= max {
+            return Err(MinrsError::InvalidArgument);
+        }
+        self.mod_tile(pos, |tile| tile.set_mines(tile.get_mines() + 1))?;
+        self.mine_count += 1;
+        Ok(())
+    }
+
+    /// Only generate boards that can be cleared by logic from the first
+    /// uncover.
+    ///
+    /// This must be set before the first move, since the layout is picked
+    /// when the first tile is uncovered.
+    pub fn set_no_guess(&mut self, no_guess: bool) -> MinrsResult<()> {
+        if self.started || self.fixed || (no_guess && self.mines_per_tile > 1) {
+            return Err(MinrsError::InvalidArgument);
+        }
+        self.no_guess = no_guess;
+        Ok(())
+    }
+
+    /// Write out the whole game, so it can be restored by `load`.
+    ///
+    /// This keeps the mine layout, every tile's state and the seed, but not
+    /// the undo history. The format is plain text:
+    ///
+    /// ```text
+    /// min-rs-weeper game 1
+    /// size <width> <height> <mines>
+    /// depth <layers>                 (if 3D)
+    /// seed <seed> <no_guess 0|1>     (or)     fixed
+    /// neighborhood <name>            (unless square)
+    /// wrap                           (if the edges wrap)
+    /// mines_per_tile <max>           (if more than 1)
+    /// time <millis>                  (once started)
+    /// clicks <uncovers> <chords> <flags>   (once any were made)
+    /// state <started 0|1> <game_over 0|1>
+    /// <the board, as written by to_ascii>
+    /// tile <x> <y> <z> <mines> <flags>   (for each stacked mine or flag)
+    /// ```
+    pub fn save(&self) -> String {
+        let mut text = format!("{SAVE_MAGIC} {SAVE_VERSION}\n");
+        text += &format!("size {} {} {}\n", self.width, self.height, self.mine_count);
+        if self.depth > 1 {
+            text += &format!("depth {}\n", self.depth);
+        }
+        match self.get_seed() {
+            Some(seed) => text += &format!("seed {seed} {}\n", self.no_guess as u8),
+            None => text += "fixed\n",
+        }
+        if self.neighborhood != Neighborhood::Square {
+            text += &format!("neighborhood {}\n", self.neighborhood);
+        }
+        if self.wrap {
+            text += "wrap\n";
+        }
+        if self.mines_per_tile > 1 {
+            text += &format!("mines_per_tile {}\n", self.mines_per_tile);
+        }
+        if self.started {
+            text += &format!("time {}\n", self.get_elapsed().as_millis());
+        }
+        if self.clicks != Clicks::default() {
+            let Clicks {
+                uncovers,
+                chords,
+                flags,
+            } = self.clicks;
+            text += &format!("clicks {uncovers} {chords} {flags}\n");
+        }
+        text += &format!("state {} {}\n", self.started as u8, self.game_over as u8);
+        text += &self.to_ascii();
+        // the glyphs only say whether there is a mine or flag at all
+        for pos in self.get_positions() {
+            let tile = &self.board[self.get_index(&pos).unwrap()];
+            if tile.get_mines() > 1 || tile.get_flag_count() > 1 {
+                text += &format!(
+                    "tile {} {} {} {} {}\n",
+                    pos.x,
+                    pos.y,
+                    pos.z,
+                    tile.get_mines(),
+                    tile.get_flag_count()
+                );
+            }
+        }
+        text
+    }
+
+    /// Restore a game written by `save`.
+    ///
+    /// throws InvalidData if the text is not a valid save.
+    pub fn load(text: &str) -> MinrsResult<StdMinrsGame> {
+        let mut lines = text.lines();
+        let version = lines
+            .next()
+            .and_then(|line| line.strip_prefix(SAVE_MAGIC))
+            .ok_or(MinrsError::InvalidData)?;
+        if parse_word::<u32>(version.split_whitespace().next())? != SAVE_VERSION {
+            return Err(MinrsError::InvalidData);
+        }
+        let mut next_words = || {
+            lines
+                .next()
+                .map(|line| line.split_whitespace())
+                .ok_or(MinrsError::InvalidData)
+        };
+
+        let mut size = next_words()?;
+        if size.next() != Some("size") {
+            return Err(MinrsError::InvalidData);
+        }
+        let width = parse_word(size.next())?;
+        let height = parse_word(size.next())?;
+        let mine_count = parse_word(size.next())?;
+
+        let mut layout = next_words()?;
+        let mut depth = 1;
+        if layout.clone().next() == Some("depth") {
+            depth = parse_word(layout.nth(1))?;
+            layout = next_words()?;
+        }
+        let mut game = match layout.next() {
+            Some("seed") => {
+                let seed = parse_word(layout.next())?;
+                let mut game = StdMinrsGame::new_3d_seeded(width, height, depth, mine_count, seed)
+                    .map_err(|_| MinrsError::InvalidData)?;
+                game.no_guess = parse_word::<u8>(layout.next())? != 0;
+                game
+            }
+            Some("fixed") => StdMinrsGame::new_3d_seeded(width, height, depth, 0, 0)
+                .map_err(|_| MinrsError::InvalidData)
+                .map(|game| StdMinrsGame {
+                    fixed: true,
+                    ..game
+                })?,
+            _ => return Err(MinrsError::InvalidData),
+        };
+
+        let mut state = next_words()?;
+        if state.clone().next() == Some("neighborhood") {
+            game.neighborhood = parse_word(state.nth(1))?;
+            state = next_words()?;
+        }
+        if state.clone().next() == Some("wrap") {
+            game.wrap = true;
+            state = next_words()?;
+        }
+        if state.clone().next() == Some("mines_per_tile") {
+            game.mines_per_tile = parse_word(state.nth(1))?;
+            state = next_words()?;
+        }
+        if state.clone().next() == Some("time") {
+            game.elapsed = Duration::from_millis(parse_word(state.nth(1))?);
+            state = next_words()?;
+        }
+        if state.clone().next() == Some("clicks") {
+            state.next();
+            game.clicks = Clicks {
+                uncovers: parse_word(state.next())?,
+                chords: parse_word(state.next())?,
+                flags: parse_word(state.next())?,
+            };
+            state = next_words()?;
+        }
+        if game.mines_per_tile == 0 || (game.no_guess && game.mines_per_tile > 1) {
+            return Err(MinrsError::InvalidData);
+        }
+        game.check_rules(&game.neighborhood, game.wrap, game.mines_per_tile)
+            .map_err(|_| MinrsError::InvalidData)?;
+        if state.next() != Some("state") {
+            return Err(MinrsError::InvalidData);
+        }
+        game.started = parse_word::<u8>(state.next())? != 0;
+        game.game_over = parse_word::<u8>(state.next())? != 0;
+
+        // layers are separated by blank lines
+        let mut rows = lines.filter(|line| !line.is_empty());
+        for z in 0..depth {
+            for y in 0..height {
+                let row: Vec<char> = rows
+                    .next()
+                    .ok_or(MinrsError::InvalidData)?
+                    .chars()
+                    .collect();
+                if row.len() != width as usize {
+                    return Err(MinrsError::InvalidData);
+                }
+                for (x, glyph) in row.into_iter().enumerate() {
+                    let tile = StdTile::from_glyph(glyph).ok_or(MinrsError::InvalidData)?;
+                    let idx = game.get_index(&Position::new_3d(x as u16, y, z))?;
+                    game.board[idx] = tile;
+                }
+            }
+        }
+        for row in rows {
+            let mut words = row.split_whitespace();
+            if words.next() != Some("tile") {
+                return Err(MinrsError::InvalidData);
+            }
+            let x = parse_word(words.next())?;
+            let y = parse_word(words.next())?;
+            let z = parse_word(words.next())?;
+            let mines = parse_word(words.next())?;
+            let flags = parse_word(words.next())?;
+            let max = game.mines_per_tile;
+            let idx = game.get_index(&Position::new_3d(x, y, z))?;
+            let tile = &mut game.board[idx];
+            if tile.is_mine() != (mines > 0) || mines > max || flags > max {
+                return Err(MinrsError::InvalidData);
+            }
+            tile.set_mines(mines);
+            if flags > 1 {
+                if tile.get_flag_count() != 1 {
+                    return Err(MinrsError::InvalidData);
+                }
+                tile.flag = Some(FlagState::RedFlag(flags));
+            }
+        }
+        let mines: u32 = game.board.iter().map(|tile| tile.get_mines() as u32).sum();
+        if mines != mine_count {
+            return Err(MinrsError::InvalidData);
+        }
+        game.mine_count = mine_count;
+        Ok(game)
+    }
+
+    /// Create a game from a plain-text picture of the board.
+    ///
+    /// Each line is a row of tiles, top to bottom, using the glyphs:
+    /// `.` covered, `*` covered mine,
+    /// `f` flagged, `F` flagged mine,
+    /// `q` questioned, `Q` questioned mine,
+    /// `_` uncovered, `X` uncovered mine.
+    ///
+    /// Blank lines separate the layers of a 3D board, and whitespace around
+    /// rows is ignored. Like `from_mines` the layout is kept as is, and the
+    /// game counts as started once any tile is uncovered.
+    ///
+    /// throws InvalidData on unknown glyphs, or rows and layers of different
+    ///        sizes.
+    pub fn from_ascii(layout: &str) -> MinrsResult<StdMinrsGame> {
+        let mut layers: Vec<Vec<Vec<StdTile>>> = vec![Vec::new()];
+        for row in layout.lines().map(str::trim) {
+            if row.is_empty() {
+                if !layers.last().unwrap().is_empty() {
+                    layers.push(Vec::new());
+                }
+                continue;
+            }
+            let row = row
+                .chars()
+                .map(StdTile::from_glyph)
+                .collect::<Option<Vec<StdTile>>>()
+                .ok_or(MinrsError::InvalidData)?;
+            layers.last_mut().unwrap().push(row);
+        }
+        if layers.len() > 1 && layers.last().unwrap().is_empty() {
+            layers.pop();
+        }
+        let height = layers[0].len();
+        let width = layers[0].first().map_or(0, |row| row.len());
+        if layers
+            .iter()
+            .any(|layer| layer.len() != height || layer.iter().any(|row| row.len() != width))
+        {
+            return Err(MinrsError::InvalidData);
+        }
+        let width = u16::try_from(width).map_err(|_| MinrsError::InvalidArgument)?;
+        let height = u16::try_from(height).map_err(|_| MinrsError::InvalidArgument)?;
+        let depth = u16::try_from(layers.len()).map_err(|_| MinrsError::InvalidArgument)?;
+
+        let mut tiles = Vec::new();
+        for (z, layer) in layers.into_iter().enumerate() {
+            for (y, row) in layer.into_iter().enumerate() {
+                for (x, tile) in row.into_iter().enumerate() {
+                    tiles.push((Position::new_3d(x as u16, y as u16, z as u16), tile));
+                }
+            }
+        }
+        let mines: Vec<Position> = tiles
+            .iter()
+            .filter(|(_, tile)| tile.is_mine())
+            .map(|(pos, _)| *pos)
+            .collect();
+        let mut game = StdMinrsGame::from_mines_3d(width, height, depth, &mines)?;
+        for (pos, tile) in tiles {
+            game.started |= !tile.is_covered();
+            game.game_over |= !tile.is_covered() && tile.is_mine();
+            let idx = game.get_index(&pos)?;
+            game.board[idx] = tile;
+        }
+        Ok(game)
+    }
+
+    /// Draw the board as text, in the format read by `from_ascii`.
+    pub fn to_ascii(&self) -> String {
+        let mut text = String::new();
+        for z in 0..self.depth {
+            if z > 0 {
+                text.push('\n');
+            }
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    let tile = self.get_tile(&Position::new_3d(x, y, z)).unwrap();
+                    text.push(tile.glyph());
+                }
+                text.push('\n');
+            }
+        }
+        text
+    }
+
+    /// Randomly place mines, never placing one on the `exclude` positions.
+    ///
+    /// A tile may be picked again, up to `mines_per_tile` times.
+    fn generate_mines(&mut self, mine_count: u32, exclude: &[Position]) -> MinrsResult<()> {
+        let mut rng_vec: HashMap<usize, u8> = HashMap::new();
+        let tile_count = self.get_tile_count();
+        let capacity = tile_count.saturating_sub(exclude.len()) * self.mines_per_tile as usize;
+        if mine_count as usize > capacity {
+            return Err(MinrsError::InvalidArgument);
+        }
+        let excluded: HashSet<usize> = exclude
+            .iter()
+            .map(|pos| self.get_index(pos))
+            .collect::<MinrsResult<_>>()?;
+        // create a unique set of random numbers indexing the tiles as
+        // get_index does
+        // XXX I feel like there might be a nicer way to do this?
+        //     Perhaps we would prefer to modify existing tiles. The rand crate
+        //     would allow us to nicely select a subset of elements from our
+        //     board if it implements the Collections trait.
+        //     Further, it would be more efficient to be able to regenerate a
+        //     single mine tile if it is chosen first.
+        for _i in 0..mine_count {
+            loop {
+                let idx = self.random_index(tile_count);
+                if excluded.contains(&idx) {
+                    continue;
+                }
+                let mines = rng_vec.entry(idx).or_default();
+                if *mines < self.mines_per_tile {
+                    *mines += 1;
+                    break;
+                }
+            }
+        }
+
+        // create the board
+        for (idx, tile) in self.board.iter_mut().enumerate() {
+            tile.set_mines(rng_vec.get(&idx).copied().unwrap_or(0));
+        }
+
+        Ok(())
+    }
+
+    /// Pick a random tile index.
+    ///
+    /// Boards small enough for u16 indices keep drawing u16s, so seeds shared
+    /// before larger boards existed still give the same layouts.
+    fn random_index(&mut self, tile_count: usize) -> usize {
+        if let Ok(count) = u16::try_from(tile_count) {
+            return self.rng.gen_range(0..count) as usize;
+        }
+        match u32::try_from(tile_count) {
+            Ok(count) => self.rng.gen_range(0..count) as usize,
+            Err(_) => self.rng.gen_range(0..tile_count as u64) as usize,
+        }
+    }
+
+    /// Regenerate the board until the player can clear it from `first`
+    /// without ever having to guess.
+    ///
+    /// Gives up after NO_GUESS_ATTEMPTS, or at once if the mines leave no
+    /// room for an opening at `first`. The game then stops being a no-guess
+    /// one, and says so with `is_no_guess_failed`.
+    fn generate_no_guess_mines(&mut self, first: &Position) -> MinrsResult<()> {
+        let mut opening = self.get_neighbors_pos(first)?;
+        opening.push(*first);
+        if self.mine_count as usize + opening.len() <= self.get_tile_count() {
+            for _attempt in 0..NO_GUESS_ATTEMPTS {
+                self.generate_mines(self.mine_count, &opening)?;
+                if self.solvable_from(first)? {
+                    return Ok(());
+                }
+            }
+        }
+        self.no_guess = false;
+        self.no_guess_failed = true;
+        Ok(())
+    }
+
+    /// Play the current layout with the solver on a scratch copy of the board.
+    fn solvable_from(&self, first: &Position) -> MinrsResult<bool> {
+        let mut scratch = self.clone();
+        scratch.started = true;
+        scratch.game_over = false;
+        scratch.board.iter_mut().for_each(|tile| {
+            tile.covered = true;
+            tile.flag = None;
+        });
+
+        scratch.uncover(first)?;
+        loop {
+            let deductions = solver::solve(&scratch)?;
+            if deductions.safe.is_empty() {
+                break;
+            }
+            for pos in deductions.safe {
+                if scratch.get_tile(&pos)?.is_covered() {
+                    scratch.uncover(&pos)?;
+                }
+            }
+        }
+        Ok(scratch.victory())
+    }
+
+    /// Build a started game with mines at `mines` where only the `covered`
+    /// tiles are still covered.
+    #[cfg(test)]
+    pub(crate) fn with_layout(
+        width: u16,
+        height: u16,
+        mines: &[Position],
+        covered: &[Position],
+    ) -> StdMinrsGame {
+        let mut game = StdMinrsGame::from_mines(width, height, mines).unwrap();
+        game.started = true;
+        for x in 0..width {
+            for y in 0..height {
+                let pos = Position::new(x, y);
+                game.mod_tile(&pos, |tile| tile.covered = covered.contains(&pos))
+                    .unwrap();
+            }
+        }
+        game
+    }
+
+    /// Uncovers a tile, cascading through empty tiles.
+    fn uncover(&mut self, pos: &Position) -> MinrsResult<()> {
+        if !self.get_tile(pos)?.is_covered() {
+            return Err(MinrsError::InvalidArgument);
+        }
+
+        // a no-guess board is picked once we know where the player starts
+        if !self.started && !self.fixed && self.no_guess {
+            self.generate_no_guess_mines(pos)?;
+        }
+
+        let self_tile = self.get_tile(pos)?;
+
+        match self_tile.get_contents(self.get_neighbors(pos)?) {
+            TileContents::MineCount(mine_count) => {
+                if mine_count == 0 {
+                    self.started = true; // enforce started game
+                    return self.cascade(pos);
+                }
+            }
+            TileContents::Mines(_) => {
+                // don't game over on first move..
+                if self.started || self.fixed {
+                    self.game_over = true;
+                }
+            }
+        }
+
+        // If we are here on the first move, we didn't open a "sea" above.
+        // We want to ensure the player's first move opens a sea of empty
+        // tiles.
+        // So, regenerate the board until it does...
+        // And the player is none the wiser >:3c
+        if !self.started && !self.fixed {
+            for _attempt in 0..FIRST_MOVE_ATTEMPTS {
+                self.generate_mines(self.mine_count, &[])?;
+                let contents = self.get_tile(pos)?.get_contents(self.get_neighbors(pos)?);
+                if contents == TileContents::MineCount(0) {
+                    self.started = true;
+                    return self.cascade(pos);
+                }
+            }
+            // a dense board rarely opens a sea by chance, so keep the mines
+            // off the tile and its neighbors, or just the tile if that is
+            // all there is room for
+            let mut opening = self.get_neighbors_pos(pos)?;
+            opening.push(*pos);
+            if self.generate_mines(self.mine_count, &opening).is_err() {
+                self.generate_mines(self.mine_count, &[*pos])?;
+            }
+            self.started = true;
+            return self.uncover(pos);
+        }
+
+        self.started = true;
+        self.uncover_one(pos)?;
+        if self.game_over {
+            self.emit(GameEvent::GameLost(*pos));
+        }
+        Ok(())
+    }
+
+    /// Uncover a single covered tile as part of the current move.
+    fn uncover_one(&mut self, pos: &Position) -> MinrsResult<TileContents> {
+        self.touch(pos)?;
+        self.mod_tile(pos, |tile| tile.uncover())??;
+        let contents = self.get_tile(pos)?.get_contents(self.get_neighbors(pos)?);
+        self.emit(GameEvent::TileUncovered(*pos, contents));
+        Ok(contents)
+    }
+
+    fn emit(&mut self, event: GameEvent) {
+        self.listeners.emit(event);
+    }
+
+    /// Uncovers an empty tile, spreading through every empty tile connected
+    /// to it and stopping at the numbers around them.
+    ///
+    /// This keeps its own stack of tiles to visit, since an opening on a
+    /// large board is far too deep to recurse through.
+    fn cascade(&mut self, start: &Position) -> MinrsResult<()> {
+        self.emit(GameEvent::CascadeStarted(*start));
+        let mut pending = vec![*start];
+        while let Some(pos) = pending.pop() {
+            if !self.get_tile(&pos)?.is_covered() {
+                continue;
+            }
+            if self.uncover_one(&pos)? == TileContents::MineCount(0) {
+                for n_pos in self.get_neighbors_pos(&pos)? {
+                    if self.get_tile(&n_pos)?.is_covered() {
+                        pending.push(n_pos);
+                    }
+                }
+            }
+        }
+        self.emit(GameEvent::CascadeFinished(*start));
+        Ok(())
+    }
+
+    /// Uncovers all unflagged neighbors of a satisfied number.
+    fn chord(&mut self, pos: &Position) -> MinrsResult<()> {
+        let mine_count;
+        // only allow uncovered tiles with a minecount
+        match self.get_tile_state(pos)? {
+            TileState::Uncovered(contents) => match contents {
+                TileContents::MineCount(count) => {
+                    mine_count = count;
+                }
+                TileContents::Mines(_) => {
+                    return Err(MinrsError::InvalidArgument);
+                }
+            },
+            TileState::Covered(_) => {
+                return Err(MinrsError::InvalidArgument);
+            }
+        }
+
+        // enforce that the user has enough flags placed to make this move
+        let mut neighbors_flag_count = 0;
+        for n_pos in self.get_neighbors_pos(pos)? {
+            neighbors_flag_count += self.get_tile(&n_pos)?.get_flag_count() as u32;
+        }
+        if neighbors_flag_count < mine_count as u32 {
+            return Err(MinrsError::InvalidArgument);
+        }
+
+        // uncover unflagged neighbors
+        let neighbors_pos = self.get_neighbors_pos(pos)?;
+        for n_pos in neighbors_pos {
+            let tile_state = self.get_tile_state(&n_pos)?;
+            match tile_state {
+                TileState::Covered(flag_state) => {
+                    if flag_state.is_none() {
+                        self.uncover(&n_pos)?;
+                    }
+                }
+                TileState::Uncovered(_) => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Run a move, recording every tile it changes as a single undoable step.
+    ///
+    /// `click` picks which kind of move to count, if it changed anything.
+    fn record_step<F>(&mut self, click: fn(&mut Clicks) -> &mut u32, f: F) -> MinrsResult<()>
+    where
+        F: FnOnce(&mut StdMinrsGame) -> MinrsResult<()>,
+    {
+        let won = self.victory();
+        self.pending = Some(Step {
+            tiles: Vec::new(),
+            started: (self.started, self.started),
+            game_over: (self.game_over, self.game_over),
+        });
+        let result = f(self);
+        let mut step = self.pending.take().unwrap();
+        step.started.1 = self.started;
+        step.game_over.1 = self.game_over;
+        for change in step.tiles.iter_mut() {
+            change.after = self.get_snapshot(&change.pos)?;
+        }
+
+        // errors are usually rejected moves, which leave nothing to undo
+        if !step.tiles.is_empty() {
+            self.history.push(step);
+            self.future.clear();
+            *click(&mut self.clicks) += 1;
+        }
+        self.update_timer();
+        if !won && self.victory() {
+            self.emit(GameEvent::GameWon);
+        }
+        result
+    }
+
+    /// Note a tile's state before the current move changes it.
+    fn touch(&mut self, pos: &Position) -> MinrsResult<()> {
+        let before = self.get_snapshot(pos)?;
+        if let Some(step) = self.pending.as_mut() {
+            step.tiles.push(TileChange {
+                pos: *pos,
+                before,
+                after: before,
+            });
+        }
+        Ok(())
+    }
+
+    fn get_snapshot(&self, pos: &Position) -> MinrsResult<TileSnapshot> {
+        let tile = self.get_tile(pos)?;
+        Ok(TileSnapshot {
+            covered: tile.is_covered(),
+            flag: tile.get_flag(),
+        })
+    }
+
+    /// Put the tiles and game state back to one side of a step.
+    ///
+    /// Every tile put back is reported to the listeners, as is a loss or win
+    /// that redoing the step brings back.
+    fn apply_step(&mut self, step: &Step, forward: bool) -> MinrsResult<()> {
+        let (won, game_over) = (self.victory(), self.game_over);
+        let mut lost_at = None;
+        for change in &step.tiles {
+            let (from, to) = if forward {
+                (change.before, change.after)
+            } else {
+                (change.after, change.before)
+            };
+            self.mod_tile(&change.pos, |tile| {
+                tile.covered = to.covered;
+                tile.flag = to.flag;
+            })?;
+            let pos = change.pos;
+            if from.covered && !to.covered {
+                let contents = self.get_tile(&pos)?.get_contents(self.get_neighbors(&pos)?);
+                if let TileContents::Mines(_) = contents {
+                    lost_at = Some(pos);
+                }
+                self.emit(GameEvent::TileUncovered(pos, contents));
+            } else if !from.covered && to.covered {
+                self.emit(GameEvent::TileCovered(pos));
+            } else if from.flag != to.flag {
+                self.emit(GameEvent::FlagChanged(pos, to.flag));
+            }
+        }
+        if forward {
+            self.started = step.started.1;
+            self.game_over = step.game_over.1;
+        } else {
+            self.started = step.started.0;
+            self.game_over = step.game_over.0;
+        }
+        if let Some(pos) = lost_at.filter(|_| !game_over && self.game_over) {
+            self.emit(GameEvent::GameLost(pos));
+        }
+        if !won && self.victory() {
+            self.emit(GameEvent::GameWon);
+        }
+        Ok(())
+    }
+
+    fn get_tile_count(&self) -> usize {
+        self.width as usize * self.height as usize * self.depth as usize
+    }
+
+    /// Get where a tile is kept in `board`.
+    ///
+    /// Tiles are stored layer by layer, and column by column within a layer.
+    fn get_index(&self, pos: &Position) -> MinrsResult<usize> {
+        if pos.x >= self.width || pos.y >= self.height || pos.z >= self.depth {
+            return Err(MinrsError::OobPosition);
+        }
+        let (width, height) = (self.width as usize, self.height as usize);
+        Ok((pos.z as usize * width + pos.x as usize) * height + pos.y as usize)
+    }
+
+    fn mod_tile<B, F>(&mut self, pos: &Position, mut f: F) -> MinrsResult<B>
+    where
+        F: FnMut(&mut StdTile) -> B,
+    {
+        let idx = self.get_index(pos)?;
+        Ok(f(&mut self.board[idx]))
+    }
+
+    fn get_tile(&self, pos: &Position) -> MinrsResult<&StdTile> {
+        Ok(&self.board[self.get_index(pos)?])
+    }
+
+    fn get_neighbors(&self, pos: &Position) -> MinrsResult<Vec<&dyn Tile>> {
+        Ok(self
+            .get_neighbors_pos(pos)?
+            .iter()
+            .map(|pos| -> &dyn Tile {
+                // since positions are validated by self.get_neighbors_pos..
+                // we assume we can unwrap (or panic)
+                self.get_tile(pos).unwrap()
+            })
+            .collect())
+    }
+}
+
+impl MinrsGame for StdMinrsGame {
+    fn game_over(&self) -> bool {
+        self.game_over
+    }
+
+    fn cycle_flag(&mut self, position: &Position) -> MinrsResult<()> {
+        if self.game_over {
+            return Err(MinrsError::GameOver);
+        }
+
+        // the flag on an uncovered tile is hidden, so don't pretend to set it
+        if !self.get_tile(position)?.is_covered() {
+            return Err(MinrsError::InvalidPosition);
+        }
+
+        self.record_step(
+            |clicks| &mut clicks.flags,
+            |game| {
+                game.touch(position)?;
+                let max_flags = game.mines_per_tile;
+                game.mod_tile(position, |tile| tile.toggle_flag(max_flags))?;
+                let flag = game.get_tile(position)?.get_flag();
+                game.emit(GameEvent::FlagChanged(*position, flag));
+                Ok(())
+            },
+        )
+    }
+
+    fn uncover_tile(&mut self, pos: &Position) -> MinrsResult<()> {
+        if self.game_over {
+            return Err(MinrsError::GameOver);
+        }
+        self.record_step(|clicks| &mut clicks.uncovers, |game| game.uncover(pos))
+    }
+
+    fn uncover_neighbors(&mut self, pos: &Position) -> MinrsResult<()> {
+        if self.game_over {
+            return Err(MinrsError::GameOver);
+        }
+        self.record_step(|clicks| &mut clicks.chords, |game| game.chord(pos))
+    }
+
+    fn get_tile_state(&self, position: &Position) -> MinrsResult<TileState> {
+        Ok(self
+            .get_tile(position)?
+            .get_state(self.get_neighbors(position)?))
+    }
+
+    fn get_neighbors_pos(&self, pos: &Position) -> MinrsResult<Vec<Position>> {
+        self.get_index(pos)?;
+        let mut neighbors: Vec<Position> = Vec::new();
+        let x_max = self.width as i32;
+        let y_max = self.height as i32;
+        let z_max = self.depth as i32;
+        let flat_offsets = self.neighborhood.offsets(pos);
+        // the layers above and below hold the tile itself, and everything
+        // next to it
+        let mut layer_offsets = flat_offsets.clone();
+        layer_offsets.push((0, 0));
+        let z_range = if self.depth > 1 { -1..=1 } else { 0..=0 };
+        for z_mod in z_range {
+            let offsets = if z_mod == 0 {
+                &flat_offsets
+            } else {
+                &layer_offsets
+            };
+            for &(x_mod, y_mod) in offsets {
+                let mut x = (pos.x as i32) + x_mod;
+                let mut y = (pos.y as i32) + y_mod;
+                let mut z = (pos.z as i32) + z_mod;
+                if self.wrap {
+                    x = x.rem_euclid(x_max);
+                    y = y.rem_euclid(y_max);
+                    z = z.rem_euclid(z_max);
+                } else if x < 0 || x >= x_max || y < 0 || y >= y_max || z < 0 || z >= z_max {
+                    continue;
+                }
+                let n_pos = Position::new_3d(x as u16, y as u16, z as u16);
+                // wrapping offsets can land back on the tile, or on one
+                // already reached from the other side
+                if n_pos != *pos && !neighbors.contains(&n_pos) {
+                    neighbors.push(n_pos);
+                }
+            }
+        }
+        Ok(neighbors)
+    }
+
+    fn get_width(&self) -> u16 {
+        self.width
+    }
+
+    fn get_height(&self) -> u16 {
+        self.height
+    }
+
+    fn get_depth(&self) -> u16 {
+        self.depth
+    }
+
+    fn get_mine_count(&self) -> u32 {
+        self.mine_count
+    }
+
+    fn get_mines_per_tile(&self) -> u8 {
+        self.mines_per_tile
+    }
+
+    fn undo(&mut self) -> MinrsResult<()> {
+        let step = self.history.pop().ok_or(MinrsError::NoHistory)?;
+        self.apply_step(&step, false)?;
+        self.future.push(step);
+        self.update_timer();
+        Ok(())
+    }
+
+    fn redo(&mut self) -> MinrsResult<()> {
+        let step = self.future.pop().ok_or(MinrsError::NoHistory)?;
+        self.apply_step(&step, true)?;
+        self.history.push(step);
+        self.update_timer();
+        Ok(())
+    }
+
+    fn subscribe(&mut self, listener: Listener) {
+        self.listeners.push(listener);
+    }
+
+    fn victory(&self) -> bool {
+        self.board
+            .iter()
+            .all(|tile| !tile.is_covered() || tile.is_mine())
+    }
+}
+
+#[cfg(test)]
+mod tests {
     use super::*;
+    use crate::clock::ManualClock;
+    use std::sync::Mutex;
 
     #[test]
     fn test_init_std_game() {
         let h = 8;
         let w = 10;
         let game = StdMinrsGame::new(w, h, 10).unwrap();
-        assert_eq!(game.board.len(), w.into());
-        game.board.iter().for_each(|col| {
-            assert_eq!(col.len(), h.into());
-        });
+        assert_eq!(game.board.len(), w as usize * h as usize);
+        assert_eq!(game.get_depth(), 1);
     }
 
     #[test]
@@ -454,7 +1761,10 @@ mod tests {
         let game = StdMinrsGame::new(w, h, 0).unwrap();
         for x in 0..h {
             for y in 0..w {
-                assert_eq!(game.get_tile_state(&Position { x, y }).unwrap(), ts_covered);
+                assert_eq!(
+                    game.get_tile_state(&Position::new(x, y)).unwrap(),
+                    ts_covered
+                );
             }
         }
     }
@@ -468,7 +1778,7 @@ mod tests {
         let game = StdMinrsGame::new(w, h, mines).unwrap();
         for x in 0..h {
             for y in 0..w {
-                if game.get_tile(&Position { x, y }).unwrap().is_mine() {
+                if game.get_tile(&Position::new(x, y)).unwrap().is_mine() {
                     count += 1;
                 }
             }
@@ -480,7 +1790,7 @@ mod tests {
     fn test_tile_simple_uncover() {
         let h = 8;
         let w = 8;
-        let test_pos = Position { x: 3, y: 4 };
+        let test_pos = Position::new(3, 4);
         let ts_uncovered = TileState::Uncovered(TileContents::MineCount(0));
         let mut game = StdMinrsGame::new(w, h, 0).unwrap();
         game.mod_tile(&test_pos, |tile| tile.uncover())
@@ -491,21 +1801,813 @@ mod tests {
 
     #[test]
     fn test_tile_simple_uncover_mine_counting() {
-        let h = 8;
-        let w = 8;
-        let test_pos = Position { x: 3, y: 4 };
-        // following positions to all set as mines
-        let test_pos1 = Position { x: 4, y: 4 };
-        let test_pos2 = Position { x: 5, y: 4 };
-        let test_pos3 = Position { x: 2, y: 3 };
+        let test_pos = Position::new(3, 4);
         let ts_uncovered = TileState::Uncovered(TileContents::MineCount(2));
-        let mut game = StdMinrsGame::new(w, h, 0).unwrap();
-        game.mod_tile(&test_pos1, |tile| tile.mine = true).unwrap();
-        game.mod_tile(&test_pos2, |tile| tile.mine = true).unwrap();
-        game.mod_tile(&test_pos3, |tile| tile.mine = true).unwrap();
-        game.mod_tile(&test_pos, |tile| tile.uncover())
-            .unwrap()
-            .unwrap();
+        let game = StdMinrsGame::from_ascii(
+            "
+            ........
+            ........
+            ........
+            ..*.....
+            ..._**..
+            ........
+            ........
+            ........
+            ",
+        )
+        .unwrap();
         assert_eq!(game.get_tile_state(&test_pos).unwrap(), ts_uncovered);
     }
+
+    fn mine_layout(game: &StdMinrsGame) -> Vec<bool> {
+        game.board.iter().map(|tile| tile.is_mine()).collect()
+    }
+
+    #[test]
+    fn test_seeded_layout_is_deterministic() {
+        let first_click = Position::new(7, 2);
+        let mut game1 = StdMinrsGame::new_seeded(16, 16, 40, 42).unwrap();
+        let mut game2 = StdMinrsGame::new_seeded(16, 16, 40, 42).unwrap();
+        assert_eq!(game1.get_seed(), Some(42));
+        assert_eq!(mine_layout(&game1), mine_layout(&game2));
+
+        // the first click regeneration must be reproducible too
+        game1.uncover_tile(&first_click).unwrap();
+        game2.uncover_tile(&first_click).unwrap();
+        assert_eq!(mine_layout(&game1), mine_layout(&game2));
+        for x in 0..16 {
+            for y in 0..16 {
+                let pos = Position::new(x, y);
+                assert_eq!(
+                    game1.get_tile_state(&pos).unwrap(),
+                    game2.get_tile_state(&pos).unwrap()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_seeded_layout_differs_by_seed() {
+        let game1 = StdMinrsGame::new_seeded(16, 16, 40, 1).unwrap();
+        let game2 = StdMinrsGame::new_seeded(16, 16, 40, 2).unwrap();
+        assert_ne!(mine_layout(&game1), mine_layout(&game2));
+    }
+
+    #[test]
+    fn test_no_guess_board_is_solvable() {
+        let first_click = Position::new(15, 15);
+        for seed in 0..3 {
+            let mut game = StdMinrsGame::new_seeded(30, 30, 99, seed).unwrap();
+            game.set_no_guess(true).unwrap();
+            game.uncover_tile(&first_click).unwrap();
+            assert_eq!(
+                game.get_tile_state(&first_click).unwrap(),
+                TileState::Uncovered(TileContents::MineCount(0))
+            );
+            assert!(game.solvable_from(&first_click).unwrap());
+            assert!(game.is_no_guess());
+            assert!(!game.is_no_guess_failed());
+            assert!(game.set_no_guess(false).is_err());
+        }
+    }
+
+    #[test]
+    fn test_no_guess_gives_up() {
+        // too dense for any layout to be cleared by logic
+        let mut game = StdMinrsGame::new_seeded(8, 8, 40, 5).unwrap();
+        game.set_no_guess(true).unwrap();
+        game.uncover_tile(&Position::new(4, 4)).unwrap();
+        assert!(!game.is_no_guess());
+        assert!(game.is_no_guess_failed());
+        assert_eq!(
+            game.get_tile_state(&Position::new(4, 4)).unwrap(),
+            TileState::Uncovered(TileContents::MineCount(0))
+        );
+    }
+
+    fn board_states(game: &StdMinrsGame) -> Vec<TileState> {
+        game.get_positions()
+            .iter()
+            .map(|pos| game.get_tile_state(pos).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_undo_redo_uncover() {
+        let mut game = StdMinrsGame::new_seeded(16, 16, 40, 9).unwrap();
+        let fresh = board_states(&game);
+        game.uncover_tile(&Position::new(4, 4)).unwrap();
+        let opened = board_states(&game);
+
+        game.undo().unwrap();
+        assert_eq!(board_states(&game), fresh);
+        assert!(!game.started);
+        assert!(matches!(game.undo(), Err(MinrsError::NoHistory)));
+
+        game.redo().unwrap();
+        assert_eq!(board_states(&game), opened);
+        assert!(game.started);
+        assert!(matches!(game.redo(), Err(MinrsError::NoHistory)));
+    }
+
+    #[test]
+    fn test_undo_flag_and_new_move_clears_redo() {
+        let pos = Position::new(1, 1);
+        let mut game = StdMinrsGame::new_seeded(8, 8, 10, 9).unwrap();
+        game.cycle_flag(&pos).unwrap();
+        game.cycle_flag(&pos).unwrap();
+        assert_eq!(
+            game.get_tile_state(&pos).unwrap(),
+            TileState::Covered(Some(FlagState::Questionable))
+        );
+        game.undo().unwrap();
+        assert_eq!(
+            game.get_tile_state(&pos).unwrap(),
+            TileState::Covered(Some(FlagState::RedFlag(1)))
+        );
+
+        game.cycle_flag(&Position::new(2, 2)).unwrap();
+        assert!(matches!(game.redo(), Err(MinrsError::NoHistory)));
+    }
+
+    #[test]
+    fn test_undo_game_over() {
+        let mine = Position::new(0, 0);
+        let number = Position::new(1, 1);
+        let covered = [mine, Position::new(1, 0), Position::new(0, 1)];
+        let mut game = StdMinrsGame::with_layout(8, 8, &[mine], &covered);
+
+        // a failed move leaves nothing to undo
+        assert!(game.uncover_neighbors(&number).is_err());
+        assert!(matches!(game.undo(), Err(MinrsError::NoHistory)));
+
+        game.uncover_tile(&mine).unwrap();
+        assert!(game.game_over());
+        game.undo().unwrap();
+        assert!(!game.game_over());
+        assert_eq!(
+            game.get_tile_state(&mine).unwrap(),
+            TileState::Covered(None)
+        );
+
+        // chording is a single step, however many tiles it opens
+        game.cycle_flag(&mine).unwrap();
+        game.uncover_neighbors(&number).unwrap();
+        assert!(game.victory());
+        game.undo().unwrap();
+        assert!(!game.victory());
+        game.undo().unwrap();
+        assert_eq!(
+            game.get_tile_state(&mine).unwrap(),
+            TileState::Covered(None)
+        );
+    }
+
+    #[test]
+    fn test_from_mines_keeps_layout() {
+        let mine = Position::new(2, 3);
+        let mut game = StdMinrsGame::from_mines(8, 8, &[mine]).unwrap();
+        assert_eq!(game.get_seed(), None);
+        assert_eq!(game.get_mine_count(), 1);
+        assert!(game.set_no_guess(true).is_err());
+        // a fixed layout is never regenerated, even on the first move
+        game.uncover_tile(&mine).unwrap();
+        assert!(game.game_over());
+        assert_eq!(
+            game.get_tile_state(&mine).unwrap(),
+            TileState::Uncovered(TileContents::Mines(1))
+        );
+
+        assert!(StdMinrsGame::from_mines(8, 8, &[mine, mine]).is_err());
+        assert!(StdMinrsGame::from_mines(8, 8, &[Position::new(8, 0)]).is_err());
+    }
+
+    #[test]
+    fn test_save_load_round_trip() {
+        let mut game = StdMinrsGame::new_seeded(16, 12, 30, 5).unwrap();
+        game.uncover_tile(&Position::new(8, 6)).unwrap();
+        let mine = game.get_mine_positions()[0];
+        game.cycle_flag(&mine).unwrap();
+        game.cycle_flag(&Position::new(0, 0)).ok();
+        game.cycle_flag(&Position::new(0, 0)).ok();
+
+        let text = game.save();
+        assert!(text.starts_with("min-rs-weeper game 1\n"));
+        let loaded = StdMinrsGame::load(&text).unwrap();
+        assert_eq!(board_states(&loaded), board_states(&game));
+        assert_eq!(mine_layout(&loaded), mine_layout(&game));
+        assert_eq!(loaded.get_seed(), Some(5));
+        assert!(loaded.started);
+        assert!(!loaded.game_over());
+        assert_eq!(loaded.save(), text);
+    }
+
+    /// A game saved before the first move picks the same board on load.
+    #[test]
+    fn test_load_unstarted_game() {
+        let first_click = Position::new(3, 3);
+        let mut game = StdMinrsGame::new_seeded(10, 10, 20, 8).unwrap();
+        game.set_no_guess(true).unwrap();
+        let mut loaded = StdMinrsGame::load(&game.save()).unwrap();
+        game.uncover_tile(&first_click).unwrap();
+        loaded.uncover_tile(&first_click).unwrap();
+        assert_eq!(board_states(&loaded), board_states(&game));
+    }
+
+    #[test]
+    fn test_load_fixed_lost_game() {
+        let mine = Position::new(7, 0);
+        let mut game = StdMinrsGame::from_mines(8, 8, &[mine]).unwrap();
+        game.uncover_tile(&mine).unwrap();
+        let loaded = StdMinrsGame::load(&game.save()).unwrap();
+        assert_eq!(loaded.get_seed(), None);
+        assert!(loaded.game_over());
+        assert_eq!(
+            loaded.get_tile_state(&mine).unwrap(),
+            TileState::Uncovered(TileContents::Mines(1))
+        );
+    }
+
+    #[test]
+    fn test_load_bad_saves() {
+        let text = StdMinrsGame::new_seeded(8, 8, 10, 1).unwrap().save();
+        assert!(StdMinrsGame::load("").is_err());
+        assert!(StdMinrsGame::load(&text.replace("game 1", "game 2")).is_err());
+        assert!(StdMinrsGame::load(&text.replace("size 8 8 10", "size 8 8 11")).is_err());
+        assert!(StdMinrsGame::load(&text.replacen('.', "?", 1)).is_err());
+        let truncated: String = text.lines().take(8).collect::<Vec<_>>().join("\n");
+        assert!(StdMinrsGame::load(&truncated).is_err());
+    }
+
+    #[test]
+    fn test_from_ascii_scenario() {
+        let layout = "\
+            *F......
+            _f......
+            __q.....
+            ........
+            ........
+            ........
+            ........
+            .......*
+        ";
+        let mut game = StdMinrsGame::from_ascii(layout).unwrap();
+        assert_eq!(game.get_mine_count(), 3);
+        assert_eq!(game.get_seed(), None);
+        assert!(game.started);
+        assert!(!game.game_over());
+        assert_eq!(
+            game.get_tile_state(&Position::new(1, 1)).unwrap(),
+            TileState::Covered(Some(FlagState::RedFlag(1)))
+        );
+        assert_eq!(
+            game.get_tile_state(&Position::new(0, 1)).unwrap(),
+            TileState::Uncovered(TileContents::MineCount(2))
+        );
+        let expected: String = layout
+            .lines()
+            .map(str::trim)
+            .filter(|row| !row.is_empty())
+            .map(|row| row.to_string() + "\n")
+            .collect();
+        assert_eq!(game.to_ascii(), expected);
+
+        // the layout is kept, so the next uncover can still lose
+        game.uncover_tile(&Position::new(7, 7)).unwrap();
+        assert!(game.game_over());
+    }
+
+    #[test]
+    fn test_from_ascii_errors() {
+        let row = "........\n";
+        assert!(StdMinrsGame::from_ascii("").is_err());
+        assert!(StdMinrsGame::from_ascii(&row.repeat(7)).is_err());
+        assert!(StdMinrsGame::from_ascii(&(row.repeat(7) + ".......")).is_err());
+        assert!(StdMinrsGame::from_ascii(&(row.repeat(7) + ".......#")).is_err());
+        assert!(StdMinrsGame::from_ascii(&row.replace('.', "*").repeat(8)).is_err());
+
+        let lost = StdMinrsGame::from_ascii(&(row.repeat(7) + "X.......")).unwrap();
+        assert!(lost.game_over());
+    }
+
+    #[test]
+    fn test_hex_neighbors() {
+        let mut game = StdMinrsGame::new_seeded(8, 8, 10, 1).unwrap();
+        game.set_neighborhood(Neighborhood::Hex).unwrap();
+        let sorted_neighbors = |x, y| {
+            let mut neighbors = game.get_neighbors_pos(&Position::new(x, y)).unwrap();
+            neighbors.sort_by_key(|pos| (pos.y, pos.x));
+            neighbors
+                .into_iter()
+                .map(|pos| (pos.x, pos.y))
+                .collect::<Vec<_>>()
+        };
+        // even rows reach left into the rows above and below, odd rows right
+        assert_eq!(
+            sorted_neighbors(3, 2),
+            [(2, 1), (3, 1), (2, 2), (4, 2), (2, 3), (3, 3)]
+        );
+        assert_eq!(
+            sorted_neighbors(3, 3),
+            [(3, 2), (4, 2), (2, 3), (4, 3), (3, 4), (4, 4)]
+        );
+        assert_eq!(sorted_neighbors(0, 0), [(1, 0), (0, 1)]);
+        assert_eq!(sorted_neighbors(7, 1), [(7, 0), (6, 1), (7, 2)]);
+    }
+
+    #[test]
+    fn test_hex_counts_and_cascade() {
+        let mut game = StdMinrsGame::from_ascii(
+            "
+            ........
+            ........
+            ........
+            ...*....
+            ........
+            ........
+            ........
+            ........
+            ",
+        )
+        .unwrap();
+        game.set_neighborhood(Neighborhood::Hex).unwrap();
+        game.uncover_tile(&Position::new(0, 7)).unwrap();
+        // (2,4) is next to the mine on a square grid, but not on a hex grid
+        assert_eq!(
+            game.get_tile_state(&Position::new(2, 4)).unwrap(),
+            TileState::Uncovered(TileContents::MineCount(0))
+        );
+        assert_eq!(
+            game.get_tile_state(&Position::new(3, 4)).unwrap(),
+            TileState::Uncovered(TileContents::MineCount(1))
+        );
+        assert!(game.victory());
+        assert!(game.set_neighborhood(Neighborhood::Square).is_err());
+
+        let loaded = StdMinrsGame::load(&game.save()).unwrap();
+        assert_eq!(loaded.get_neighborhood(), &Neighborhood::Hex);
+        assert_eq!(board_states(&loaded), board_states(&game));
+    }
+
+    #[test]
+    fn test_wrap_neighbors() {
+        let mut game = StdMinrsGame::new_seeded(8, 10, 10, 1).unwrap();
+        game.set_wrap(true).unwrap();
+        for x in 0..8 {
+            for y in 0..10 {
+                let neighbors = game.get_neighbors_pos(&Position::new(x, y)).unwrap();
+                assert_eq!(neighbors.len(), 8);
+            }
+        }
+        let corner = game.get_neighbors_pos(&Position::new(0, 0)).unwrap();
+        assert!(corner.contains(&Position::new(7, 9)));
+        assert!(corner.contains(&Position::new(0, 9)));
+        assert!(corner.contains(&Position::new(7, 1)));
+
+        game.set_neighborhood(Neighborhood::Hex).unwrap();
+        let corner = game.get_neighbors_pos(&Position::new(0, 0)).unwrap();
+        assert_eq!(corner.len(), 6);
+        assert!(corner.contains(&Position::new(7, 9)));
+
+        // hex rows can only wrap onto a row with the other offset
+        let mut odd = StdMinrsGame::new_seeded(8, 9, 10, 1).unwrap();
+        odd.set_neighborhood(Neighborhood::Hex).unwrap();
+        assert!(odd.set_wrap(true).is_err());
+    }
+
+    #[test]
+    fn test_wrap_counts_and_cascade() {
+        let mut game = StdMinrsGame::from_ascii(
+            "
+            *.......
+            ........
+            ........
+            ........
+            ........
+            ........
+            ........
+            ........
+            ",
+        )
+        .unwrap();
+        game.set_wrap(true).unwrap();
+        game.uncover_tile(&Position::new(4, 4)).unwrap();
+        assert!(game.victory());
+        for pos in [(7, 7), (7, 0), (0, 7), (1, 1)] {
+            assert_eq!(
+                game.get_tile_state(&Position::new(pos.0, pos.1)).unwrap(),
+                TileState::Uncovered(TileContents::MineCount(1))
+            );
+        }
+        assert!(game.set_wrap(false).is_err());
+
+        let loaded = StdMinrsGame::load(&game.save()).unwrap();
+        assert!(loaded.is_wrapping());
+        assert_eq!(board_states(&loaded), board_states(&game));
+    }
+
+    /// The first uncover still opens a sea, even across the seams.
+    #[test]
+    fn test_wrap_first_click_opening() {
+        let corner = Position::new(0, 0);
+        for seed in 0..10 {
+            let mut game = StdMinrsGame::new_seeded(8, 8, 20, seed).unwrap();
+            game.set_wrap(true).unwrap();
+            game.uncover_tile(&corner).unwrap();
+            assert_eq!(
+                game.get_tile_state(&corner).unwrap(),
+                TileState::Uncovered(TileContents::MineCount(0))
+            );
+            let seam = game.get_tile_state(&Position::new(7, 7)).unwrap();
+            assert!(matches!(seam, TileState::Uncovered(_)));
+        }
+    }
+
+    #[test]
+    fn test_3d_neighbors() {
+        let game = StdMinrsGame::new_3d_seeded(8, 8, 3, 20, 1).unwrap();
+        assert_eq!(game.get_depth(), 3);
+        assert_eq!(game.get_positions().len(), 8 * 8 * 3);
+        let count = |x, y, z| {
+            game.get_neighbors_pos(&Position::new_3d(x, y, z))
+                .unwrap()
+                .len()
+        };
+        assert_eq!(count(4, 4, 1), 26);
+        assert_eq!(count(4, 4, 0), 17);
+        assert_eq!(count(0, 0, 0), 7);
+        assert!(game.get_neighbors_pos(&Position::new_3d(0, 0, 3)).is_err());
+
+        assert!(StdMinrsGame::new_3d(8, 8, 0, 10).is_err());
+        assert!(StdMinrsGame::new_3d(8, 8, 2, 128).is_err());
+    }
+
+    #[test]
+    fn test_3d_layers_from_ascii() {
+        let layout = "
+            ........
+            ........
+            ........
+            ........
+            ........
+            ........
+            ........
+            ........
+
+            ........
+            ........
+            ........
+            ...*....
+            ........
+            ........
+            ........
+            ........
+        ";
+        let mut game = StdMinrsGame::from_ascii(layout).unwrap();
+        assert_eq!(game.get_depth(), 2);
+        assert_eq!(game.get_mine_count(), 1);
+        // the mine counts on the layer above it
+        game.uncover_tile(&Position::new(0, 0)).unwrap();
+        assert_eq!(
+            game.get_tile_state(&Position::new(2, 2)).unwrap(),
+            TileState::Uncovered(TileContents::MineCount(1))
+        );
+        // and the cascade carried on into the layer below
+        assert_eq!(
+            game.get_tile_state(&Position::new_3d(0, 0, 1)).unwrap(),
+            TileState::Uncovered(TileContents::MineCount(0))
+        );
+        // only the tile right above the mine is walled in by numbers
+        assert!(!game.victory());
+        game.uncover_tile(&Position::new(3, 3)).unwrap();
+        assert!(game.victory());
+
+        let loaded = StdMinrsGame::load(&game.save()).unwrap();
+        assert_eq!(loaded.get_depth(), 2);
+        assert_eq!(board_states(&loaded), board_states(&game));
+        assert_eq!(
+            StdMinrsGame::from_ascii(&game.to_ascii())
+                .unwrap()
+                .to_ascii(),
+            game.to_ascii()
+        );
+    }
+
+    /// A 3D seeded game plays the same every time, and round trips through
+    /// a save before the first move.
+    #[test]
+    fn test_3d_seeded_save() {
+        let first_click = Position::new_3d(4, 4, 1);
+        let mut game = StdMinrsGame::new_3d_seeded(10, 8, 3, 15, 21).unwrap();
+        let mut loaded = StdMinrsGame::load(&game.save()).unwrap();
+        game.uncover_tile(&first_click).unwrap();
+        loaded.uncover_tile(&first_click).unwrap();
+        assert_eq!(
+            game.get_tile_state(&first_click).unwrap(),
+            TileState::Uncovered(TileContents::MineCount(0))
+        );
+        assert_eq!(board_states(&loaded), board_states(&game));
+    }
+
+    #[test]
+    fn test_neighborhood_shapes() {
+        let mut game = StdMinrsGame::new_seeded(10, 10, 10, 1).unwrap();
+        let center = Position::new(5, 5);
+        let mut count = |neighborhood| {
+            game.set_neighborhood(neighborhood).unwrap();
+            game.get_neighbors_pos(&center).unwrap().len()
+        };
+        assert_eq!(count(Neighborhood::Cross), 4);
+        assert_eq!(count(Neighborhood::Knight), 8);
+        assert_eq!(count(Neighborhood::Radius(2)), 24);
+        // duplicates and the tile itself are dropped
+        assert_eq!(
+            count(Neighborhood::Custom(vec![(0, 0), (3, 0), (3, 0), (-1, -4)])),
+            2
+        );
+
+        game.set_neighborhood(Neighborhood::Knight).unwrap();
+        let corner = game.get_neighbors_pos(&Position::new(0, 0)).unwrap();
+        assert_eq!(corner.len(), 2);
+        assert!(corner.contains(&Position::new(1, 2)));
+        assert!(corner.contains(&Position::new(2, 1)));
+
+        assert!(game.set_neighborhood(Neighborhood::Radius(8)).is_err());
+        let mut layered = StdMinrsGame::new_3d_seeded(8, 8, 3, 10, 1).unwrap();
+        assert!(layered.set_neighborhood(Neighborhood::Radius(4)).is_ok());
+        assert!(layered.set_neighborhood(Neighborhood::Radius(5)).is_err());
+
+        for neighborhood in [
+            Neighborhood::Square,
+            Neighborhood::Cross,
+            Neighborhood::Radius(3),
+            Neighborhood::Custom(vec![(1, 0), (-2, 3)]),
+            Neighborhood::Custom(Vec::new()),
+        ] {
+            assert_eq!(
+                neighborhood.to_string().parse::<Neighborhood>().unwrap(),
+                neighborhood
+            );
+        }
+        assert!("radius-x".parse::<Neighborhood>().is_err());
+        assert!("custom:1".parse::<Neighborhood>().is_err());
+    }
+
+    /// Numbers, cascades and chords all follow the cross shape.
+    #[test]
+    fn test_cross_neighborhood_play() {
+        let mut game = StdMinrsGame::from_ascii(
+            "
+            .*......
+            ........
+            ........
+            ........
+            ........
+            ........
+            ........
+            ........
+            ",
+        )
+        .unwrap();
+        game.set_neighborhood(Neighborhood::Cross).unwrap();
+        let mut chorded = game.clone();
+
+        game.uncover_tile(&Position::new(7, 7)).unwrap();
+        // the diagonals of the mine do not see it
+        assert_eq!(
+            game.get_tile_state(&Position::new(2, 1)).unwrap(),
+            TileState::Uncovered(TileContents::MineCount(0))
+        );
+        assert_eq!(
+            game.get_tile_state(&Position::new(1, 1)).unwrap(),
+            TileState::Uncovered(TileContents::MineCount(1))
+        );
+        assert!(game.victory());
+
+        // chording only needs the one flag in the cross
+        chorded.uncover_tile(&Position::new(1, 1)).unwrap();
+        assert!(chorded.uncover_neighbors(&Position::new(1, 1)).is_err());
+        chorded.cycle_flag(&Position::new(1, 0)).unwrap();
+        chorded.uncover_neighbors(&Position::new(1, 1)).unwrap();
+        assert!(chorded.victory());
+    }
+
+    #[test]
+    fn test_mines_per_tile_flags_and_counts() {
+        let mut game = StdMinrsGame::from_mines(8, 8, &[Position::new(0, 0)]).unwrap();
+        game.set_mines_per_tile(3).unwrap();
+        game.add_mine(&Position::new(0, 0)).unwrap();
+        game.add_mine(&Position::new(0, 0)).unwrap();
+        assert!(game.add_mine(&Position::new(0, 0)).is_err());
+        assert_eq!(game.get_mine_count(), 3);
+        // a tile can no longer be lowered below what it holds
+        assert!(game.set_mines_per_tile(2).is_err());
+
+        let pos = Position::new(0, 0);
+        let mut flags = Vec::new();
+        for _ in 0..5 {
+            game.cycle_flag(&pos).unwrap();
+            flags.push(game.get_tile_state(&pos).unwrap());
+        }
+        assert_eq!(
+            flags,
+            [
+                TileState::Covered(Some(FlagState::RedFlag(1))),
+                TileState::Covered(Some(FlagState::RedFlag(2))),
+                TileState::Covered(Some(FlagState::RedFlag(3))),
+                TileState::Covered(Some(FlagState::Questionable)),
+                TileState::Covered(None),
+            ]
+        );
+
+        game.uncover_tile(&Position::new(1, 1)).unwrap();
+        assert_eq!(
+            game.get_tile_state(&Position::new(1, 1)).unwrap(),
+            TileState::Uncovered(TileContents::MineCount(3))
+        );
+        // one flag is not enough for three mines
+        game.cycle_flag(&pos).unwrap();
+        assert!(game.uncover_neighbors(&Position::new(1, 1)).is_err());
+        game.cycle_flag(&pos).unwrap();
+        game.cycle_flag(&pos).unwrap();
+        game.uncover_neighbors(&Position::new(1, 1)).unwrap();
+        assert!(game.victory());
+    }
+
+    #[test]
+    fn test_mines_per_tile_generation() {
+        let mut game = StdMinrsGame::new_seeded(8, 8, 40, 5).unwrap();
+        game.set_mines_per_tile(3).unwrap();
+        let mines: Vec<u8> = game.board.iter().map(|tile| tile.get_mines()).collect();
+        assert_eq!(mines.iter().map(|m| *m as u16).sum::<u16>(), 40);
+        assert!(mines.iter().all(|m| *m <= 3));
+        assert!(mines.iter().any(|m| *m > 1));
+
+        let mut again = StdMinrsGame::new_seeded(8, 8, 40, 5).unwrap();
+        again.set_mines_per_tile(3).unwrap();
+        assert_eq!(again.get_mine_positions(), game.get_mine_positions());
+        assert!(again.set_no_guess(true).is_err());
+
+        // stacks and multiple flags survive a save
+        again.uncover_tile(&Position::new(4, 4)).unwrap();
+        let stacked = again
+            .get_positions()
+            .into_iter()
+            .find(|pos| again.get_tile(pos).unwrap().get_mines() > 1)
+            .unwrap();
+        again.cycle_flag(&stacked).unwrap();
+        again.cycle_flag(&stacked).unwrap();
+        let loaded = StdMinrsGame::load(&again.save()).unwrap();
+        assert_eq!(loaded.get_mines_per_tile(), 3);
+        assert_eq!(loaded.get_mine_positions(), again.get_mine_positions());
+        assert_eq!(
+            loaded.get_tile_state(&stacked).unwrap(),
+            TileState::Covered(Some(FlagState::RedFlag(2)))
+        );
+    }
+
+    /// Big boards need wide coordinates, more than u16 mines, and an opening
+    /// too large to recurse through.
+    #[test]
+    fn test_large_board() {
+        let game = StdMinrsGame::new_seeded(2000, 2000, 70_000, 3).unwrap();
+        assert_eq!(game.get_mine_count(), 70_000);
+        assert_eq!(game.get_mine_positions().len(), 70_000);
+        let corner = Position::new(1999, 1999);
+        assert_eq!(game.get_neighbors_pos(&corner).unwrap().len(), 3);
+
+        // a wall of mines keeps the opening to the first 100 columns
+        let wall: Vec<Position> = (0..2000).map(|y| Position::new(100, y)).collect();
+        let mut game = StdMinrsGame::from_mines(2000, 2000, &wall).unwrap();
+        game.uncover_tile(&Position::new(0, 0)).unwrap();
+        assert!(!game.game_over());
+        assert_eq!(
+            game.get_tile_state(&Position::new(99, 1999)).unwrap(),
+            TileState::Uncovered(TileContents::MineCount(2))
+        );
+        assert_eq!(
+            game.get_tile_state(&Position::new(101, 0)).unwrap(),
+            TileState::Covered(None)
+        );
+        game.undo().unwrap();
+        assert!(game.board.iter().all(|tile| tile.is_covered()));
+    }
+
+    #[test]
+    fn test_timer() {
+        let clock = ManualClock::new();
+        let mut game = StdMinrsGame::from_mines(8, 8, &[Position::new(0, 0)]).unwrap();
+        game.set_clock(Arc::new(clock.clone()));
+        // flags alone don't start the timer
+        game.cycle_flag(&Position::new(0, 0)).unwrap();
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(game.get_elapsed(), Duration::ZERO);
+
+        game.uncover_tile(&Position::new(0, 1)).unwrap();
+        clock.advance(Duration::from_millis(1500));
+        assert_eq!(game.get_elapsed(), Duration::from_millis(1500));
+        let saved = game.save();
+        assert!(saved.contains("\ntime 1500\n"));
+
+        // the timer stops once the game is won
+        game.uncover_tile(&Position::new(7, 7)).unwrap();
+        assert!(game.victory());
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(game.get_elapsed(), Duration::from_millis(1500));
+        // and picks up from there after an undo
+        game.undo().unwrap();
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(game.get_elapsed(), Duration::from_millis(2500));
+
+        // a loaded game waits for the next move
+        let mut loaded = StdMinrsGame::load(&saved).unwrap();
+        loaded.set_clock(Arc::new(clock.clone()));
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(loaded.get_elapsed(), Duration::from_millis(1500));
+        loaded.uncover_tile(&Position::new(1, 0)).unwrap();
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(loaded.get_elapsed(), Duration::from_millis(2500));
+    }
+
+    fn listen(game: &mut StdMinrsGame) -> Arc<Mutex<Vec<GameEvent>>> {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        game.subscribe(Box::new(move |event| sink.lock().unwrap().push(*event)));
+        events
+    }
+
+    fn take_events(events: &Arc<Mutex<Vec<GameEvent>>>) -> Vec<GameEvent> {
+        std::mem::take(&mut *events.lock().unwrap())
+    }
+
+    #[test]
+    fn test_events() {
+        let mine = Position::new(0, 0);
+        let mut game = StdMinrsGame::from_mines(8, 8, &[mine]).unwrap();
+        let events = listen(&mut game);
+
+        game.cycle_flag(&mine).unwrap();
+        assert_eq!(
+            take_events(&events),
+            [GameEvent::FlagChanged(mine, Some(FlagState::RedFlag(1)))]
+        );
+        // rejected moves report nothing
+        assert!(game.uncover_neighbors(&Position::new(4, 4)).is_err());
+        assert!(take_events(&events).is_empty());
+
+        let start = Position::new(7, 7);
+        game.uncover_tile(&start).unwrap();
+        let opened = take_events(&events);
+        assert_eq!(opened[0], GameEvent::CascadeStarted(start));
+        assert_eq!(
+            opened[1],
+            GameEvent::TileUncovered(start, TileContents::MineCount(0))
+        );
+        assert_eq!(
+            opened[opened.len() - 2..],
+            [GameEvent::CascadeFinished(start), GameEvent::GameWon]
+        );
+        let uncovered = |events: &[GameEvent]| {
+            events
+                .iter()
+                .filter(|event| matches!(event, GameEvent::TileUncovered(..)))
+                .count()
+        };
+        assert_eq!(uncovered(&opened), 63);
+
+        // undo and redo report every tile they put back
+        game.undo().unwrap();
+        let covered = take_events(&events);
+        assert_eq!(covered.len(), 63);
+        assert!(covered
+            .iter()
+            .all(|event| matches!(event, GameEvent::TileCovered(_))));
+        game.redo().unwrap();
+        let redone = take_events(&events);
+        assert_eq!(uncovered(&redone), 63);
+        assert_eq!(redone.last(), Some(&GameEvent::GameWon));
+
+        // scratch copies don't report to the original's listeners
+        let mut copy = game.clone();
+        copy.undo().unwrap();
+        assert!(take_events(&events).is_empty());
+    }
+
+    #[test]
+    fn test_events_game_lost() {
+        let mine = Position::new(0, 0);
+        let mut game = StdMinrsGame::from_mines(8, 8, &[mine]).unwrap();
+        let events = listen(&mut game);
+        let lost = [
+            GameEvent::TileUncovered(mine, TileContents::Mines(1)),
+            GameEvent::GameLost(mine),
+        ];
+
+        game.uncover_tile(&mine).unwrap();
+        assert_eq!(take_events(&events), lost);
+        game.undo().unwrap();
+        assert_eq!(take_events(&events), [GameEvent::TileCovered(mine)]);
+        game.redo().unwrap();
+        assert_eq!(take_events(&events), lost);
+    }
 }
@@ -0,0 +1,574 @@
+// Copyright 2022 nitepone <luna@night.horse>
+//
+// An endless board, generated a chunk at a time as the player explores it.
+
+use crate::error::{MinrsError, MinrsResult};
+use crate::game::{
+    FlagState, GameEvent, Listener, Listeners, MinrsGame, Position, TileContents, TileState,
+};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// Chunks are square, this many tiles a side.
+pub const CHUNK_SIZE: i64 = 16;
+const CHUNK_TILES: usize = (CHUNK_SIZE * CHUNK_SIZE) as usize;
+/// With fewer mines than this per chunk, a single opening could spread
+/// forever.
+pub const MIN_CHUNK_MINES: u16 = 40;
+/// About 20% mines, the density of a classic 30x16 expert board with 99.
+/// Hard here is sparser, at 11%, but that is below MIN_CHUNK_MINES.
+pub const DEFAULT_CHUNK_MINES: u16 = 52;
+
+/// A tile on the endless plane.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct PlanePos {
+    pub x: i64,
+    pub y: i64,
+}
+
+impl PlanePos {
+    pub fn new(x: i64, y: i64) -> PlanePos {
+        PlanePos { x, y }
+    }
+
+    /// Get the chunk holding this tile, and where it is kept in the chunk.
+    fn chunk_index(&self) -> ((i64, i64), usize) {
+        let chunk = (self.x.div_euclid(CHUNK_SIZE), self.y.div_euclid(CHUNK_SIZE));
+        let idx = self.x.rem_euclid(CHUNK_SIZE) * CHUNK_SIZE + self.y.rem_euclid(CHUNK_SIZE);
+        (chunk, idx as usize)
+    }
+
+    /// Get the 8 tiles around this one.
+    fn neighbors(&self) -> Vec<PlanePos> {
+        let mut neighbors = Vec::with_capacity(8);
+        for x_mod in -1..=1 {
+            for y_mod in -1..=1 {
+                if x_mod != 0 || y_mod != 0 {
+                    neighbors.push(PlanePos::new(self.x + x_mod, self.y + y_mod));
+                }
+            }
+        }
+        neighbors
+    }
+}
+
+#[derive(Clone)]
+struct PlaneTile {
+    covered: bool,
+    mine: bool,
+    flag: Option<FlagState>,
+}
+
+/// A game on an unbounded plane, with no way to win.
+///
+/// Every chunk's mines come from the game's seed and the chunk's place, and
+/// are only worked out once something in or next to the chunk is uncovered
+/// or flagged. The score is the number of safe tiles opened before a mine
+/// goes off.
+///
+/// As a `MinrsGame` this shows a movable window onto the plane, the view, with
+/// positions counted from its top left corner. Numbers still count mines
+/// outside the view, so the solver can't be trusted near its edges.
+/// Listeners are likewise only told about tiles in the view, a mine going
+/// off outside it included.
+#[derive(Clone)]
+pub struct InfiniteMinrsGame {
+    started: bool,
+    game_over: bool,
+    seed: u64,
+    chunk_mines: u16,
+    chunks: HashMap<(i64, i64), Vec<PlaneTile>>,
+    /// Chunks worked out only to be looked at, like when counting the mines
+    /// in the view, kept so they aren't worked out again. They move to
+    /// `chunks` once touched.
+    untouched: RefCell<HashMap<(i64, i64), Vec<PlaneTile>>>,
+    score: u64,
+    view_origin: PlanePos,
+    view_width: u16,
+    view_height: u16,
+    listeners: Listeners,
+}
+
+impl InfiniteMinrsGame {
+    pub fn new(view_width: u16, view_height: u16) -> MinrsResult<InfiniteMinrsGame> {
+        InfiniteMinrsGame::new_seeded(view_width, view_height, rand::thread_rng().gen())
+    }
+
+    /// Create a game whose whole plane is determined by `seed`, and the tile
+    /// first uncovered.
+    pub fn new_seeded(
+        view_width: u16,
+        view_height: u16,
+        seed: u64,
+    ) -> MinrsResult<InfiniteMinrsGame> {
+        if view_width == 0 || view_height == 0 {
+            return Err(MinrsError::InvalidArgument);
+        }
+        Ok(InfiniteMinrsGame {
+            started: false,
+            game_over: false,
+            seed,
+            chunk_mines: DEFAULT_CHUNK_MINES,
+            chunks: HashMap::new(),
+            untouched: RefCell::new(HashMap::new()),
+            score: 0,
+            view_origin: PlanePos::new(0, 0),
+            view_width,
+            view_height,
+            listeners: Listeners::default(),
+        })
+    }
+
+    pub fn get_seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn get_chunk_mines(&self) -> u16 {
+        self.chunk_mines
+    }
+
+    /// Set how many mines each chunk holds, before the first move.
+    ///
+    /// throws InvalidArgument if the game has started, or the count is below
+    ///        MIN_CHUNK_MINES or more than a chunk can hold.
+    pub fn set_chunk_mines(&mut self, chunk_mines: u16) -> MinrsResult<()> {
+        if self.started || chunk_mines < MIN_CHUNK_MINES || chunk_mines as usize > CHUNK_TILES {
+            return Err(MinrsError::InvalidArgument);
+        }
+        self.chunk_mines = chunk_mines;
+        self.chunks.clear();
+        self.untouched.get_mut().clear();
+        Ok(())
+    }
+
+    /// Get how many safe tiles have been uncovered.
+    pub fn get_score(&self) -> u64 {
+        self.score
+    }
+
+    /// Get the plane position shown at the top left of the view.
+    pub fn get_view_origin(&self) -> PlanePos {
+        self.view_origin
+    }
+
+    pub fn set_view_origin(&mut self, origin: PlanePos) {
+        self.view_origin = origin;
+    }
+
+    /// Move the view by the given number of tiles.
+    pub fn pan(&mut self, x: i64, y: i64) {
+        self.view_origin = PlanePos::new(self.view_origin.x + x, self.view_origin.y + y);
+    }
+
+    /// Get the plane position shown at a view position.
+    ///
+    /// throws OobPosition if the position is outside the view.
+    pub fn to_plane(&self, pos: &Position) -> MinrsResult<PlanePos> {
+        if pos.x >= self.view_width || pos.y >= self.view_height || pos.z != 0 {
+            return Err(MinrsError::OobPosition);
+        }
+        Ok(PlanePos::new(
+            self.view_origin.x + pos.x as i64,
+            self.view_origin.y + pos.y as i64,
+        ))
+    }
+
+    /// Get the view position showing a plane position, if it is in view.
+    pub fn to_view(&self, pos: &PlanePos) -> Option<Position> {
+        let x = u16::try_from(pos.x - self.view_origin.x).ok()?;
+        let y = u16::try_from(pos.y - self.view_origin.y).ok()?;
+        if x >= self.view_width || y >= self.view_height {
+            return None;
+        }
+        Some(Position::new(x, y))
+    }
+
+    /// Get what the player can see of a tile anywhere on the plane.
+    pub fn get_plane_state(&self, pos: &PlanePos) -> TileState {
+        let (chunk, idx) = pos.chunk_index();
+        let tile = match self.chunks.get(&chunk) {
+            Some(tiles) => &tiles[idx],
+            // nothing here has been touched yet
+            None => return TileState::Covered(None),
+        };
+        if tile.covered {
+            return TileState::Covered(tile.flag);
+        }
+        if tile.mine {
+            return TileState::Uncovered(TileContents::Mines(1));
+        }
+        TileState::Uncovered(TileContents::MineCount(self.count_mines(pos)))
+    }
+
+    /// Uncover a tile anywhere on the plane, cascading through empty tiles.
+    ///
+    /// The first uncover never hits a mine, and always opens an empty tile.
+    ///
+    /// throws GameOver once a mine has gone off.
+    /// throws InvalidArgument if the tile is already uncovered.
+    pub fn uncover_plane(&mut self, pos: &PlanePos) -> MinrsResult<()> {
+        if self.game_over {
+            return Err(MinrsError::GameOver);
+        }
+        self.generate_around(pos);
+        if !self.started {
+            // clear the mines around the first uncover
+            self.started = true;
+            for n_pos in pos.neighbors().iter().chain([pos]) {
+                self.get_tile_mut(n_pos).mine = false;
+            }
+        }
+        if !self.get_tile_mut(pos).covered {
+            return Err(MinrsError::InvalidArgument);
+        }
+
+        let start = *pos;
+        let mut cascading = false;
+        let mut pending = vec![start];
+        while let Some(pos) = pending.pop() {
+            self.generate_around(&pos);
+            let tile = self.get_tile_mut(&pos);
+            if !tile.covered {
+                continue;
+            }
+            tile.covered = false;
+            if tile.mine {
+                self.game_over = true;
+                self.emit_at(&pos, |pos| {
+                    GameEvent::TileUncovered(pos, TileContents::Mines(1))
+                });
+                self.emit_at(&pos, GameEvent::GameLost);
+                return Ok(());
+            }
+            self.score += 1;
+            let count = self.count_mines(&pos);
+            if count == 0 && !cascading {
+                cascading = true;
+                self.emit_at(&start, GameEvent::CascadeStarted);
+            }
+            self.emit_at(&pos, |pos| {
+                GameEvent::TileUncovered(pos, TileContents::MineCount(count))
+            });
+            if count == 0 {
+                pending.extend(pos.neighbors());
+            }
+        }
+        if cascading {
+            self.emit_at(&start, GameEvent::CascadeFinished);
+        }
+        Ok(())
+    }
+
+    /// Uncover the unflagged tiles around a number with enough flags.
+    ///
+    /// throws GameOver once a mine has gone off.
+    /// throws InvalidArgument if the tile is not a number, or is missing
+    ///        flags.
+    pub fn chord_plane(&mut self, pos: &PlanePos) -> MinrsResult<()> {
+        if self.game_over {
+            return Err(MinrsError::GameOver);
+        }
+        let mine_count = match self.get_plane_state(pos) {
+            TileState::Uncovered(TileContents::MineCount(count)) => count,
+            _ => return Err(MinrsError::InvalidArgument),
+        };
+        let neighbors = pos.neighbors();
+        let flag_count = neighbors
+            .iter()
+            .filter(|n_pos| matches!(self.get_plane_state(n_pos), TileState::Covered(Some(_))))
+            .count();
+        if flag_count < mine_count as usize {
+            return Err(MinrsError::InvalidArgument);
+        }
+        for n_pos in neighbors {
+            if self.game_over {
+                break;
+            }
+            if self.get_plane_state(&n_pos) == TileState::Covered(None) {
+                self.uncover_plane(&n_pos)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Cycle a covered tile through a flag, a question mark and no flag.
+    ///
+    /// throws GameOver once a mine has gone off.
+    /// throws InvalidPosition if the tile is uncovered.
+    pub fn cycle_plane_flag(&mut self, pos: &PlanePos) -> MinrsResult<()> {
+        if self.game_over {
+            return Err(MinrsError::GameOver);
+        }
+        self.generate_around(pos);
+        let tile = self.get_tile_mut(pos);
+        if !tile.covered {
+            return Err(MinrsError::InvalidPosition);
+        }
+        tile.flag = match tile.flag {
+            None => Some(FlagState::RedFlag(1)),
+            Some(FlagState::RedFlag(_)) => Some(FlagState::Questionable),
+            Some(FlagState::Questionable) => None,
+        };
+        let flag = tile.flag;
+        self.emit_at(pos, |pos| GameEvent::FlagChanged(pos, flag));
+        Ok(())
+    }
+
+    /// Tell the listeners about a tile, if it is in the view.
+    fn emit_at<F>(&mut self, pos: &PlanePos, event: F)
+    where
+        F: FnOnce(Position) -> GameEvent,
+    {
+        if let Some(pos) = self.to_view(pos) {
+            self.listeners.emit(event(pos));
+        }
+    }
+
+    /// Count the mines around a tile whose neighbors have been generated.
+    fn count_mines(&self, pos: &PlanePos) -> u8 {
+        pos.neighbors()
+            .iter()
+            .filter(|n_pos| {
+                let (chunk, idx) = n_pos.chunk_index();
+                self.chunks.get(&chunk).is_some_and(|tiles| tiles[idx].mine)
+            })
+            .count() as u8
+    }
+
+    /// Make sure the chunks holding a tile and its neighbors exist.
+    fn generate_around(&mut self, pos: &PlanePos) {
+        for n_pos in pos.neighbors().iter().chain([pos]) {
+            let (chunk, _) = n_pos.chunk_index();
+            if !self.chunks.contains_key(&chunk) {
+                let tiles = match self.untouched.get_mut().remove(&chunk) {
+                    Some(tiles) => tiles,
+                    None => self.generate_chunk(chunk),
+                };
+                self.chunks.insert(chunk, tiles);
+            }
+        }
+    }
+
+    /// Work out the mines of a chunk from the seed and where the chunk is.
+    fn generate_chunk(&self, (x, y): (i64, i64)) -> Vec<PlaneTile> {
+        // mix the chunk into the seed, so neighboring chunks look unrelated
+        let chunk_seed = self.seed
+            ^ (x as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+            ^ (y as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f);
+        let mut rng = StdRng::seed_from_u64(chunk_seed);
+        let mut tiles = vec![
+            PlaneTile {
+                covered: true,
+                mine: false,
+                flag: None,
+            };
+            CHUNK_TILES
+        ];
+        for idx in rand::seq::index::sample(&mut rng, CHUNK_TILES, self.chunk_mines as usize) {
+            tiles[idx].mine = true;
+        }
+        tiles
+    }
+
+    /// Get a tile, whose chunk must have been generated.
+    fn get_tile_mut(&mut self, pos: &PlanePos) -> &mut PlaneTile {
+        let (chunk, idx) = pos.chunk_index();
+        &mut self.chunks.get_mut(&chunk).unwrap()[idx]
+    }
+}
+
+impl MinrsGame for InfiniteMinrsGame {
+    fn game_over(&self) -> bool {
+        self.game_over
+    }
+
+    fn cycle_flag(&mut self, position: &Position) -> MinrsResult<()> {
+        let pos = self.to_plane(position)?;
+        self.cycle_plane_flag(&pos)
+    }
+
+    fn uncover_tile(&mut self, position: &Position) -> MinrsResult<()> {
+        let pos = self.to_plane(position)?;
+        self.uncover_plane(&pos)
+    }
+
+    fn uncover_neighbors(&mut self, position: &Position) -> MinrsResult<()> {
+        let pos = self.to_plane(position)?;
+        self.chord_plane(&pos)
+    }
+
+    fn get_tile_state(&self, position: &Position) -> MinrsResult<TileState> {
+        Ok(self.get_plane_state(&self.to_plane(position)?))
+    }
+
+    /// Only the neighbors inside the view.
+    fn get_neighbors_pos(&self, position: &Position) -> MinrsResult<Vec<Position>> {
+        let pos = self.to_plane(position)?;
+        Ok(pos
+            .neighbors()
+            .iter()
+            .filter_map(|n_pos| self.to_view(n_pos))
+            .collect())
+    }
+
+    fn get_width(&self) -> u16 {
+        self.view_width
+    }
+
+    fn get_height(&self) -> u16 {
+        self.view_height
+    }
+
+    fn get_depth(&self) -> u16 {
+        1
+    }
+
+    /// Get the number of mines in the view.
+    fn get_mine_count(&self) -> u32 {
+        let mut mines = 0;
+        for x in 0..self.view_width as i64 {
+            for y in 0..self.view_height as i64 {
+                let pos = PlanePos::new(self.view_origin.x + x, self.view_origin.y + y);
+                let (chunk, idx) = pos.chunk_index();
+                let mine = match self.chunks.get(&chunk) {
+                    Some(tiles) => tiles[idx].mine,
+                    None => {
+                        self.untouched
+                            .borrow_mut()
+                            .entry(chunk)
+                            .or_insert_with(|| self.generate_chunk(chunk))[idx]
+                            .mine
+                    }
+                };
+                mines += mine as u32;
+            }
+        }
+        mines
+    }
+
+    fn get_mines_per_tile(&self) -> u8 {
+        1
+    }
+
+    /// There is always more board to clear.
+    fn victory(&self) -> bool {
+        false
+    }
+
+    fn undo(&mut self) -> MinrsResult<()> {
+        Err(MinrsError::NoHistory)
+    }
+
+    fn redo(&mut self) -> MinrsResult<()> {
+        Err(MinrsError::NoHistory)
+    }
+
+    fn subscribe(&mut self, listener: Listener) {
+        self.listeners.push(listener);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plane_states(game: &InfiniteMinrsGame, from: i64, to: i64) -> Vec<TileState> {
+        let mut states = Vec::new();
+        for x in from..to {
+            for y in from..to {
+                states.push(game.get_plane_state(&PlanePos::new(x, y)));
+            }
+        }
+        states
+    }
+
+    #[test]
+    fn test_infinite_first_uncover_opens() {
+        for seed in 0..10 {
+            let mut game = InfiniteMinrsGame::new_seeded(20, 20, seed).unwrap();
+            game.uncover_plane(&PlanePos::new(-5, 7)).unwrap();
+            assert!(!game.game_over());
+            assert_eq!(
+                game.get_plane_state(&PlanePos::new(-5, 7)),
+                TileState::Uncovered(TileContents::MineCount(0))
+            );
+            assert!(game.get_score() >= 9);
+        }
+    }
+
+    /// The plane only depends on the seed and the first uncover, not on the
+    /// order chunks were reached in.
+    #[test]
+    fn test_infinite_chunks_are_deterministic() {
+        let mut a = InfiniteMinrsGame::new_seeded(10, 10, 42).unwrap();
+        let mut b = InfiniteMinrsGame::new_seeded(10, 10, 42).unwrap();
+        a.uncover_plane(&PlanePos::new(0, 0)).unwrap();
+        b.uncover_plane(&PlanePos::new(0, 0)).unwrap();
+        b.cycle_plane_flag(&PlanePos::new(-100, 300)).unwrap();
+        assert_eq!(plane_states(&a, -40, 40), plane_states(&b, -40, 40));
+        assert_eq!(a.get_score(), b.get_score());
+
+        let far = PlanePos::new(1000, -1000);
+        a.cycle_plane_flag(&far).unwrap();
+        b.cycle_plane_flag(&far).unwrap();
+        assert_eq!(a.get_mine_count(), b.get_mine_count());
+        let mut c = InfiniteMinrsGame::new_seeded(10, 10, 43).unwrap();
+        c.uncover_plane(&PlanePos::new(0, 0)).unwrap();
+        assert_ne!(plane_states(&a, -40, 40), plane_states(&c, -40, 40));
+    }
+
+    #[test]
+    fn test_infinite_view_and_game_over() {
+        let mut game = InfiniteMinrsGame::new_seeded(8, 8, 7).unwrap();
+        game.pan(-4, -4);
+        // the view's middle is the plane's origin
+        game.uncover_tile(&Position::new(4, 4)).unwrap();
+        assert_eq!(
+            game.get_tile_state(&Position::new(4, 4)).unwrap(),
+            game.get_plane_state(&PlanePos::new(0, 0))
+        );
+        assert!(game.get_tile_state(&Position::new(8, 0)).is_err());
+        assert_eq!(
+            game.get_neighbors_pos(&Position::new(0, 0)).unwrap().len(),
+            3
+        );
+        assert!(matches!(game.undo(), Err(MinrsError::NoHistory)));
+
+        // walk right until a mine goes off
+        let mut x = 0;
+        let score = loop {
+            x += 1;
+            let pos = PlanePos::new(x, 0);
+            if let TileState::Covered(_) = game.get_plane_state(&pos) {
+                let before = game.get_score();
+                game.uncover_plane(&pos).unwrap();
+                if game.game_over() {
+                    break before;
+                }
+            }
+        };
+        assert_eq!(game.get_score(), score);
+        assert!(!game.victory());
+        assert!(matches!(
+            game.uncover_plane(&PlanePos::new(0, 50)),
+            Err(MinrsError::GameOver)
+        ));
+    }
+
+    #[test]
+    fn test_infinite_chunk_mines() {
+        let mut game = InfiniteMinrsGame::new_seeded(16, 16, 1).unwrap();
+        assert!(game.set_chunk_mines(MIN_CHUNK_MINES - 1).is_err());
+        game.set_chunk_mines(60).unwrap();
+        // the view is exactly the chunk at the origin, worked out just once
+        assert_eq!(game.get_mine_count(), 60);
+        assert_eq!(game.get_mine_count(), 60);
+        assert_eq!(game.untouched.borrow().len(), 1);
+        assert!(game.chunks.is_empty());
+        game.uncover_plane(&PlanePos::new(3, 3)).unwrap();
+        assert!(!game.untouched.borrow().contains_key(&(0, 0)));
+        assert!(game.set_chunk_mines(50).is_err());
+    }
+}
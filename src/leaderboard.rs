@@ -0,0 +1,368 @@
+// Copyright 2022 nitepone <luna@night.horse>
+//
+// The best times for each kind of board.
+//
+// A leaderboard is plain text, with a line naming each board followed by a
+// line for each of its records, fastest first:
+//
+//     min-rs-weeper leaderboard 1
+//     board <name>
+//     <millis> <3bv> <seed|-> <yyyy-mm-dd> <player name>
+//
+// Board names come from `board_name`, and player names may contain spaces.
+
+use crate::difficulty::GameDifficulty;
+use crate::error::{MinrsError, MinrsResult};
+use crate::game::{parse_word, MinrsGame, Neighborhood, StdMinrsGame};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const LEADERBOARD_MAGIC: &str = "min-rs-weeper leaderboard";
+const LEADERBOARD_VERSION: u32 = 1;
+/// Records kept for each board.
+pub const MAX_RECORDS: usize = 10;
+
+/// One won game.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Record {
+    pub name: String,
+    pub time: Duration,
+    pub bbbv: u32,
+    /// The seed of a generated board.
+    pub seed: Option<u64>,
+    /// When it was won, as `yyyy-mm-dd`.
+    pub date: String,
+}
+
+/// The fastest games on each kind of board.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Leaderboard {
+    boards: BTreeMap<String, Vec<Record>>,
+}
+
+/// Decides which wins make the leaderboard: each game only once, and none
+/// that had help.
+///
+/// Help is a hint, taking back a move that hit a mine, or starting from a
+/// replay.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct WinTracker {
+    eligible: bool,
+    recorded: bool,
+}
+
+impl Default for WinTracker {
+    fn default() -> WinTracker {
+        WinTracker {
+            eligible: true,
+            recorded: false,
+        }
+    }
+}
+
+impl WinTracker {
+    /// Track a new game, which is eligible until it gets help.
+    pub fn new() -> WinTracker {
+        WinTracker::default()
+    }
+
+    /// Keep the current game off the leaderboard.
+    pub fn disqualify(&mut self) {
+        self.eligible = false;
+    }
+
+    /// Note that a move of the game is about to be taken back.
+    pub fn undoing(&mut self, game: &StdMinrsGame) {
+        if game.game_over() {
+            self.disqualify();
+        }
+    }
+
+    /// Check if the game was just won in a way that should be recorded,
+    /// which is only true the first time.
+    pub fn take_win(&mut self, game: &StdMinrsGame) -> bool {
+        if !game.victory() || game.game_over() || !self.eligible || self.recorded {
+            return false;
+        }
+        self.recorded = true;
+        true
+    }
+}
+
+/// Name the kind of board a game is on, so only games on the same kind
+/// compete.
+///
+/// Presets are named like `easy`, and anything else by its size, mines and
+/// options, like `20x20 60 hex wrap`.
+pub fn board_name(game: &StdMinrsGame) -> String {
+    let mut name = [
+        GameDifficulty::Easy,
+        GameDifficulty::Medium,
+        GameDifficulty::Hard,
+    ]
+    .iter()
+    .find(|diff| {
+        diff.get_width() == game.get_width()
+            && diff.get_height() == game.get_height()
+            && diff.get_mines() == game.get_mine_count()
+            && game.get_depth() == 1
+    })
+    .map_or_else(
+        || {
+            let mut size = format!("{}x{}", game.get_width(), game.get_height());
+            if game.get_depth() > 1 {
+                size += &format!("x{}", game.get_depth());
+            }
+            format!("{size} {}", game.get_mine_count())
+        },
+        |diff| diff.get_name().to_string(),
+    );
+    if *game.get_neighborhood() != Neighborhood::Square {
+        name += &format!(" {}", game.get_neighborhood());
+    }
+    if game.is_wrapping() {
+        name += " wrap";
+    }
+    if game.get_mines_per_tile() > 1 {
+        name += &format!(" stacks={}", game.get_mines_per_tile());
+    }
+    if game.is_no_guess() {
+        name += " no_guess";
+    }
+    name
+}
+
+/// Write a day as `yyyy-mm-dd`.
+pub fn format_date(time: SystemTime) -> String {
+    let days = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs() / 86400) as i64;
+    // from days since 1970-01-01 to the civil calendar, counting in 400 year
+    // eras that start on march 1st so leap days fall at the end
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+impl Leaderboard {
+    pub fn new() -> Leaderboard {
+        Leaderboard::default()
+    }
+
+    /// Add a record to a board's list, returning its place from 0 for the
+    /// new best. Returns None if it was too slow to be kept.
+    pub fn add(&mut self, board: &str, record: Record) -> Option<usize> {
+        let records = self.boards.entry(board.to_string()).or_default();
+        // a tie goes to whoever got there first
+        let place = records.partition_point(|other| other.time <= record.time);
+        if place >= MAX_RECORDS {
+            return None;
+        }
+        records.insert(place, record);
+        records.truncate(MAX_RECORDS);
+        Some(place)
+    }
+
+    /// Get a board's records, fastest first.
+    pub fn get_records(&self, board: &str) -> &[Record] {
+        self.boards.get(board).map_or(&[], Vec::as_slice)
+    }
+
+    /// Get every board with records, by name.
+    pub fn get_boards(&self) -> impl Iterator<Item = (&str, &[Record])> {
+        self.boards
+            .iter()
+            .map(|(board, records)| (board.as_str(), records.as_slice()))
+    }
+}
+
+impl fmt::Display for Leaderboard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{LEADERBOARD_MAGIC} {LEADERBOARD_VERSION}")?;
+        for (board, records) in &self.boards {
+            writeln!(f, "board {board}")?;
+            for record in records {
+                let seed = record.seed.map_or("-".to_string(), |seed| seed.to_string());
+                writeln!(
+                    f,
+                    "{} {} {seed} {} {}",
+                    record.time.as_millis(),
+                    record.bbbv,
+                    record.date,
+                    record.name
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Leaderboard {
+    type Err = MinrsError;
+
+    fn from_str(text: &str) -> MinrsResult<Leaderboard> {
+        let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+        let header = lines.next().ok_or(MinrsError::InvalidData)?;
+        let version = header
+            .strip_prefix(LEADERBOARD_MAGIC)
+            .ok_or(MinrsError::InvalidData)?;
+        if parse_word::<u32>(Some(version.trim()))? != LEADERBOARD_VERSION {
+            return Err(MinrsError::InvalidData);
+        }
+
+        let mut leaderboard = Leaderboard::new();
+        let mut board = None;
+        for line in lines {
+            if let Some(name) = line.strip_prefix("board ") {
+                board = Some(
+                    leaderboard
+                        .boards
+                        .entry(name.trim().to_string())
+                        .or_default(),
+                );
+                continue;
+            }
+            let records = board.as_mut().ok_or(MinrsError::InvalidData)?;
+            let mut words = line.splitn(5, ' ');
+            let time = Duration::from_millis(parse_word(words.next())?);
+            let bbbv = parse_word(words.next())?;
+            let seed = match words.next() {
+                Some("-") => None,
+                word => Some(parse_word(word)?),
+            };
+            let date = parse_word(words.next())?;
+            let name = words.next().ok_or(MinrsError::InvalidData)?.to_string();
+            records.push(Record {
+                name,
+                time,
+                bbbv,
+                seed,
+                date,
+            });
+        }
+        for records in leaderboard.boards.values_mut() {
+            records.sort_by_key(|record| record.time);
+            records.truncate(MAX_RECORDS);
+        }
+        Ok(leaderboard)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Position;
+
+    fn record(name: &str, millis: u64) -> Record {
+        Record {
+            name: name.to_string(),
+            time: Duration::from_millis(millis),
+            bbbv: 30,
+            seed: Some(7),
+            date: "2022-06-01".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_board_name() {
+        let mut game = GameDifficulty::Hard.new_game().unwrap();
+        assert_eq!(board_name(&game), "hard");
+        game.set_wrap(true).unwrap();
+        game.set_no_guess(true).unwrap();
+        assert_eq!(board_name(&game), "hard wrap no_guess");
+
+        let mut game = StdMinrsGame::new_3d(20, 20, 2, 60).unwrap();
+        game.set_neighborhood(Neighborhood::Hex).unwrap();
+        assert_eq!(board_name(&game), "20x20x2 60 hex");
+    }
+
+    #[test]
+    fn test_format_date() {
+        assert_eq!(format_date(UNIX_EPOCH), "1970-01-01");
+        let leap_day = UNIX_EPOCH + Duration::from_secs(951782400);
+        assert_eq!(format_date(leap_day), "2000-02-29");
+        let later = UNIX_EPOCH + Duration::from_secs(1654041600 + 86399);
+        assert_eq!(format_date(later), "2022-06-01");
+    }
+
+    #[test]
+    fn test_leaderboard() {
+        let mut leaderboard = Leaderboard::new();
+        assert_eq!(leaderboard.add("easy", record("luna", 9000)), Some(0));
+        assert_eq!(leaderboard.add("easy", record("sol", 12000)), Some(1));
+        // ties keep the older record ahead
+        assert_eq!(leaderboard.add("easy", record("a b c", 9000)), Some(1));
+        for slow in 0..MAX_RECORDS as u64 {
+            leaderboard.add("easy", record("slow", 20000 + slow));
+        }
+        assert_eq!(leaderboard.add("easy", record("slowest", 99000)), None);
+        assert_eq!(leaderboard.get_records("easy").len(), MAX_RECORDS);
+        assert_eq!(leaderboard.get_records("medium"), &[]);
+
+        let mut custom = record("luna", 60000);
+        custom.seed = None;
+        leaderboard.add("20x20 60 hex wrap", custom);
+        let text = leaderboard.to_string();
+        let parsed: Leaderboard = text.parse().unwrap();
+        assert_eq!(parsed, leaderboard);
+        let boards: Vec<&str> = parsed.get_boards().map(|(board, _)| board).collect();
+        assert_eq!(boards, ["20x20 60 hex wrap", "easy"]);
+        assert_eq!(parsed.get_records("easy")[1].name, "a b c");
+
+        assert!("min-rs-weeper leaderboard 2\n"
+            .parse::<Leaderboard>()
+            .is_err());
+        assert!("min-rs-weeper leaderboard 1\n9000 30 - 2022-06-01 luna\n"
+            .parse::<Leaderboard>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_win_tracker() {
+        let mut game = StdMinrsGame::from_mines(8, 8, &[Position::new(0, 0)]).unwrap();
+        let mut tracker = WinTracker::new();
+        let mut leaderboard = Leaderboard::new();
+        game.uncover_tile(&Position::new(4, 4)).unwrap();
+        assert!(game.victory());
+        if tracker.take_win(&game) {
+            leaderboard.add("8x8 1", record("luna", 9000));
+        }
+        // taking the winning move back and playing it again is the same win
+        tracker.undoing(&game);
+        game.undo().unwrap();
+        assert!(!tracker.take_win(&game));
+        game.redo().unwrap();
+        if tracker.take_win(&game) {
+            leaderboard.add("8x8 1", record("luna", 8000));
+        }
+        assert_eq!(leaderboard.get_records("8x8 1").len(), 1);
+
+        // taking back a mine means the win doesn't count
+        let mut game = StdMinrsGame::from_mines(8, 8, &[Position::new(0, 0)]).unwrap();
+        let mut tracker = WinTracker::new();
+        game.uncover_tile(&Position::new(0, 0)).unwrap();
+        tracker.undoing(&game);
+        game.undo().unwrap();
+        game.uncover_tile(&Position::new(4, 4)).unwrap();
+        assert!(game.victory());
+        assert!(!tracker.take_win(&game));
+
+        let mut tracker = WinTracker::new();
+        tracker.disqualify();
+        assert!(!tracker.take_win(&game));
+    }
+}
@@ -0,0 +1,21 @@
+// Copyright 2022 nitepone <luna@night.horse>
+//
+// The game itself, with no frontend attached.
+
+pub mod analysis;
+pub mod bench;
+pub mod clock;
+pub mod coop;
+pub mod difficulty;
+pub mod error;
+pub mod game;
+pub mod infinite;
+pub mod leaderboard;
+pub mod probability;
+pub mod protocol;
+pub mod race;
+pub mod replay;
+pub mod solver;
+
+pub use crate::error::{MinrsError, MinrsResult};
+pub use crate::game::{GameEvent, MinrsGame, Position, StdMinrsGame, TileContents, TileState};
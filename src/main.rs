@@ -3,62 +3,122 @@
 // Mostly gui code in here.. Mostly not cute..
 
 extern crate gtk;
-extern crate rand;
 
-pub mod error;
-mod game;
-
-use crate::game::{FlagState, MinrsGame, Position, StdMinrsGame, TileContents, TileState};
 use gtk::gdk;
 use gtk::prelude::*;
+use min_rs_weeper::analysis::{self, GameStats};
+use min_rs_weeper::coop::{self, ClientMessage, CoopBoard, CoopClient, ServerMessage};
+use min_rs_weeper::difficulty::GameDifficulty;
+use min_rs_weeper::error::MinrsResult;
+use min_rs_weeper::game::{
+    FlagState, GameEvent, GameStatus, MinrsGame, Neighborhood, Position, StdMinrsGame,
+    TileContents, TileState,
+};
+use min_rs_weeper::infinite::{InfiniteMinrsGame, PlanePos};
+use min_rs_weeper::leaderboard::{self, Leaderboard, Record, WinTracker};
+use min_rs_weeper::race::{self, Progress, Race, RaceClient};
+use min_rs_weeper::replay::{Player, Recorder, Replay};
+use min_rs_weeper::{probability, solver};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
 
 const NO_WINDOW_PARENT: Option<&gtk::Window> = None;
-
-#[derive(Copy, Clone)]
-enum GameDifficulty {
-    Easy,
-    Medium,
-    Hard,
-}
-
-impl GameDifficulty {
-    fn get_width(&self) -> u8 {
-        match self {
-            GameDifficulty::Easy => 8,
-            GameDifficulty::Medium => 15,
-            GameDifficulty::Hard => 30,
-        }
-    }
-
-    fn get_height(&self) -> u8 {
-        match self {
-            GameDifficulty::Easy => 8,
-            GameDifficulty::Medium => 15,
-            GameDifficulty::Hard => 30,
-        }
-    }
-
-    fn get_mines(&self) -> u16 {
-        match self {
-            GameDifficulty::Easy => 10,
-            GameDifficulty::Medium => 40,
-            GameDifficulty::Hard => 99,
-        }
-    }
-}
+const GUI_SAVE_MAGIC: &str = "min-rs-weeper save 1";
+const GUI_SAVE_SECTION: &str = "---\n";
+/// Tiles shown at once in the infinite mode window.
+const INFINITE_VIEW_WIDTH: u16 = 24;
+const INFINITE_VIEW_HEIGHT: u16 = 16;
+/// How far the arrow keys move the infinite view.
+const INFINITE_PAN_STEP: i64 = 4;
+const TIMER_INTERVAL: Duration = Duration::from_millis(100);
+/// Where the join dialog points first, matching the server's default port.
+const COOP_DEFAULT_ADDRESS: &str = "127.0.0.1:7373";
+/// Race boards sit side by side, so their tiles are drawn a little smaller.
+const RACE_TILE_SIZE: i32 = 24;
 
 struct GuiPriv {
     difficulty: GameDifficulty,
-    buttons: Vec<Vec<gtk::Button>>,
+    no_guess: bool,
+    neighborhood: Neighborhood,
+    wrap: bool,
+    /// Layers of a 3D board, 1 for a flat one.
+    depth: u16,
+    mines_per_tile: u8,
+    show_odds: bool,
+    buttons: HashMap<Position, gtk::Button>,
     window: gtk::ApplicationWindow,
     grid: gtk::Grid,
-    game: StdMinrsGame,
+    game: Recorder<StdMinrsGame>,
+    /// Whether a win of the current game goes on the best times.
+    wins: WinTracker,
+    /// What the game reported since the board was last drawn.
+    events: Arc<Mutex<Vec<GameEvent>>>,
     gp_arc: Option<Arc<Mutex<GuiPriv>>>,
     menu_bar: gtk::MenuBar,
+    /// The Difficulty menu's options, kept to show the game's settings.
+    settings_menu: Option<SettingsMenu>,
+    /// Shows how long the current game has been going.
+    timer: gtk::Label,
     v_box: gtk::Box,
 }
 
+/// The options of the Difficulty menu that change how boards are made.
+///
+/// Their handlers skip the change when the lock is held, which is how
+/// `update_settings_menu` sets them without starting a new game.
+struct SettingsMenu {
+    no_guess: gtk::CheckMenuItem,
+    wrap: gtk::CheckMenuItem,
+    layers: gtk::CheckMenuItem,
+    neighbors: Vec<(Neighborhood, gtk::RadioMenuItem)>,
+    /// Indexed by mines per tile, from 1.
+    stacks: Vec<gtk::RadioMenuItem>,
+}
+
+/// The infinite mode window, which pans one fixed grid of buttons over the
+/// plane rather than building a button per tile.
+struct InfinitePriv {
+    game: InfiniteMinrsGame,
+    buttons: HashMap<Position, gtk::Button>,
+    status: gtk::Label,
+    window: gtk::Window,
+}
+
+/// A window onto a co-op server's board, redrawn as its messages arrive.
+struct CoopPriv {
+    client: CoopClient,
+    board: CoopBoard,
+    buttons: HashMap<Position, gtk::Button>,
+    grid: gtk::Grid,
+    status: gtk::Label,
+}
+
+/// One board in a race window, with whoever is playing it.
+struct RaceSeat {
+    name: String,
+    game: StdMinrsGame,
+    buttons: HashMap<Position, gtk::Button>,
+}
+
+/// A race window, which shows every board played on this screen side by
+/// side, and how everyone is doing.
+struct RacePriv {
+    race: Race,
+    seats: Vec<RaceSeat>,
+    /// The server, when racing players elsewhere.
+    client: Option<RaceClient>,
+    player: Option<u32>,
+    opponents: BTreeMap<u32, Progress>,
+    /// Who cleared their board first.
+    winner: Option<String>,
+    boards: gtk::Box,
+    panel: gtk::Label,
+}
+
 fn main() {
     let application = gtk::Application::new(
         Some("com.github.nitepone.min-rs-weeper"),
@@ -71,148 +131,1148 @@ fn main() {
 }
 
 fn update_buttons(gp: &mut MutexGuard<GuiPriv>) {
-    for x in 0..gp.game.get_width() {
-        for y in 0..gp.game.get_height() {
-            let button = gp.buttons.get(x as usize).unwrap().get(y as usize).unwrap();
-
-            match gp.game.get_tile_state(&Position { x, y }).unwrap() {
-                TileState::Covered(flag_opt) => {
-                    button.set_relief(gtk::ReliefStyle::Normal);
-                    match flag_opt {
-                        None => {
-                            button.set_label(" ");
-                        }
-                        Some(flag) => match flag {
-                            FlagState::Questionable => {
-                                button.set_label("?");
-                            }
-                            FlagState::RedFlag => {
-                                button.set_label("!");
-                            }
-                        },
+    let odds = if gp.show_odds && !gp.game.game_over() {
+        probability::mine_probabilities(&gp.game).ok()
+    } else {
+        None
+    };
+    for pos in gp.game.get_positions() {
+        let button = gp.buttons.get(&pos).unwrap();
+        let odds = odds.as_ref().and_then(|odds| odds.get(&pos)).copied();
+        draw_tile(button, gp.game.get_tile_state(&pos).unwrap(), odds);
+    }
+}
+
+/// Redraw only the tiles the game reported changing, and say if the game
+/// ended.
+///
+/// Mine odds can move anywhere on the board, so those redraw everything.
+fn redraw_changes(gp: &mut MutexGuard<GuiPriv>) -> bool {
+    let events: Vec<GameEvent> = gp.events.lock().unwrap().drain(..).collect();
+    let mut ended = false;
+    for event in &events {
+        let pos = match event {
+            GameEvent::TileUncovered(pos, _)
+            | GameEvent::TileCovered(pos)
+            | GameEvent::FlagChanged(pos, _) => pos,
+            GameEvent::GameWon | GameEvent::GameLost(_) => {
+                ended = true;
+                continue;
+            }
+            GameEvent::CascadeStarted(_) | GameEvent::CascadeFinished(_) => continue,
+        };
+        if !gp.show_odds {
+            let button = gp.buttons.get(pos).unwrap();
+            draw_tile(button, gp.game.get_tile_state(pos).unwrap(), None);
+        }
+    }
+    if gp.show_odds && !events.is_empty() {
+        update_buttons(gp);
+    }
+    ended
+}
+
+/// Label a tile's button with what the player knows about it.
+fn draw_tile(button: &gtk::Button, state: TileState, odds: Option<f64>) {
+    match state {
+        TileState::Covered(flag_opt) => {
+            button.set_relief(gtk::ReliefStyle::Normal);
+            match flag_opt {
+                None => match odds {
+                    Some(odds) => {
+                        button.set_label(&format!("{:.0}%", odds * 100.0));
+                    }
+                    None => {
+                        button.set_label(" ");
+                    }
+                },
+                Some(flag) => match flag {
+                    FlagState::Questionable => {
+                        button.set_label("?");
+                    }
+                    FlagState::RedFlag(1) => {
+                        button.set_label("!");
                     }
+                    FlagState::RedFlag(count) => {
+                        button.set_label(&format!("!{count}"));
+                    }
+                },
+            }
+        }
+        TileState::Uncovered(con) => {
+            button.set_relief(gtk::ReliefStyle::None);
+            match con {
+                TileContents::Mines(count) => {
+                    button.set_label(&"*".repeat(count as usize));
                 }
-                TileState::Uncovered(con) => {
-                    button.set_relief(gtk::ReliefStyle::None);
-                    match con {
-                        TileContents::Mine => {
-                            button.set_label("*");
-                        }
-                        TileContents::MineCount(count) => {
-                            if count == 0 {
-                                button.set_label(" ");
-                            } else {
-                                button.set_label(&format!("{count}"));
-                            }
-                        }
+                TileContents::MineCount(count) => {
+                    if count == 0 {
+                        button.set_label(" ");
+                    } else {
+                        button.set_label(&format!("{count}"));
                     }
                 }
             }
         }
     }
-    // *looks up* triple nested decision struture in a double nested loop? O.o
 }
 
-fn draw_gameover_dialog() -> bool {
+/// What the player picked once a game ended.
+enum EndChoice {
+    NewGame,
+    Quit,
+    Undo,
+    SaveReplay,
+}
+
+fn end_choice(resp: gtk::ResponseType) -> EndChoice {
+    match resp {
+        gtk::ResponseType::Yes => EndChoice::NewGame,
+        gtk::ResponseType::Reject => EndChoice::Undo,
+        gtk::ResponseType::Apply => EndChoice::SaveReplay,
+        _ => EndChoice::Quit,
+    }
+}
+
+/// Show a game time as minutes, seconds and tenths.
+fn format_time(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    format!(
+        "{}:{:02}.{}",
+        secs / 60,
+        secs % 60,
+        elapsed.subsec_millis() / 100
+    )
+}
+
+fn update_timer(gp: &mut MutexGuard<GuiPriv>) {
+    let elapsed = gp.game.get_game().get_elapsed();
+    let warning = if gp.game.get_game().is_no_guess_failed() {
+        "    (no guess-free board found, this one may need a guess)"
+    } else {
+        ""
+    };
+    gp.timer
+        .set_text(&format!("Time: {}{warning}", format_time(elapsed)));
+}
+
+fn draw_gameover_dialog(elapsed: Duration) -> EndChoice {
     let mtype = gtk::MessageType::Warning;
     let dialog = gtk::MessageDialog::new(
         NO_WINDOW_PARENT,
         gtk::DialogFlags::MODAL,
         mtype,
         gtk::ButtonsType::YesNo,
-        "Gameover!\nStart a new game?",
+        &format!(
+            "Gameover!\nTime: {}\nStart a new game?",
+            format_time(elapsed)
+        ),
     );
+    dialog.add_button("Undo", gtk::ResponseType::Reject);
+    dialog.add_button("Save Replay", gtk::ResponseType::Apply);
     dialog.set_title("min-rs-weeper - gameover");
     dialog.set_position(gtk::WindowPosition::Center);
     let resp = dialog.run();
     dialog.close();
-    match resp {
-        gtk::ResponseType::Yes => true,
-        gtk::ResponseType::No => false,
-        _ => false,
-    }
+    end_choice(resp)
 }
 
-fn draw_victory_dialog() -> bool {
+/// Show how the game went, and where it landed in the best times if it made
+/// them.
+fn draw_victory_dialog(stats: &GameStats, place: Option<(String, usize)>) -> EndChoice {
     let mtype = gtk::MessageType::Warning;
+    let record = match place {
+        Some((board, 0)) => format!("New record for {board}!\n"),
+        Some((board, place)) => format!("#{} in the {board} best times!\n", place + 1),
+        None => String::new(),
+    };
     let dialog = gtk::MessageDialog::new(
         NO_WINDOW_PARENT,
         gtk::DialogFlags::MODAL,
         mtype,
         gtk::ButtonsType::YesNo,
-        "Victory!\nStart a new game?",
+        &format!(
+            "Victory!\n{record}Time: {}\n3BV: {}    3BV/s: {:.2}\nClicks: {}    Efficiency: {:.0}%\nStart a new game?",
+            format_time(stats.elapsed),
+            stats.metrics.bbbv,
+            stats.bbbv_per_second,
+            stats.clicks.total(),
+            stats.efficiency * 100.0,
+        ),
     );
+    dialog.add_button("Save Replay", gtk::ResponseType::Apply);
     dialog.set_title("min-rs-weeper - victory!");
     dialog.set_position(gtk::WindowPosition::Center);
     let resp = dialog.run();
     dialog.close();
-    match resp {
-        gtk::ResponseType::Yes => true,
-        gtk::ResponseType::No => false,
-        _ => false,
-    }
+    end_choice(resp)
 }
 
 fn restart_game(gp: &mut MutexGuard<GuiPriv>) {
     let diff = gp.difficulty;
-    gp.game = StdMinrsGame::new(diff.get_width(), diff.get_height(), diff.get_mines()).unwrap();
+    // keep the mine density of the flat board on every layer
+    let mut game = StdMinrsGame::new_3d(
+        diff.get_width(),
+        diff.get_height(),
+        gp.depth,
+        diff.get_mines() * gp.depth as u32,
+    )
+    .unwrap();
+    game.set_no_guess(gp.no_guess).unwrap();
+    game.set_neighborhood(gp.neighborhood.clone()).unwrap();
+    if game.set_wrap(gp.wrap).is_err() {
+        draw_error_dialog("Hex boards can only wrap around with an even number of rows.");
+    }
+    if game.set_mines_per_tile(gp.mines_per_tile).is_err() {
+        draw_error_dialog("No Guessing only works with one mine per tile.");
+    }
+    // whatever the game refused is off now, in the menu too
+    gp.wrap = game.is_wrapping();
+    gp.mines_per_tile = game.get_mines_per_tile();
+    update_settings_menu(gp);
+    set_game(gp, Recorder::new(game));
+    update_title(gp);
+    draw_buttons(gp);
+}
+
+/// Show the current settings in the Difficulty menu.
+fn update_settings_menu(gp: &MutexGuard<GuiPriv>) {
+    let menu = match &gp.settings_menu {
+        Some(menu) => menu,
+        None => return,
+    };
+    menu.no_guess.set_active(gp.no_guess);
+    menu.wrap.set_active(gp.wrap);
+    menu.layers.set_active(gp.depth > 1);
+    for (neighborhood, item) in &menu.neighbors {
+        if *neighborhood == gp.neighborhood {
+            item.set_active(true);
+        }
+    }
+    if let Some(item) = menu.stacks.get(gp.mines_per_tile as usize - 1) {
+        item.set_active(true);
+    }
+}
+
+/// Switch to a new game, listening to it from the start.
+fn set_game(gp: &mut MutexGuard<GuiPriv>, mut game: Recorder<StdMinrsGame>) {
+    let events = gp.events.clone();
+    events.lock().unwrap().clear();
+    game.subscribe(Box::new(move |event| events.lock().unwrap().push(*event)));
+    gp.game = game;
+    gp.wins = WinTracker::new();
+}
+
+fn update_title(gp: &mut MutexGuard<GuiPriv>) {
+    // show the seed so a board can be reported or shared
+    match gp.game.get_game().get_seed() {
+        Some(seed) => gp.window.set_title(&format!("min-rs-weeper - seed {seed}")),
+        None => gp.window.set_title("min-rs-weeper"),
+    }
+}
+
+fn draw_error_dialog(message: &str) {
+    let dialog = gtk::MessageDialog::new(
+        NO_WINDOW_PARENT,
+        gtk::DialogFlags::MODAL,
+        gtk::MessageType::Error,
+        gtk::ButtonsType::Ok,
+        message,
+    );
+    dialog.set_title("min-rs-weeper - error");
+    dialog.set_position(gtk::WindowPosition::Center);
+    dialog.run();
+    dialog.close();
+}
+
+/// Ask the player for a file to open or save.
+fn draw_file_dialog(
+    gp: &MutexGuard<GuiPriv>,
+    title: &str,
+    action: gtk::FileChooserAction,
+) -> Option<PathBuf> {
+    let accept = match action {
+        gtk::FileChooserAction::Save => "_Save",
+        _ => "_Open",
+    };
+    let dialog = gtk::FileChooserDialog::with_buttons(
+        Some(title),
+        Some(&gp.window),
+        action,
+        &[
+            ("_Cancel", gtk::ResponseType::Cancel),
+            (accept, gtk::ResponseType::Accept),
+        ],
+    );
+    dialog.set_do_overwrite_confirmation(true);
+    let resp = dialog.run();
+    let path = dialog.filename();
+    dialog.close();
+    match resp {
+        gtk::ResponseType::Accept => path,
+        _ => None,
+    }
+}
+
+fn save_replay(gp: &mut MutexGuard<GuiPriv>) {
+    let path = match draw_file_dialog(gp, "Save Replay", gtk::FileChooserAction::Save) {
+        Some(path) => path,
+        None => return,
+    };
+    if let Err(e) = fs::write(&path, gp.game.get_replay().to_string()) {
+        draw_error_dialog(&format!("Could not save replay: {e}"));
+    }
+}
+
+/// Load a replay, leaving the game at its last move so the player can undo
+/// back through it, or play on.
+fn open_replay(gp: &mut MutexGuard<GuiPriv>) {
+    let path = match draw_file_dialog(gp, "Open Replay", gtk::FileChooserAction::Open) {
+        Some(path) => path,
+        None => return,
+    };
+    let mut player = match fs::read_to_string(&path)
+        .ok()
+        .and_then(|text| text.parse::<Replay>().ok())
+        .and_then(|replay| Player::new(replay).ok())
+    {
+        Some(player) => player,
+        None => {
+            draw_error_dialog("Could not read replay.");
+            return;
+        }
+    };
+    while let Ok(Some(_)) = player.step() {}
+    set_game(gp, player.into_recorder());
+    // it was someone else's game, at least up to here
+    gp.wins.disqualify();
+    update_title(gp);
+    draw_buttons(gp);
+    update_buttons(gp);
+}
+
+/// Where we keep anything that should outlive the window, following the XDG
+/// base directory spec.
+fn data_dir() -> Option<PathBuf> {
+    env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+        .map(|dir| dir.join("min-rs-weeper"))
+}
+
+fn leaderboard_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("leaderboard"))
+}
+
+/// Read the best times, starting afresh if there are none yet or they
+/// can't be read.
+fn read_leaderboard() -> Leaderboard {
+    leaderboard_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|text| text.parse().ok())
+        .unwrap_or_default()
+}
+
+/// The name records are kept under, which is the login name.
+fn player_name() -> String {
+    env::var("USER")
+        .or_else(|_| env::var("USERNAME"))
+        .unwrap_or_else(|_| "Player".to_string())
+}
+
+/// Add a won game to the best times, returning its board and place if it
+/// made them.
+fn record_win(gp: &MutexGuard<GuiPriv>, stats: &GameStats) -> Option<(String, usize)> {
+    let game = gp.game.get_game();
+    let board = leaderboard::board_name(game);
+    let record = Record {
+        name: player_name(),
+        time: stats.elapsed,
+        bbbv: stats.metrics.bbbv,
+        seed: game.get_seed(),
+        date: leaderboard::format_date(SystemTime::now()),
+    };
+    let mut leaderboard = read_leaderboard();
+    let place = leaderboard.add(&board, record)?;
+    let path = leaderboard_path()?;
+    let written = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&path, leaderboard.to_string()));
+    if let Err(e) = written {
+        eprintln!("min-rs-weeper: could not save best times: {e}");
+    }
+    Some((board, place))
+}
+
+/// List the best times for every board played so far.
+fn draw_best_times_window(parent: &gtk::ApplicationWindow) {
+    let window = gtk::Window::new(gtk::WindowType::Toplevel);
+    window.set_title("min-rs-weeper - best times");
+    window.set_transient_for(Some(parent));
+    window.set_default_size(520, 400);
+    let scrolled = gtk::ScrolledWindow::new(gtk::Adjustment::NONE, gtk::Adjustment::NONE);
+    let grid = gtk::Grid::new();
+    grid.set_column_spacing(16);
+    grid.set_row_spacing(4);
+    grid.set_margin(10);
+    scrolled.add(&grid);
+    window.add(&scrolled);
+
+    let leaderboard = read_leaderboard();
+    let mut row = 0;
+    let attach = |column: i32, row: i32, text: &str| {
+        let label = gtk::Label::new(Some(text));
+        label.set_xalign(0.0);
+        grid.attach(&label, column, row, 1, 1);
+        label
+    };
+    for (board, records) in leaderboard.get_boards() {
+        let header = attach(0, row, "");
+        header.set_markup(&format!("<b>{}</b>", gtk::glib::markup_escape_text(board)));
+        row += 1;
+        for (place, record) in records.iter().enumerate() {
+            let seed = record.seed.map_or("-".to_string(), |seed| seed.to_string());
+            attach(0, row, &format!("#{}", place + 1));
+            attach(1, row, &record.name);
+            attach(2, row, &format_time(record.time));
+            attach(3, row, &format!("3BV {}", record.bbbv));
+            attach(4, row, &record.date);
+            attach(5, row, &format!("seed {seed}"));
+            row += 1;
+        }
+    }
+    if row == 0 {
+        attach(0, 0, "No games won yet.");
+    }
+    window.set_position(gtk::WindowPosition::CenterOnParent);
+    window.show_all();
+}
+
+fn autosave_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("autosave"))
+}
+
+/// Write the game, the replay recorded so far and the menu settings it was
+/// started with.
+fn write_game(gp: &MutexGuard<GuiPriv>, path: &Path) -> std::io::Result<()> {
+    let text = format!(
+        "{GUI_SAVE_MAGIC}\ndifficulty {}\nno_guess {}\n{GUI_SAVE_SECTION}{}{GUI_SAVE_SECTION}{}",
+        gp.difficulty.get_name(),
+        gp.no_guess as u8,
+        gp.game.get_game().save(),
+        gp.game.get_replay(),
+    );
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, text)
+}
+
+/// Restore a game written by `write_game`, leaving the current one untouched
+/// if the file can not be read.
+fn read_game(gp: &mut MutexGuard<GuiPriv>, path: &Path) -> Option<()> {
+    let text = fs::read_to_string(path).ok()?;
+    let mut sections = text.split(GUI_SAVE_SECTION);
+    let mut header = sections.next()?.lines();
+    if header.next()? != GUI_SAVE_MAGIC {
+        return None;
+    }
+    let difficulty = GameDifficulty::from_name(header.next()?.strip_prefix("difficulty ")?)?;
+    let no_guess = match header.next()?.strip_prefix("no_guess ")? {
+        "0" => false,
+        "1" => true,
+        _ => return None,
+    };
+    let game = StdMinrsGame::load(sections.next()?).ok()?;
+    let replay = sections.next()?.parse::<Replay>().ok()?;
+    gp.difficulty = difficulty;
+    gp.no_guess = no_guess;
+    gp.neighborhood = game.get_neighborhood().clone();
+    gp.wrap = game.is_wrapping();
+    gp.depth = game.get_depth();
+    gp.mines_per_tile = game.get_mines_per_tile();
+    update_settings_menu(gp);
+    set_game(gp, Recorder::resume(game, replay));
+    update_title(gp);
     draw_buttons(gp);
+    update_buttons(gp);
+    Some(())
+}
+
+fn save_game(gp: &mut MutexGuard<GuiPriv>) {
+    let path = match draw_file_dialog(gp, "Save Game", gtk::FileChooserAction::Save) {
+        Some(path) => path,
+        None => return,
+    };
+    if let Err(e) = write_game(gp, &path) {
+        draw_error_dialog(&format!("Could not save game: {e}"));
+    }
+}
+
+fn load_game(gp: &mut MutexGuard<GuiPriv>) {
+    let path = match draw_file_dialog(gp, "Load Game", gtk::FileChooserAction::Open) {
+        Some(path) => path,
+        None => return,
+    };
+    if read_game(gp, &path).is_none() {
+        draw_error_dialog("Could not read saved game.");
+    }
+}
+
+/// Keep an unfinished game around for the next start.
+fn autosave(gp: &MutexGuard<GuiPriv>) {
+    if gp.game.game_over() || gp.game.victory() {
+        return;
+    }
+    if let Some(path) = autosave_path() {
+        if let Err(e) = write_game(gp, &path) {
+            eprintln!("min-rs-weeper: could not autosave: {e}");
+        }
+    }
+}
+
+/// Pick up where the last session left off, if it left anything.
+fn restore_autosave(gp: &mut MutexGuard<GuiPriv>) {
+    if let Some(path) = autosave_path() {
+        if read_game(gp, &path).is_some() {
+            // it is the current game now, and gets saved again on exit
+            let _ = fs::remove_file(&path);
+        }
+    }
+}
+
+/// Take back the last move. Taking back a mine keeps the game off the best
+/// times.
+fn undo_move(gp: &mut MutexGuard<GuiPriv>) -> MinrsResult<()> {
+    let gp = &mut **gp;
+    gp.wins.undoing(gp.game.get_game());
+    gp.game.undo()
+}
+
+/// Redraw after a move, and offer a new game if that move ended this one.
+fn check_game_end(gp: &mut MutexGuard<GuiPriv>) {
+    if !redraw_changes(gp) {
+        return;
+    }
+    update_timer(gp);
+    // record a win once, however many times the dialog is shown or the
+    // winning move is taken back and played again
+    let new_win = {
+        let gp = &mut **gp;
+        gp.wins.take_win(gp.game.get_game())
+    };
+    let place = if new_win {
+        let stats = analysis::game_stats(gp.game.get_game()).unwrap();
+        record_win(gp, &stats)
+    } else {
+        None
+    };
+    loop {
+        let choice = if gp.game.game_over() {
+            draw_gameover_dialog(gp.game.get_game().get_elapsed())
+        } else {
+            let stats = analysis::game_stats(gp.game.get_game()).unwrap();
+            draw_victory_dialog(&stats, place.clone())
+        };
+        match choice {
+            EndChoice::NewGame => {
+                restart_game(gp);
+                update_buttons(gp);
+            }
+            EndChoice::Quit => std::process::exit(0),
+            EndChoice::Undo => {
+                undo_move(gp).unwrap();
+                redraw_changes(gp);
+            }
+            // then ask again
+            EndChoice::SaveReplay => {
+                save_replay(gp);
+                continue;
+            }
+        }
+        break;
+    }
 }
 
 fn draw_buttons(gp: &mut MutexGuard<GuiPriv>) {
-    gp.buttons = Vec::new();
+    gp.buttons = HashMap::new();
     gp.v_box.remove(&gp.grid);
     gp.grid = gtk::Grid::new();
-    for x in 0..gp.game.get_width() {
-        let mut buttons_row_arr = Vec::new();
-        for y in 0..gp.game.get_height() {
-            let button = gtk::Button::new();
-            let d_gui_priv = gp.gp_arc.clone().unwrap();
-            #[allow(unused_must_use)] // there are a lot of errors to no-op on..
+    // each tile spans two grid columns, so hex rows can be offset by half a
+    // tile. Layers sit side by side, with a column for a separator between.
+    let layer_columns = gp.game.get_width() as i32 * 2 + 2;
+    for pos in gp.game.get_positions() {
+        let button = gtk::Button::new();
+        let d_gui_priv = gp.gp_arc.clone().unwrap();
+        #[allow(unused_must_use)] // there are a lot of errors to no-op on..
+        button.connect_event(move |_btn, e| {
+            if e.event_type() == gdk::EventType::ButtonPress {
+                let mut gp = d_gui_priv.lock().unwrap();
+                if e.button().unwrap_or(0) == 1 {
+                    gp.game.uncover_tile(&pos);
+                } else if e.button().unwrap_or(0) == 3 {
+                    // Uh. So we can attempt uncovering neighbors, then cycling the flag.
+                    // One of these will always error. But.. Whatever.
+                    gp.game.uncover_neighbors(&pos);
+                    gp.game.cycle_flag(&pos);
+                }
+                check_game_end(&mut gp);
+            }
+            gtk::Inhibit(false)
+        });
+        button.set_size_request(50, 50);
+        button.set_expand(false);
+        button.set_hexpand(false);
+        button.set_margin(0);
+        let offset = match gp.game.get_game().get_neighborhood() {
+            Neighborhood::Hex => pos.y as i32 % 2,
+            _ => 0,
+        };
+        let column = pos.z as i32 * layer_columns + pos.x as i32 * 2 + offset;
+        gp.grid.attach(&button, column, pos.y as i32, 2, 1);
+        gp.buttons.insert(pos, button);
+    }
+    for z in 1..gp.game.get_depth() as i32 {
+        let separator = gtk::Separator::new(gtk::Orientation::Vertical);
+        separator.set_margin(10);
+        gp.grid.attach(
+            &separator,
+            z * layer_columns - 1,
+            0,
+            1,
+            gp.game.get_height() as i32,
+        );
+    }
+    gp.v_box.pack_start(&gp.grid, true, true, 0);
+    gp.window.show_all();
+}
+
+fn draw_infinite_window(parent: &gtk::ApplicationWindow) {
+    let window = gtk::Window::new(gtk::WindowType::Toplevel);
+    window.set_title("min-rs-weeper - infinite");
+    window.set_transient_for(Some(parent));
+    let v_box = gtk::Box::new(gtk::Orientation::Vertical, 10);
+    let status = gtk::Label::new(None);
+    let grid = gtk::Grid::new();
+    v_box.pack_start(&status, false, false, 0);
+    v_box.pack_start(&grid, true, true, 0);
+    window.add(&v_box);
+
+    let mut game = InfiniteMinrsGame::new(INFINITE_VIEW_WIDTH, INFINITE_VIEW_HEIGHT).unwrap();
+    // start with the plane's origin in the middle of the view
+    game.pan(
+        -(INFINITE_VIEW_WIDTH as i64 / 2),
+        -(INFINITE_VIEW_HEIGHT as i64 / 2),
+    );
+    #[allow(clippy::arc_with_non_send_sync)] // only ever touched from the gtk main thread
+    let ip_arc = Arc::new(Mutex::new(InfinitePriv {
+        game,
+        buttons: HashMap::new(),
+        status,
+        window: window.clone(),
+    }));
+    let mut ip = ip_arc.lock().unwrap();
+    for pos in ip.game.get_positions() {
+        let button = gtk::Button::new();
+        let button_ip = ip_arc.clone();
+        #[allow(unused_must_use)]
+        button.connect_event(move |_btn, e| {
+            if e.event_type() == gdk::EventType::ButtonPress {
+                let mut ip = button_ip.lock().unwrap();
+                if e.button().unwrap_or(0) == 1 {
+                    ip.game.uncover_tile(&pos);
+                } else if e.button().unwrap_or(0) == 3 {
+                    ip.game.uncover_neighbors(&pos);
+                    ip.game.cycle_flag(&pos);
+                }
+                check_infinite_end(&mut ip);
+            }
+            gtk::Inhibit(false)
+        });
+        button.set_size_request(30, 30);
+        grid.attach(&button, pos.x as i32, pos.y as i32, 1, 1);
+        ip.buttons.insert(pos, button);
+    }
+    let key_ip = ip_arc.clone();
+    window.connect_key_press_event(move |_, e| {
+        let (x, y) = match e.keyval() {
+            gdk::keys::constants::Left => (-INFINITE_PAN_STEP, 0),
+            gdk::keys::constants::Right => (INFINITE_PAN_STEP, 0),
+            gdk::keys::constants::Up => (0, -INFINITE_PAN_STEP),
+            gdk::keys::constants::Down => (0, INFINITE_PAN_STEP),
+            _ => return gtk::Inhibit(false),
+        };
+        let mut ip = key_ip.lock().unwrap();
+        ip.game.pan(x, y);
+        update_infinite(&mut ip);
+        gtk::Inhibit(true)
+    });
+    update_infinite(&mut ip);
+    window.set_position(gtk::WindowPosition::CenterOnParent);
+    window.show_all();
+}
+
+fn update_infinite(ip: &mut MutexGuard<InfinitePriv>) {
+    for pos in ip.game.get_positions() {
+        draw_tile(
+            &ip.buttons[&pos],
+            ip.game.get_tile_state(&pos).unwrap(),
+            None,
+        );
+    }
+    // name the tile in the middle, so the player knows how far they went
+    let middle = ip.game.get_view_origin();
+    let middle = PlanePos::new(
+        middle.x + INFINITE_VIEW_WIDTH as i64 / 2,
+        middle.y + INFINITE_VIEW_HEIGHT as i64 / 2,
+    );
+    ip.status.set_text(&format!(
+        "Score: {}    At: {}, {}    Arrow keys move the view",
+        ip.game.get_score(),
+        middle.x,
+        middle.y,
+    ));
+}
+
+/// Redraw after a move, and offer another go once a mine went off.
+fn check_infinite_end(ip: &mut MutexGuard<InfinitePriv>) {
+    update_infinite(ip);
+    if !ip.game.game_over() {
+        return;
+    }
+    let dialog = gtk::MessageDialog::new(
+        Some(&ip.window),
+        gtk::DialogFlags::MODAL,
+        gtk::MessageType::Warning,
+        gtk::ButtonsType::YesNo,
+        &format!(
+            "Gameover!\nYou cleared {} safe tiles.\nStart a new game?",
+            ip.game.get_score()
+        ),
+    );
+    dialog.set_title("min-rs-weeper - gameover");
+    let resp = dialog.run();
+    dialog.close();
+    if resp != gtk::ResponseType::Yes {
+        ip.window.close();
+        return;
+    }
+    let origin = ip.game.get_view_origin();
+    ip.game = InfiniteMinrsGame::new(INFINITE_VIEW_WIDTH, INFINITE_VIEW_HEIGHT).unwrap();
+    ip.game.set_view_origin(origin);
+    update_infinite(ip);
+}
+
+/// Ask the player where the co-op server is.
+fn draw_join_dialog(parent: &gtk::ApplicationWindow, title: &str) -> Option<String> {
+    let dialog = gtk::Dialog::with_buttons(
+        Some(title),
+        Some(parent),
+        gtk::DialogFlags::MODAL,
+        &[
+            ("Cancel", gtk::ResponseType::Cancel),
+            ("Join", gtk::ResponseType::Accept),
+        ],
+    );
+    let entry = gtk::Entry::new();
+    entry.set_text(COOP_DEFAULT_ADDRESS);
+    entry.set_activates_default(true);
+    dialog.set_default_response(gtk::ResponseType::Accept);
+    let content = dialog.content_area();
+    content.pack_start(&gtk::Label::new(Some("Server address:")), false, false, 5);
+    content.pack_start(&entry, false, false, 5);
+    dialog.show_all();
+    let resp = dialog.run();
+    let addr = entry.text().to_string();
+    dialog.close();
+    (resp == gtk::ResponseType::Accept).then_some(addr)
+}
+
+fn draw_coop_window(parent: &gtk::ApplicationWindow, addr: &str) {
+    let client = match CoopClient::connect(addr) {
+        Ok(client) => client,
+        Err(e) => {
+            draw_error_dialog(&format!("Couldn't join {addr}: {e}"));
+            return;
+        }
+    };
+    let (sender, receiver) = gtk::glib::MainContext::channel(gtk::glib::PRIORITY_DEFAULT);
+    let listened = client.listen(move |message| {
+        let _ = sender.send(message);
+    });
+    if let Err(e) = listened {
+        draw_error_dialog(&format!("Couldn't join {addr}: {e}"));
+        return;
+    }
+
+    let window = gtk::Window::new(gtk::WindowType::Toplevel);
+    window.set_title(&format!("min-rs-weeper - co-op on {addr}"));
+    window.set_transient_for(Some(parent));
+    let v_box = gtk::Box::new(gtk::Orientation::Vertical, 10);
+    let status = gtk::Label::new(Some("Joining…"));
+    let grid = gtk::Grid::new();
+    let new_game = gtk::Button::with_label("New Game");
+    v_box.pack_start(&status, false, false, 0);
+    v_box.pack_start(&grid, true, true, 0);
+    v_box.pack_start(&new_game, false, false, 0);
+    window.add(&v_box);
+
+    #[allow(clippy::arc_with_non_send_sync)] // only ever touched from the gtk main thread
+    let cp_arc = Arc::new(Mutex::new(CoopPriv {
+        client,
+        board: CoopBoard::new(),
+        buttons: HashMap::new(),
+        grid,
+        status,
+    }));
+    let new_game_cp = cp_arc.clone();
+    new_game.connect_clicked(move |_| {
+        send_coop_move(&mut new_game_cp.lock().unwrap(), ClientMessage::NewGame);
+    });
+    let message_cp = cp_arc.clone();
+    // stops by itself once the connection closes and the sender goes
+    receiver.attach(None, move |message| {
+        handle_coop_message(&message_cp, message);
+        gtk::glib::Continue(true)
+    });
+    let close_cp = cp_arc;
+    window.connect_delete_event(move |_, _| {
+        close_cp.lock().unwrap().client.disconnect();
+        gtk::Inhibit(false)
+    });
+    window.set_position(gtk::WindowPosition::CenterOnParent);
+    window.show_all();
+}
+
+fn send_coop_move(cp: &mut MutexGuard<CoopPriv>, message: ClientMessage) {
+    if let Err(e) = cp.client.send(message) {
+        cp.status.set_text(&format!("Lost the server: {e}"));
+    }
+}
+
+fn handle_coop_message(cp_arc: &Arc<Mutex<CoopPriv>>, message: ServerMessage) {
+    let mut cp = cp_arc.lock().unwrap();
+    cp.board.apply(&message);
+    match message {
+        ServerMessage::Board { .. } => draw_coop_buttons(cp_arc, &mut cp),
+        ServerMessage::Tile { pos, .. } => draw_coop_tile(&cp, &pos),
+        ServerMessage::Error(code) => {
+            cp.status.set_text(&format!("That didn't work: {code}"));
+            return;
+        }
+        ServerMessage::Player(_) | ServerMessage::State(_) => {}
+    }
+    update_coop_status(&cp);
+}
+
+/// Build a button per tile of a new board.
+fn draw_coop_buttons(cp_arc: &Arc<Mutex<CoopPriv>>, cp: &mut MutexGuard<CoopPriv>) {
+    for child in cp.grid.children() {
+        cp.grid.remove(&child);
+    }
+    cp.buttons.clear();
+    for y in 0..cp.board.get_height() {
+        for x in 0..cp.board.get_width() {
+            let pos = Position::new(x, y);
+            let button = gtk::Button::with_label(" ");
+            let button_cp = cp_arc.clone();
             button.connect_event(move |_btn, e| {
                 if e.event_type() == gdk::EventType::ButtonPress {
-                    let pos = Position { x, y };
-                    let mut gp = d_gui_priv.lock().unwrap();
-                    if e.button().unwrap_or(0) == 1 {
-                        gp.game.uncover_tile(&pos);
-                    } else if e.button().unwrap_or(0) == 3 {
-                        // Uh. So we can attempt uncovering neighbors, then cycling the flag.
-                        // One of these will always error. But.. Whatever.
-                        gp.game.uncover_neighbors(&pos);
-                        gp.game.cycle_flag(&pos);
-                    }
-                    update_buttons(&mut gp);
-                    let mut restart = None;
-                    if gp.game.game_over() {
-                        restart = Some(draw_gameover_dialog());
-                    }
-                    if gp.game.victory() {
-                        restart = Some(draw_victory_dialog());
-                    }
-                    if let Some(restart) = restart {
-                        if !restart {
-                            std::process::exit(0);
-                        } else {
-                            restart_game(&mut gp);
-                            update_buttons(&mut gp);
-                        }
-                    }
+                    let mut cp = button_cp.lock().unwrap();
+                    let uncovered =
+                        matches!(cp.board.get_tile_state(&pos), TileState::Uncovered(_));
+                    let message = match e.button().unwrap_or(0) {
+                        _ if uncovered => ClientMessage::Chord(pos),
+                        1 => ClientMessage::Open(pos),
+                        3 => ClientMessage::Flag(pos),
+                        _ => return gtk::Inhibit(false),
+                    };
+                    send_coop_move(&mut cp, message);
                 }
-                return gtk::Inhibit(false);
+                gtk::Inhibit(false)
             });
-            let gbox = gtk::Box::new(gtk::Orientation::Horizontal, 0);
-            button.set_size_request(50, 50);
-            button.set_expand(false);
-            button.set_hexpand(false);
-            button.set_margin(0);
-            gp.grid.attach(&button, x as i32, y as i32, 1, 1);
-            gbox.set_expand(false);
-            buttons_row_arr.push(button)
+            button.set_size_request(30, 30);
+            cp.grid.attach(&button, x as i32, y as i32, 1, 1);
+            cp.buttons.insert(pos, button);
         }
-        gp.buttons.push(buttons_row_arr);
     }
-    gp.v_box.pack_start(&gp.grid, true, true, 0);
-    gp.window.show_all();
+    cp.grid.show_all();
+}
+
+/// Draw a tile like any other, but in the colour of whoever flagged it.
+fn draw_coop_tile(cp: &MutexGuard<CoopPriv>, pos: &Position) {
+    let button = match cp.buttons.get(pos) {
+        Some(button) => button,
+        None => return,
+    };
+    let state = cp.board.get_tile_state(pos);
+    draw_tile(button, state, None);
+    let owner = match (state, cp.board.get_owner(pos)) {
+        (TileState::Covered(Some(_)), Some(owner)) => owner,
+        _ => return,
+    };
+    let (r, g, b) = coop::player_color(owner);
+    if let Some(label) = button
+        .child()
+        .and_then(|child| child.downcast::<gtk::Label>().ok())
+    {
+        label.set_markup(&format!(
+            "<span foreground=\"#{r:02x}{g:02x}{b:02x}\" weight=\"bold\">{}</span>",
+            gtk::glib::markup_escape_text(&label.text())
+        ));
+    }
+}
+
+fn update_coop_status(cp: &MutexGuard<CoopPriv>) {
+    let status = match cp.board.get_status() {
+        GameStatus::Playing => "Clearing",
+        GameStatus::Won => "Cleared!",
+        GameStatus::Lost => "Gameover!",
+    };
+    let player = match cp.board.get_player() {
+        Some(player) => {
+            let (r, g, b) = coop::player_color(player);
+            format!("<span foreground=\"#{r:02x}{g:02x}{b:02x}\">Player {player}</span>")
+        }
+        None => "Joining".to_string(),
+    };
+    cp.status.set_markup(&format!("{player}    {status}"));
+}
+
+/// Open an empty race window, with a seat per name once the race is set.
+fn draw_race_window(
+    parent: &gtk::ApplicationWindow,
+    title: &str,
+    race: Race,
+    client: Option<RaceClient>,
+) -> Arc<Mutex<RacePriv>> {
+    let window = gtk::Window::new(gtk::WindowType::Toplevel);
+    window.set_title(title);
+    window.set_transient_for(Some(parent));
+    let v_box = gtk::Box::new(gtk::Orientation::Vertical, 10);
+    let panel = gtk::Label::new(None);
+    let boards = gtk::Box::new(gtk::Orientation::Horizontal, 20);
+    let new_race = gtk::Button::with_label("New Race");
+    v_box.pack_start(&panel, false, false, 0);
+    v_box.pack_start(&boards, true, true, 0);
+    v_box.pack_start(&new_race, false, false, 0);
+    window.add(&v_box);
+
+    #[allow(clippy::arc_with_non_send_sync)] // only ever touched from the gtk main thread
+    let rp_arc = Arc::new(Mutex::new(RacePriv {
+        race,
+        seats: Vec::new(),
+        client,
+        player: None,
+        opponents: BTreeMap::new(),
+        winner: None,
+        boards,
+        panel,
+    }));
+    let new_race_rp = rp_arc.clone();
+    new_race.connect_clicked(move |_| {
+        let mut rp = new_race_rp.lock().unwrap();
+        match rp.client.as_mut() {
+            // the server answers with the race, which resets every window
+            Some(client) => {
+                let _ = client.send(race::ClientMessage::NewRace);
+            }
+            None => {
+                let race = Race {
+                    seed: rand::random(),
+                    ..rp.race
+                };
+                set_race(&new_race_rp, &mut rp, race);
+            }
+        }
+    });
+    let close_rp = rp_arc.clone();
+    window.connect_delete_event(move |_, _| {
+        if let Some(client) = &close_rp.lock().unwrap().client {
+            client.disconnect();
+        }
+        gtk::Inhibit(false)
+    });
+    window.set_position(gtk::WindowPosition::CenterOnParent);
+    window.show_all();
+    rp_arc
+}
+
+/// Race several players on this screen, each on their own copy of a board.
+fn draw_local_race(parent: &gtk::ApplicationWindow, difficulty: GameDifficulty, players: u32) {
+    let race = Race::random(difficulty);
+    let rp_arc = draw_race_window(parent, "min-rs-weeper - race", race, None);
+    let mut rp = rp_arc.lock().unwrap();
+    for player in 1..=players {
+        rp.seats.push(RaceSeat {
+            name: format!("Player {player}"),
+            game: race.new_game().unwrap(),
+            buttons: HashMap::new(),
+        });
+    }
+    set_race(&rp_arc, &mut rp, race);
+}
+
+/// Race players elsewhere through a race server.
+fn draw_remote_race(parent: &gtk::ApplicationWindow, addr: &str) {
+    let client = match RaceClient::connect(addr) {
+        Ok(client) => client,
+        Err(e) => {
+            draw_error_dialog(&format!("Couldn't join {addr}: {e}"));
+            return;
+        }
+    };
+    let (sender, receiver) = gtk::glib::MainContext::channel(gtk::glib::PRIORITY_DEFAULT);
+    let listened = client.listen(move |message| {
+        let _ = sender.send(message);
+    });
+    if let Err(e) = listened {
+        draw_error_dialog(&format!("Couldn't join {addr}: {e}"));
+        return;
+    }
+    // the real board comes from the server, this one only fills the window
+    let race = Race::random(GameDifficulty::Easy);
+    let title = format!("min-rs-weeper - race on {addr}");
+    let rp_arc = draw_race_window(parent, &title, race, Some(client));
+    rp_arc.lock().unwrap().panel.set_text("Joining…");
+    let message_rp = rp_arc;
+    receiver.attach(None, move |message| {
+        handle_race_message(&message_rp, message);
+        gtk::glib::Continue(true)
+    });
+}
+
+fn handle_race_message(rp_arc: &Arc<Mutex<RacePriv>>, message: race::ServerMessage) {
+    let mut rp = rp_arc.lock().unwrap();
+    match message {
+        race::ServerMessage::Player(id) => rp.player = Some(id),
+        race::ServerMessage::Race(race) => {
+            if rp.seats.is_empty() {
+                rp.seats.push(RaceSeat {
+                    name: "You".to_string(),
+                    game: race.new_game().unwrap(),
+                    buttons: HashMap::new(),
+                });
+            }
+            set_race(rp_arc, &mut rp, race);
+            return;
+        }
+        race::ServerMessage::Progress(id, _) if Some(id) == rp.player => return,
+        race::ServerMessage::Progress(id, progress) => {
+            if progress.status == GameStatus::Won && rp.winner.is_none() {
+                rp.winner = Some(format!("Player {id}"));
+            }
+            rp.opponents.insert(id, progress);
+        }
+        race::ServerMessage::Left(id) => {
+            rp.opponents.remove(&id);
+        }
+    }
+    update_race_panel(&rp);
+}
+
+/// Give every seat a fresh copy of the race's board.
+fn set_race(rp_arc: &Arc<Mutex<RacePriv>>, rp: &mut MutexGuard<RacePriv>, race: Race) {
+    rp.race = race;
+    rp.opponents.clear();
+    rp.winner = None;
+    for child in rp.boards.children() {
+        rp.boards.remove(&child);
+    }
+    for seat in 0..rp.seats.len() {
+        let grid = gtk::Grid::new();
+        let mut buttons = HashMap::new();
+        for y in 0..race.height {
+            for x in 0..race.width {
+                let pos = Position::new(x, y);
+                let button = gtk::Button::new();
+                let button_rp = rp_arc.clone();
+                button.connect_event(move |_btn, e| {
+                    if e.event_type() == gdk::EventType::ButtonPress {
+                        let mut rp = button_rp.lock().unwrap();
+                        race_move(&mut rp, seat, &pos, e.button().unwrap_or(0));
+                    }
+                    gtk::Inhibit(false)
+                });
+                button.set_size_request(RACE_TILE_SIZE, RACE_TILE_SIZE);
+                grid.attach(&button, x as i32, y as i32, 1, 1);
+                buttons.insert(pos, button);
+            }
+        }
+        let frame = gtk::Frame::new(Some(&rp.seats[seat].name));
+        frame.add(&grid);
+        rp.boards.pack_start(&frame, true, true, 0);
+        let seat = &mut rp.seats[seat];
+        seat.game = race.new_game().unwrap();
+        seat.buttons = buttons;
+        update_race_seat(seat);
+    }
+    rp.boards.show_all();
+    report_race_progress(rp);
+    update_race_panel(rp);
+}
+
+/// Play a click on one seat's board. A board that exploded or was cleared
+/// takes no more moves.
+#[allow(unused_must_use)]
+fn race_move(rp: &mut MutexGuard<RacePriv>, seat: usize, pos: &Position, button: u32) {
+    let game = &mut rp.seats[seat].game;
+    if GameStatus::of(game) != GameStatus::Playing {
+        return;
+    }
+    if button == 1 {
+        game.uncover_tile(pos);
+    } else if button == 3 {
+        game.uncover_neighbors(pos);
+        game.cycle_flag(pos);
+    }
+    let status = GameStatus::of(game);
+    update_race_seat(&rp.seats[seat]);
+    if status == GameStatus::Won && rp.winner.is_none() {
+        rp.winner = Some(rp.seats[seat].name.clone());
+    }
+    report_race_progress(rp);
+    update_race_panel(rp);
+}
+
+fn update_race_seat(seat: &RaceSeat) {
+    for (pos, button) in &seat.buttons {
+        draw_tile(button, seat.game.get_tile_state(pos).unwrap(), None);
+    }
+}
+
+/// Tell the server how our board is going.
+fn report_race_progress(rp: &mut MutexGuard<RacePriv>) {
+    let progress = match rp.seats.first() {
+        Some(seat) => Progress::of(&seat.game),
+        None => return,
+    };
+    if let Some(client) = rp.client.as_mut() {
+        let _ = client.send(race::ClientMessage::Progress(progress));
+    }
+}
+
+/// Show how far everyone got, here and elsewhere.
+fn update_race_panel(rp: &MutexGuard<RacePriv>) {
+    let describe = |name: &str, progress: &Progress| match progress.status {
+        GameStatus::Playing => format!("{name}: {:.0}% cleared", progress.get_percent(&rp.race)),
+        GameStatus::Won => format!("{name}: cleared!"),
+        GameStatus::Lost => format!("{name}: exploded at {:.0}%", progress.get_percent(&rp.race)),
+    };
+    let mut lines: Vec<String> = rp
+        .seats
+        .iter()
+        .map(|seat| describe(&seat.name, &Progress::of(&seat.game)))
+        .collect();
+    for (id, progress) in &rp.opponents {
+        lines.push(describe(&format!("Player {id}"), progress));
+    }
+    if let Some(winner) = &rp.winner {
+        lines.insert(0, format!("{winner} wins!"));
+    }
+    rp.panel.set_text(&lines.join("\n"));
 }
 
 fn populate_menu_bar(gp: &mut MutexGuard<GuiPriv>) {
@@ -245,32 +1305,318 @@ fn populate_menu_bar(gp: &mut MutexGuard<GuiPriv>) {
         draw_buttons(&mut gp);
         update_buttons(&mut gp);
     });
+    let no_guess = gtk::CheckMenuItem::with_label("No Guessing");
+    let no_guess_gp = gp.gp_arc.clone().unwrap();
+    no_guess.connect_toggled(move |item| {
+        let mut gp = match no_guess_gp.try_lock() {
+            Ok(gp) => gp,
+            Err(_) => return,
+        };
+        gp.no_guess = item.is_active();
+        restart_game(&mut gp);
+        draw_buttons(&mut gp);
+        update_buttons(&mut gp);
+    });
     diff_submenu.append(&easy);
     diff_submenu.append(&medium);
     diff_submenu.append(&hard);
+    let neighbors_submenu = gtk::Menu::new();
+    let neighbors = gtk::MenuItem::with_label("Neighbors");
+    let mut first_neighbors: Option<gtk::RadioMenuItem> = None;
+    let mut neighbors_items = Vec::new();
+    for (label, neighborhood) in [
+        ("Square", Neighborhood::Square),
+        ("Hex Tiles", Neighborhood::Hex),
+        ("Cross", Neighborhood::Cross),
+        ("Knight Moves", Neighborhood::Knight),
+        ("Radius 2", Neighborhood::Radius(2)),
+    ] {
+        let item = match &first_neighbors {
+            Some(first) => gtk::RadioMenuItem::with_label_from_widget(first, Some(label)),
+            None => gtk::RadioMenuItem::with_label(label),
+        };
+        let neighborhood_item = neighborhood.clone();
+        let item_gp = gp.gp_arc.clone().unwrap();
+        item.connect_toggled(move |item| {
+            // the item being switched away from is toggled too
+            if !item.is_active() {
+                return;
+            }
+            let mut gp = match item_gp.try_lock() {
+                Ok(gp) => gp,
+                Err(_) => return,
+            };
+            gp.neighborhood = neighborhood.clone();
+            restart_game(&mut gp);
+            draw_buttons(&mut gp);
+            update_buttons(&mut gp);
+        });
+        neighbors_submenu.append(&item);
+        neighbors_items.push((neighborhood_item, item.clone()));
+        first_neighbors.get_or_insert(item);
+    }
+    neighbors.set_submenu(Some(&neighbors_submenu));
+    let stacks_submenu = gtk::Menu::new();
+    let stacks = gtk::MenuItem::with_label("Mines Per Tile");
+    let mut first_stacks: Option<gtk::RadioMenuItem> = None;
+    let mut stacks_items = Vec::new();
+    for mines_per_tile in 1..=3 {
+        let label = mines_per_tile.to_string();
+        let item = match &first_stacks {
+            Some(first) => gtk::RadioMenuItem::with_label_from_widget(first, Some(&label)),
+            None => gtk::RadioMenuItem::with_label(&label),
+        };
+        let item_gp = gp.gp_arc.clone().unwrap();
+        item.connect_toggled(move |item| {
+            if !item.is_active() {
+                return;
+            }
+            let mut gp = match item_gp.try_lock() {
+                Ok(gp) => gp,
+                Err(_) => return,
+            };
+            gp.mines_per_tile = mines_per_tile;
+            restart_game(&mut gp);
+            draw_buttons(&mut gp);
+            update_buttons(&mut gp);
+        });
+        stacks_submenu.append(&item);
+        stacks_items.push(item.clone());
+        first_stacks.get_or_insert(item);
+    }
+    stacks.set_submenu(Some(&stacks_submenu));
+    let wrap = gtk::CheckMenuItem::with_label("Wrap Around Edges");
+    let wrap_gp = gp.gp_arc.clone().unwrap();
+    wrap.connect_toggled(move |item| {
+        let mut gp = match wrap_gp.try_lock() {
+            Ok(gp) => gp,
+            Err(_) => return,
+        };
+        gp.wrap = item.is_active();
+        restart_game(&mut gp);
+        draw_buttons(&mut gp);
+        update_buttons(&mut gp);
+    });
+    diff_submenu.append(&gtk::SeparatorMenuItem::new());
+    diff_submenu.append(&no_guess);
+    let layers = gtk::CheckMenuItem::with_label("3D (3 Layers)");
+    let layers_gp = gp.gp_arc.clone().unwrap();
+    layers.connect_toggled(move |item| {
+        let mut gp = match layers_gp.try_lock() {
+            Ok(gp) => gp,
+            Err(_) => return,
+        };
+        gp.depth = if item.is_active() { 3 } else { 1 };
+        restart_game(&mut gp);
+        draw_buttons(&mut gp);
+        update_buttons(&mut gp);
+    });
+    diff_submenu.append(&neighbors);
+    diff_submenu.append(&wrap);
+    diff_submenu.append(&layers);
+    diff_submenu.append(&stacks);
     diff.set_submenu(Some(&diff_submenu));
     gp.menu_bar.append(&diff);
+    gp.settings_menu = Some(SettingsMenu {
+        no_guess,
+        wrap,
+        layers,
+        neighbors: neighbors_items,
+        stacks: stacks_items,
+    });
+
+    let game_submenu = gtk::Menu::new();
+    let game = gtk::MenuItem::with_label("Game");
+    let hint = gtk::MenuItem::with_label("Hint");
+    let hint_gp = gp.gp_arc.clone().unwrap();
+    hint.connect_activate(move |_| {
+        let mut gp = hint_gp.lock().unwrap();
+        // uncover a tile the solver can prove is safe, if there is one
+        if let Ok(Some(pos)) = solver::hint(&gp.game) {
+            gp.wins.disqualify();
+            if gp.game.uncover_tile(&pos).is_ok() {
+                check_game_end(&mut gp);
+            }
+        }
+    });
+    let infinite = gtk::MenuItem::with_label("Infinite Mode…");
+    let infinite_gp = gp.gp_arc.clone().unwrap();
+    infinite.connect_activate(move |_| {
+        let gp = infinite_gp.lock().unwrap();
+        draw_infinite_window(&gp.window);
+    });
+    let join_coop = gtk::MenuItem::with_label("Join Co-op Game…");
+    let join_coop_gp = gp.gp_arc.clone().unwrap();
+    join_coop.connect_activate(move |_| {
+        let gp = join_coop_gp.lock().unwrap();
+        if let Some(addr) = draw_join_dialog(&gp.window, "min-rs-weeper - join co-op game") {
+            draw_coop_window(&gp.window, &addr);
+        }
+    });
+    let race = gtk::MenuItem::with_label("Race");
+    let race_submenu = gtk::Menu::new();
+    for players in 2..=4 {
+        let item = gtk::MenuItem::with_label(&format!("{players} Players Here"));
+        let item_gp = gp.gp_arc.clone().unwrap();
+        item.connect_activate(move |_| {
+            let gp = item_gp.lock().unwrap();
+            draw_local_race(&gp.window, gp.difficulty, players);
+        });
+        race_submenu.append(&item);
+    }
+    let join_race = gtk::MenuItem::with_label("Join Race…");
+    let join_race_gp = gp.gp_arc.clone().unwrap();
+    join_race.connect_activate(move |_| {
+        let gp = join_race_gp.lock().unwrap();
+        if let Some(addr) = draw_join_dialog(&gp.window, "min-rs-weeper - join race") {
+            draw_remote_race(&gp.window, &addr);
+        }
+    });
+    race_submenu.append(&gtk::SeparatorMenuItem::new());
+    race_submenu.append(&join_race);
+    race.set_submenu(Some(&race_submenu));
+    let best_times = gtk::MenuItem::with_label("Best Times…");
+    let best_times_gp = gp.gp_arc.clone().unwrap();
+    best_times.connect_activate(move |_| {
+        let gp = best_times_gp.lock().unwrap();
+        draw_best_times_window(&gp.window);
+    });
+    let show_odds = gtk::CheckMenuItem::with_label("Show Mine Odds");
+    let show_odds_gp = gp.gp_arc.clone().unwrap();
+    show_odds.connect_toggled(move |item| {
+        let mut gp = show_odds_gp.lock().unwrap();
+        gp.show_odds = item.is_active();
+        update_buttons(&mut gp);
+    });
+    let undo = gtk::MenuItem::with_label("Undo");
+    let undo_gp = gp.gp_arc.clone().unwrap();
+    undo.connect_activate(move |_| {
+        let mut gp = undo_gp.lock().unwrap();
+        if undo_move(&mut gp).is_ok() {
+            redraw_changes(&mut gp);
+        }
+    });
+    let redo = gtk::MenuItem::with_label("Redo");
+    let redo_gp = gp.gp_arc.clone().unwrap();
+    redo.connect_activate(move |_| {
+        let mut gp = redo_gp.lock().unwrap();
+        if gp.game.redo().is_ok() {
+            check_game_end(&mut gp);
+        }
+    });
+    let save_game_item = gtk::MenuItem::with_label("Save Game…");
+    let save_game_gp = gp.gp_arc.clone().unwrap();
+    save_game_item.connect_activate(move |_| {
+        let mut gp = save_game_gp.lock().unwrap();
+        save_game(&mut gp);
+    });
+    let load_game_item = gtk::MenuItem::with_label("Load Game…");
+    let load_game_gp = gp.gp_arc.clone().unwrap();
+    load_game_item.connect_activate(move |_| {
+        let mut gp = load_game_gp.lock().unwrap();
+        load_game(&mut gp);
+    });
+    let accel_group = gtk::AccelGroup::new();
+    gp.window.add_accel_group(&accel_group);
+    undo.add_accelerator(
+        "activate",
+        &accel_group,
+        *gdk::keys::constants::z,
+        gdk::ModifierType::CONTROL_MASK,
+        gtk::AccelFlags::VISIBLE,
+    );
+    redo.add_accelerator(
+        "activate",
+        &accel_group,
+        *gdk::keys::constants::y,
+        gdk::ModifierType::CONTROL_MASK,
+        gtk::AccelFlags::VISIBLE,
+    );
+    let save_replay_item = gtk::MenuItem::with_label("Save Replay…");
+    let save_replay_gp = gp.gp_arc.clone().unwrap();
+    save_replay_item.connect_activate(move |_| {
+        let mut gp = save_replay_gp.lock().unwrap();
+        save_replay(&mut gp);
+    });
+    let open_replay_item = gtk::MenuItem::with_label("Open Replay…");
+    let open_replay_gp = gp.gp_arc.clone().unwrap();
+    open_replay_item.connect_activate(move |_| {
+        let mut gp = open_replay_gp.lock().unwrap();
+        open_replay(&mut gp);
+    });
+    game_submenu.append(&undo);
+    game_submenu.append(&redo);
+    game_submenu.append(&gtk::SeparatorMenuItem::new());
+    game_submenu.append(&save_game_item);
+    game_submenu.append(&load_game_item);
+    game_submenu.append(&gtk::SeparatorMenuItem::new());
+    game_submenu.append(&save_replay_item);
+    game_submenu.append(&open_replay_item);
+    game_submenu.append(&gtk::SeparatorMenuItem::new());
+    game_submenu.append(&best_times);
+    game_submenu.append(&gtk::SeparatorMenuItem::new());
+    game_submenu.append(&hint);
+    game_submenu.append(&show_odds);
+    game_submenu.append(&gtk::SeparatorMenuItem::new());
+    game_submenu.append(&infinite);
+    game_submenu.append(&join_coop);
+    game_submenu.append(&race);
+    game.set_submenu(Some(&game_submenu));
+    gp.menu_bar.append(&game);
 }
 
 fn build_ui(application: &gtk::Application) {
+    #[allow(clippy::arc_with_non_send_sync)] // only ever touched from the gtk main thread
     let gui_priv_arc = Arc::new(Mutex::new(GuiPriv {
         difficulty: GameDifficulty::Easy,
-        game: StdMinrsGame::new(8, 8, 10).unwrap(),
-        buttons: Vec::new(),
+        no_guess: false,
+        neighborhood: Neighborhood::Square,
+        wrap: false,
+        depth: 1,
+        mines_per_tile: 1,
+        show_odds: false,
+        game: Recorder::new(StdMinrsGame::new(8, 8, 10).unwrap()),
+        wins: WinTracker::new(),
+        events: Arc::new(Mutex::new(Vec::new())),
+        buttons: HashMap::new(),
         grid: gtk::Grid::new(),
         window: gtk::ApplicationWindow::new(application),
         gp_arc: None,
         menu_bar: gtk::MenuBar::new(),
+        settings_menu: None,
+        timer: gtk::Label::new(None),
         v_box: gtk::Box::new(gtk::Orientation::Vertical, 10),
     }));
     let mut gp = gui_priv_arc.lock().unwrap();
     gp.gp_arc = Some(gui_priv_arc.clone());
     populate_menu_bar(&mut gp);
     gp.v_box.pack_start(&gp.menu_bar, false, false, 0);
+    gp.v_box.pack_start(&gp.timer, false, false, 0);
     gp.window.add(&gp.v_box);
     restart_game(&mut gp);
+    restore_autosave(&mut gp);
     draw_buttons(&mut gp);
-    gp.window.set_title("min-rs-weeper");
+    update_buttons(&mut gp);
+    let autosave_gp = gui_priv_arc.clone();
+    gp.window.connect_delete_event(move |_, _| {
+        // a dialog may be holding the lock, and would never let go of it
+        // while we wait here, so keep the window open until it's closed
+        match autosave_gp.try_lock() {
+            Ok(gp) => {
+                autosave(&gp);
+                gtk::Inhibit(false)
+            }
+            Err(_) => gtk::Inhibit(true),
+        }
+    });
+    let timer_gp = gui_priv_arc.clone();
+    gtk::glib::timeout_add_local(TIMER_INTERVAL, move || {
+        // a dialog may be holding the lock, the timer can wait for it
+        if let Ok(mut gp) = timer_gp.try_lock() {
+            update_timer(&mut gp);
+        }
+        gtk::glib::Continue(true)
+    });
     gp.window.set_position(gtk::WindowPosition::Center);
     gp.window.show_all();
 }
@@ -0,0 +1,378 @@
+// Copyright 2022 nitepone <luna@night.horse>
+//
+// Exact mine odds for every covered tile, from what a player could see.
+
+use crate::error::{MinrsError, MinrsResult};
+use crate::game::{MinrsGame, Position};
+use crate::solver::{self, Constraint};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// Covered tiles that share numbers, and every way mines could sit in them.
+struct Group {
+    tiles: Vec<Position>,
+    /// `ways[k]` counts the arrangements with `k` mines in the group.
+    ways: Vec<f64>,
+    /// `mine_ways[i][k]` counts the arrangements with `k` mines in the group
+    /// where `tiles[i]` is a mine.
+    mine_ways: Vec<Vec<f64>>,
+}
+
+/// Backtracking state for enumerating one group.
+struct Enumeration<'a> {
+    /// For each constraint, its tile indexes and mine count.
+    constraints: &'a [(Vec<usize>, usize)],
+    /// For each tile, the constraints it is part of.
+    tile_constraints: Vec<Vec<usize>>,
+    /// For each constraint, the mines placed and tiles left unassigned.
+    placed: Vec<(usize, usize)>,
+    mines: Vec<bool>,
+}
+
+impl Group {
+    /// Count every arrangement of mines in `tiles` satisfying `constraints`.
+    fn enumerate(tiles: Vec<Position>, constraints: &[&Constraint]) -> Group {
+        let index: HashMap<Position, usize> = tiles
+            .iter()
+            .enumerate()
+            .map(|(idx, pos)| (*pos, idx))
+            .collect();
+        let local: Vec<(Vec<usize>, usize)> = constraints
+            .iter()
+            .map(|c| (c.tiles.iter().map(|pos| index[pos]).collect(), c.mines))
+            .collect();
+        let mut tile_constraints = vec![Vec::new(); tiles.len()];
+        for (c_idx, (c_tiles, _)) in local.iter().enumerate() {
+            for &t_idx in c_tiles {
+                tile_constraints[t_idx].push(c_idx);
+            }
+        }
+
+        let mut group = Group {
+            ways: vec![0.0; tiles.len() + 1],
+            mine_ways: vec![vec![0.0; tiles.len() + 1]; tiles.len()],
+            tiles,
+        };
+        let mut state = Enumeration {
+            constraints: &local,
+            tile_constraints,
+            placed: local
+                .iter()
+                .map(|(c_tiles, _)| (0, c_tiles.len()))
+                .collect(),
+            mines: Vec::new(),
+        };
+        group.place(&mut state);
+        group
+    }
+
+    /// Try both contents for the next unassigned tile, recording every
+    /// arrangement that gets all the way through.
+    fn place(&mut self, state: &mut Enumeration) {
+        let t_idx = state.mines.len();
+        if t_idx == self.tiles.len() {
+            let count = state.mines.iter().filter(|mine| **mine).count();
+            self.ways[count] += 1.0;
+            for (idx, mine) in state.mines.iter().enumerate() {
+                if *mine {
+                    self.mine_ways[idx][count] += 1.0;
+                }
+            }
+            return;
+        }
+
+        for mine in [false, true] {
+            let mut valid = true;
+            for &c_idx in &state.tile_constraints[t_idx] {
+                let (placed, left) = &mut state.placed[c_idx];
+                *placed += mine as usize;
+                *left -= 1;
+                let needed = state.constraints[c_idx].1;
+                valid &= *placed <= needed && *placed + *left >= needed;
+            }
+            if valid {
+                state.mines.push(mine);
+                self.place(state);
+                state.mines.pop();
+            }
+            for &c_idx in &state.tile_constraints[t_idx] {
+                let (placed, left) = &mut state.placed[c_idx];
+                *placed -= mine as usize;
+                *left += 1;
+            }
+        }
+    }
+}
+
+/// Get the exact chance that each covered tile holds a mine.
+///
+/// Every arrangement of mines agreeing with the uncovered numbers and the
+/// total mine count is equally likely. The tiles next to numbers are
+/// enumerated one connected group at a time, and each arrangement is weighted
+/// by the ways the remaining covered tiles could hold the leftover mines.
+/// Flags are ignored, since the player may have placed them wrongly.
+///
+/// throws InvalidArgument if no arrangement fits the board, or a tile can
+///        hold more than one mine.
+pub fn mine_probabilities(game: &dyn MinrsGame) -> MinrsResult<HashMap<Position, f64>> {
+    let deductions = solver::solve(game)?;
+    let mut odds = HashMap::new();
+    odds.extend(deductions.safe.iter().map(|pos| (*pos, 0.0)));
+    odds.extend(deductions.mines.iter().map(|pos| (*pos, 1.0)));
+
+    // anything the solver proved doesn't need enumerating
+    let mut numbers: Vec<Constraint> = solver::get_constraints(game)?
+        .iter()
+        .map(|constraint| constraint.reduce(&deductions))
+        .collect();
+    let remaining = numbers.pop().ok_or(MinrsError::InvalidArgument)?;
+    numbers.retain(|constraint| !constraint.tiles.is_empty());
+
+    let groups = split_groups(&numbers);
+    let frontier: HashSet<Position> = groups
+        .iter()
+        .flat_map(|group| group.tiles.iter().copied())
+        .collect();
+    let unconstrained: Vec<Position> = remaining
+        .tiles
+        .iter()
+        .filter(|pos| !frontier.contains(pos))
+        .copied()
+        .collect();
+
+    // weight[m] is proportional to the ways of putting all but m of the
+    // remaining mines in the unconstrained tiles.
+    let weight = outside_weights(unconstrained.len(), remaining.mines, frontier.len());
+
+    let all = groups
+        .iter()
+        .fold(vec![1.0], |dist, group| convolve(&dist, &group.ways));
+    let total: f64 = all.iter().enumerate().map(|(m, w)| w * weight[m]).sum();
+    if total <= 0.0 || !total.is_finite() {
+        return Err(MinrsError::InvalidArgument);
+    }
+
+    for (g_idx, group) in groups.iter().enumerate() {
+        let others = groups
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| *idx != g_idx)
+            .fold(vec![1.0], |dist, (_, other)| convolve(&dist, &other.ways));
+        // the weight of everything outside this group, given k mines in it
+        let outside: Vec<f64> = (0..group.ways.len())
+            .map(|k| {
+                others
+                    .iter()
+                    .enumerate()
+                    .map(|(m, w)| w * weight[k + m])
+                    .sum()
+            })
+            .collect();
+        for (idx, pos) in group.tiles.iter().enumerate() {
+            let ways: f64 = group.mine_ways[idx]
+                .iter()
+                .zip(&outside)
+                .map(|(ways, outside)| ways * outside)
+                .sum();
+            odds.insert(*pos, ways / total);
+        }
+    }
+
+    if !unconstrained.is_empty() {
+        let expected: f64 = all
+            .iter()
+            .enumerate()
+            .filter(|(m, _)| *m <= remaining.mines)
+            .map(|(m, w)| w * weight[m] * (remaining.mines - m) as f64)
+            .sum();
+        let chance = expected / total / unconstrained.len() as f64;
+        odds.extend(unconstrained.iter().map(|pos| (*pos, chance)));
+    }
+
+    Ok(odds)
+}
+
+/// Split the frontier into groups of tiles linked by shared numbers, and
+/// enumerate each one.
+///
+/// Tiles are ordered breadth first, so numbers are closed off early and bad
+/// branches get cut quickly.
+fn split_groups(constraints: &[Constraint]) -> Vec<Group> {
+    let mut by_tile: HashMap<Position, Vec<usize>> = HashMap::new();
+    for (idx, constraint) in constraints.iter().enumerate() {
+        for pos in &constraint.tiles {
+            by_tile.entry(*pos).or_default().push(idx);
+        }
+    }
+
+    let mut groups = Vec::new();
+    let mut seen_tiles = HashSet::new();
+    let mut seen_constraints = HashSet::new();
+    for (start, constraint) in constraints.iter().enumerate() {
+        if !seen_constraints.insert(start) {
+            continue;
+        }
+        let mut tiles = Vec::new();
+        let mut members = vec![constraint];
+        let mut queue = VecDeque::from([start]);
+        while let Some(c_idx) = queue.pop_front() {
+            let mut c_tiles: Vec<&Position> = constraints[c_idx].tiles.iter().collect();
+            c_tiles.sort_by_key(|pos| (pos.z, pos.x, pos.y));
+            for pos in c_tiles {
+                if !seen_tiles.insert(*pos) {
+                    continue;
+                }
+                tiles.push(*pos);
+                for &next in &by_tile[pos] {
+                    if seen_constraints.insert(next) {
+                        members.push(&constraints[next]);
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+        groups.push(Group::enumerate(tiles, &members));
+    }
+    groups
+}
+
+/// Relative weight of having `m` of the `mines` left on the frontier, for
+/// every `m` up to `frontier`.
+///
+/// This is C(unconstrained, mines - m), scaled so the largest weight is 1 to
+/// keep it in range of an f64.
+fn outside_weights(unconstrained: usize, mines: usize, frontier: usize) -> Vec<f64> {
+    let mut ln_fact = vec![0.0; unconstrained + 1];
+    for n in 1..=unconstrained {
+        ln_fact[n] = ln_fact[n - 1] + (n as f64).ln();
+    }
+    let ln_choose = |m: usize| -> Option<f64> {
+        let outside = mines.checked_sub(m)?;
+        if outside > unconstrained {
+            return None;
+        }
+        Some(ln_fact[unconstrained] - ln_fact[outside] - ln_fact[unconstrained - outside])
+    };
+
+    let shift = (0..=frontier)
+        .filter_map(ln_choose)
+        .fold(f64::NEG_INFINITY, f64::max);
+    (0..=frontier)
+        .map(|m| ln_choose(m).map_or(0.0, |ln| (ln - shift).exp()))
+        .collect()
+}
+
+fn convolve(a: &[f64], b: &[f64]) -> Vec<f64> {
+    let mut out = vec![0.0; a.len() + b.len() - 1];
+    for (i, x) in a.iter().enumerate() {
+        for (j, y) in b.iter().enumerate() {
+            out[i + j] += x * y;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{StdMinrsGame, TileContents, TileState};
+
+    const EPSILON: f64 = 1e-9;
+
+    #[test]
+    fn test_fresh_board_odds_are_uniform() {
+        let game = StdMinrsGame::new_seeded(10, 8, 10, 3).unwrap();
+        let odds = mine_probabilities(&game).unwrap();
+        assert_eq!(odds.len(), 80);
+        assert!(odds.values().all(|p| (p - 10.0 / 80.0).abs() < EPSILON));
+    }
+
+    /// The top wall row of 1s fits exactly two layouts, so every tile but the
+    /// two provably safe ones is a coin flip.
+    #[test]
+    fn test_ambiguous_row_odds() {
+        let mines = [
+            Position::new(1, 0),
+            Position::new(4, 0),
+            Position::new(7, 0),
+        ];
+        let covered: Vec<Position> = (0..8).map(|x| Position::new(x, 0)).collect();
+        let game = StdMinrsGame::with_layout(8, 8, &mines, &covered);
+        let odds = mine_probabilities(&game).unwrap();
+        assert_eq!(odds.len(), 8);
+        for (pos, p) in odds {
+            let expected = if pos.x == 2 || pos.x == 5 { 0.0 } else { 0.5 };
+            assert!((p - expected).abs() < EPSILON, "{pos:?} = {p}");
+        }
+    }
+
+    /// Check against every possible layout of the two covered columns on the
+    /// left, where the first column touches no numbers at all.
+    #[test]
+    fn test_odds_match_brute_force() {
+        let mines = [
+            Position::new(0, 1),
+            Position::new(1, 2),
+            Position::new(0, 5),
+            Position::new(1, 7),
+        ];
+        let covered: Vec<Position> = (0..2)
+            .flat_map(|x| (0..8).map(move |y| Position::new(x, y)))
+            .collect();
+        let game = StdMinrsGame::with_layout(8, 8, &mines, &covered);
+
+        let mut layouts = 0.0;
+        let mut counts = vec![0.0; covered.len()];
+        for bits in 0u32..(1 << covered.len()) {
+            if bits.count_ones() != mines.len() as u32 {
+                continue;
+            }
+            let is_mine = |pos: &Position| {
+                covered
+                    .iter()
+                    .position(|c| c == pos)
+                    .is_some_and(|idx| bits & (1 << idx) != 0)
+            };
+            let fits = (0..8).all(|y| {
+                let pos = Position::new(2, y);
+                let shown = match game.get_tile_state(&pos).unwrap() {
+                    TileState::Uncovered(TileContents::MineCount(count)) => count as usize,
+                    _ => unreachable!(),
+                };
+                let neighbors = game.get_neighbors_pos(&pos).unwrap();
+                neighbors.iter().filter(|n| is_mine(n)).count() == shown
+            });
+            if fits {
+                layouts += 1.0;
+                for (idx, count) in counts.iter_mut().enumerate() {
+                    if bits & (1 << idx) != 0 {
+                        *count += 1.0;
+                    }
+                }
+            }
+        }
+
+        let odds = mine_probabilities(&game).unwrap();
+        for (idx, pos) in covered.iter().enumerate() {
+            let expected = counts[idx] / layouts;
+            assert!((odds[pos] - expected).abs() < EPSILON, "{pos:?}");
+        }
+    }
+
+    /// The odds over all covered tiles must add up to the mines left.
+    #[test]
+    fn test_odds_sum_to_remaining_mines() {
+        for seed in 0..10 {
+            let mut game = StdMinrsGame::new_seeded(30, 30, 99, seed).unwrap();
+            game.uncover_tile(&Position::new(15, 15)).unwrap();
+            let odds = mine_probabilities(&game).unwrap();
+            let covered = odds
+                .keys()
+                .filter(|pos| matches!(game.get_tile_state(pos).unwrap(), TileState::Covered(_)))
+                .count();
+            assert_eq!(covered, odds.len());
+            let sum: f64 = odds.values().sum();
+            assert!((sum - 99.0).abs() < 1e-6, "seed {seed}: {sum}");
+            assert!(odds.values().all(|p| (0.0..=1.0).contains(p)));
+        }
+    }
+}
@@ -0,0 +1,330 @@
+// Copyright 2022 nitepone <luna@night.horse>
+//
+// A line based protocol for playing from another program, such as a bot.
+//
+// Each command is one line of words, and gets exactly one reply:
+//
+//     new <width> <height> <mines> [options]
+//     new easy|medium|hard [options]
+//     open <x> <y>
+//     flag <x> <y>
+//     chord <x> <y>
+//     undo
+//     redo
+//     state
+//     board
+//     quit
+//
+// The options of `new` are any of `seed=<seed>`, `no_guess`, `wrap` and
+// `neighborhood=<name>`, with names as in saved games.
+//
+// Replies start with `ok` or `err`. Moves, `new` and `state` reply
+// `ok playing`, `ok won` or `ok lost`. If `no_guess` was asked for but no
+// board that can be cleared by logic was found, `no_guess_failed` follows.
+// `board` replies `ok <width> <height>` followed by a line per row, with a
+// character per tile as given by `TileState::to_char`. `quit` replies `ok`
+// and ends the session.
+//
+// Errors are `err <code>`, where the code is one of:
+//
+//     unknown_command    the command isn't one of the above
+//     syntax             the command's arguments couldn't be read
+//     no_game            no game was started with `new` yet
+//     blocked_by_flag    and the rest, after the MinrsError of the move
+//
+// Blank lines and lines starting with `#` are ignored, and get no reply.
+
+use crate::difficulty::GameDifficulty;
+use crate::error::MinrsError;
+use crate::game::{parse_word, GameStatus, MinrsGame, Neighborhood, Position, StdMinrsGame};
+use rand::Rng;
+use std::str::SplitWhitespace;
+
+/// A bad command, which the game never saw.
+enum CommandError {
+    Unknown,
+    Syntax,
+    NoGame,
+    Game(MinrsError),
+}
+
+impl From<MinrsError> for CommandError {
+    fn from(e: MinrsError) -> CommandError {
+        CommandError::Game(e)
+    }
+}
+
+impl CommandError {
+    fn code(&self) -> &'static str {
+        match self {
+            CommandError::Unknown => "unknown_command",
+            CommandError::Syntax => "syntax",
+            CommandError::NoGame => "no_game",
+            CommandError::Game(e) => e.code(),
+        }
+    }
+}
+
+/// One player's conversation with the engine.
+#[derive(Default)]
+pub struct Session {
+    game: Option<StdMinrsGame>,
+    finished: bool,
+}
+
+impl Session {
+    pub fn new() -> Session {
+        Session::default()
+    }
+
+    /// Check if the player has quit.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Get the game being played, if one was started.
+    pub fn get_game(&self) -> Option<&StdMinrsGame> {
+        self.game.as_ref()
+    }
+
+    /// Run one command, returning the reply without a trailing newline.
+    ///
+    /// Lines that aren't commands get no reply.
+    pub fn handle(&mut self, line: &str) -> Option<String> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        Some(match self.run(line) {
+            Ok(reply) => reply,
+            Err(e) => format!("err {}", e.code()),
+        })
+    }
+
+    fn run(&mut self, line: &str) -> Result<String, CommandError> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or_default();
+        match command {
+            "new" => {
+                self.game = Some(new_game(&mut words)?);
+                return self.status();
+            }
+            "quit" => {
+                self.finished = true;
+                return Ok("ok".to_string());
+            }
+            _ => {}
+        }
+
+        let game = self.game.as_mut().ok_or(CommandError::NoGame)?;
+        match command {
+            "open" => game.uncover_tile(&read_position(&mut words)?)?,
+            "flag" => game.cycle_flag(&read_position(&mut words)?)?,
+            "chord" => game.uncover_neighbors(&read_position(&mut words)?)?,
+            "undo" => game.undo()?,
+            "redo" => game.redo()?,
+            "state" => {}
+            "board" => return Ok(draw_board(game)),
+            _ => return Err(CommandError::Unknown),
+        }
+        self.status()
+    }
+
+    fn status(&self) -> Result<String, CommandError> {
+        let game = self.game.as_ref().ok_or(CommandError::NoGame)?;
+        let mut reply = format!("ok {}", GameStatus::of(game));
+        if game.is_no_guess_failed() {
+            reply += " no_guess_failed";
+        }
+        Ok(reply)
+    }
+}
+
+/// Start the game described by the arguments of `new`.
+fn new_game(words: &mut SplitWhitespace) -> Result<StdMinrsGame, CommandError> {
+    let first = words.next().ok_or(CommandError::Syntax)?;
+    let (width, height, mines) = match GameDifficulty::from_name(first) {
+        Some(diff) => (diff.get_width(), diff.get_height(), diff.get_mines()),
+        None => (
+            read_word(Some(first))?,
+            read_word(words.next())?,
+            read_word(words.next())?,
+        ),
+    };
+
+    let mut seed = rand::thread_rng().gen();
+    let mut no_guess = false;
+    let mut wrap = false;
+    let mut neighborhood = Neighborhood::Square;
+    for option in words {
+        match option.split_once('=') {
+            Some(("seed", value)) => seed = read_word(Some(value))?,
+            Some(("neighborhood", value)) => {
+                neighborhood = value.parse().map_err(|_| CommandError::Syntax)?
+            }
+            None if option == "no_guess" => no_guess = true,
+            None if option == "wrap" => wrap = true,
+            _ => return Err(CommandError::Syntax),
+        }
+    }
+
+    let mut game = StdMinrsGame::new_seeded(width, height, mines, seed)?;
+    game.set_neighborhood(neighborhood)?;
+    game.set_wrap(wrap)?;
+    game.set_no_guess(no_guess)?;
+    Ok(game)
+}
+
+fn read_word<T: std::str::FromStr>(word: Option<&str>) -> Result<T, CommandError> {
+    parse_word(word).map_err(|_| CommandError::Syntax)
+}
+
+/// Read the `<x> <y>` of a move, which must be all that is left.
+fn read_position(words: &mut SplitWhitespace) -> Result<Position, CommandError> {
+    let pos = Position::new(read_word(words.next())?, read_word(words.next())?);
+    if words.next().is_some() {
+        return Err(CommandError::Syntax);
+    }
+    Ok(pos)
+}
+
+/// Write the `board` reply, which is also what bots in the benchmark are shown.
+pub(crate) fn draw_board(game: &dyn MinrsGame) -> String {
+    let mut text = format!("ok {} {}", game.get_width(), game.get_height());
+    for y in 0..game.get_height() {
+        text.push('\n');
+        for x in 0..game.get_width() {
+            text.push(game.get_tile_state(&Position::new(x, y)).unwrap().to_char());
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replies(session: &mut Session, script: &str) -> Vec<String> {
+        script
+            .lines()
+            .filter_map(|line| session.handle(line))
+            .collect()
+    }
+
+    #[test]
+    fn test_session() {
+        let mut session = Session::new();
+        let script = "
+            # nothing to play yet
+            open 0 0
+            new 8 8 1 seed=3
+            open 4 4
+            flag 4 4
+            jump 1 1
+            open 1
+            undo
+            redo
+            state
+            quit
+        ";
+        assert_eq!(
+            replies(&mut session, script),
+            [
+                "err no_game",
+                "ok playing",
+                "ok playing",
+                "err invalid_position",
+                "err unknown_command",
+                "err syntax",
+                "ok playing",
+                "ok playing",
+                "ok playing",
+                "ok",
+            ]
+        );
+        assert!(session.is_finished());
+        let game = session.get_game().unwrap();
+        assert_eq!(game.get_seed(), Some(3));
+    }
+
+    #[test]
+    fn test_session_board_and_end() {
+        let mut session = Session::new();
+        assert_eq!(
+            session
+                .handle("new easy seed=7 neighborhood=cross")
+                .unwrap(),
+            "ok playing"
+        );
+        assert_eq!(
+            session.get_game().unwrap().get_neighborhood(),
+            &Neighborhood::Cross
+        );
+        assert_eq!(
+            session.handle("new 8 8 10 seed=7 hex").unwrap(),
+            "err syntax"
+        );
+        assert_eq!(
+            session.handle("new 8 8 64").unwrap(),
+            "err invalid_argument"
+        );
+
+        session.handle("new 8 8 1 seed=1").unwrap();
+        session.handle("open 0 0").unwrap();
+        let board = session.handle("board").unwrap();
+        let mut lines = board.lines();
+        assert_eq!(lines.next(), Some("ok 8 8"));
+        let rows: Vec<&str> = lines.collect();
+        assert_eq!(rows.len(), 8);
+        assert!(rows.iter().all(|row| row.len() == 8));
+
+        // clear what the opening left, then step on the mine
+        let mine = session.get_game().unwrap().get_mine_positions()[0];
+        let mut reply = String::new();
+        for (y, row) in rows.iter().enumerate() {
+            for (x, glyph) in row.chars().enumerate() {
+                if glyph == '#' && Position::new(x as u16, y as u16) != mine {
+                    reply = session.handle(&format!("open {x} {y}")).unwrap();
+                }
+            }
+        }
+        assert_eq!(reply, "ok won");
+        assert_eq!(session.handle("undo").unwrap(), "ok playing");
+        assert_eq!(
+            session
+                .handle(&format!("open {} {}", mine.x, mine.y))
+                .unwrap(),
+            "ok lost"
+        );
+        assert_eq!(session.handle("flag 0 0").unwrap(), "err game_over");
+        assert_eq!(session.handle("open 0 0").unwrap(), "err game_over");
+        assert_eq!(session.handle("chord 0 0").unwrap(), "err game_over");
+    }
+
+    #[test]
+    fn test_session_dense_first_move() {
+        // too dense to open on an empty tile by chance, which once recursed
+        // until the stack ran out
+        let mut session = Session::new();
+        session.handle("new 8 8 60 seed=1").unwrap();
+        assert_eq!(session.handle("open 0 0").unwrap(), "ok won");
+
+        // no room for an opening at all, but the first tile is still safe
+        session.handle("new 8 8 63 seed=1").unwrap();
+        assert_eq!(session.handle("open 3 3").unwrap(), "ok won");
+    }
+
+    #[test]
+    fn test_session_no_guess_failed() {
+        let mut session = Session::new();
+        session.handle("new 8 8 40 seed=5 no_guess").unwrap();
+        assert_eq!(
+            session.handle("open 4 4").unwrap(),
+            "ok playing no_guess_failed"
+        );
+        assert_eq!(
+            session.handle("state").unwrap(),
+            "ok playing no_guess_failed"
+        );
+    }
+}
@@ -0,0 +1,509 @@
+// Copyright 2022 nitepone <luna@night.horse>
+//
+// Several players racing to clear their own copies of one board.
+//
+// Every player's board is made from the same seed, with the same opening
+// already uncovered, so nobody's first click is luckier than anyone else's.
+// Players race on one screen, or over TCP through a server that hands out
+// the board and passes on how everyone is doing. Messages are lines of words.
+// Clients send:
+//
+//     progress <cleared> playing|won|lost
+//     new                                   (start a new race for everyone)
+//
+// and the server sends:
+//
+//     player <id>                           (sent once, to the client it names)
+//     race <width> <height> <mines> <seed>  (start racing on this board)
+//     progress <id> <cleared> playing|won|lost
+//     left <id>                             (a player went away)
+//
+// `cleared` counts the safe tiles a player has uncovered. A client that joins
+// late is sent the race, then the progress of everyone already in it. A race
+// that can't be built, or is more than MAX_RACE_SIDE tiles across, is not
+// read at all.
+
+use crate::coop::{read_line, Outbox};
+use crate::difficulty::GameDifficulty;
+use crate::error::{MinrsError, MinrsResult};
+use crate::game::{
+    parse_word, GameStatus, MinrsGame, Position, StdMinrsGame, TileContents, TileState,
+};
+use rand::Rng;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Widest or tallest board a race can be on. Races are on preset boards, so
+/// anything near this is a server gone wrong.
+pub const MAX_RACE_SIDE: u16 = 256;
+
+/// The board every racer gets a copy of.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Race {
+    pub width: u16,
+    pub height: u16,
+    pub mines: u32,
+    pub seed: u64,
+}
+
+impl Race {
+    /// Pick a random board of a preset size.
+    pub fn random(difficulty: GameDifficulty) -> Race {
+        Race {
+            width: difficulty.get_width(),
+            height: difficulty.get_height(),
+            mines: difficulty.get_mines(),
+            seed: rand::thread_rng().gen(),
+        }
+    }
+
+    /// Make a racer's copy of the board, with the middle already opened.
+    ///
+    /// throws InvalidArgument if the board can't hold its mines
+    pub fn new_game(&self) -> MinrsResult<StdMinrsGame> {
+        let mut game = StdMinrsGame::new_seeded(self.width, self.height, self.mines, self.seed)?;
+        game.uncover_tile(&Position::new(self.width / 2, self.height / 2))?;
+        Ok(game)
+    }
+
+    /// Get how many tiles have to be uncovered to win.
+    pub fn get_safe_tiles(&self) -> u32 {
+        (self.width as u32 * self.height as u32).saturating_sub(self.mines)
+    }
+}
+
+/// How far a racer has got.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Progress {
+    /// Safe tiles uncovered so far.
+    pub cleared: u32,
+    pub status: GameStatus,
+}
+
+impl Progress {
+    pub fn of(game: &dyn MinrsGame) -> Progress {
+        let cleared = game
+            .get_positions()
+            .iter()
+            .filter(|pos| {
+                matches!(
+                    game.get_tile_state(pos),
+                    Ok(TileState::Uncovered(TileContents::MineCount(_)))
+                )
+            })
+            .count();
+        Progress {
+            cleared: cleared as u32,
+            status: GameStatus::of(game),
+        }
+    }
+
+    /// Get how much of the race's board was cleared, from 0 to 100.
+    pub fn get_percent(&self, race: &Race) -> f64 {
+        match race.get_safe_tiles() {
+            0 => 100.0,
+            safe => self.cleared as f64 * 100.0 / safe as f64,
+        }
+    }
+}
+
+/// Something a racer tells the server.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ClientMessage {
+    Progress(Progress),
+    NewRace,
+}
+
+impl fmt::Display for ClientMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClientMessage::Progress(progress) => {
+                write!(f, "progress {} {}", progress.cleared, progress.status)
+            }
+            ClientMessage::NewRace => write!(f, "new"),
+        }
+    }
+}
+
+impl FromStr for ClientMessage {
+    type Err = MinrsError;
+
+    fn from_str(line: &str) -> MinrsResult<ClientMessage> {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("progress") => Ok(ClientMessage::Progress(Progress {
+                cleared: parse_word(words.next())?,
+                status: parse_word(words.next())?,
+            })),
+            Some("new") => Ok(ClientMessage::NewRace),
+            _ => Err(MinrsError::InvalidData),
+        }
+    }
+}
+
+/// Something the server tells racers.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ServerMessage {
+    /// The id of the client receiving this.
+    Player(u32),
+    Race(Race),
+    Progress(u32, Progress),
+    Left(u32),
+}
+
+impl fmt::Display for ServerMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ServerMessage::Player(id) => write!(f, "player {id}"),
+            ServerMessage::Race(race) => write!(
+                f,
+                "race {} {} {} {}",
+                race.width, race.height, race.mines, race.seed
+            ),
+            ServerMessage::Progress(id, progress) => {
+                write!(f, "progress {id} {} {}", progress.cleared, progress.status)
+            }
+            ServerMessage::Left(id) => write!(f, "left {id}"),
+        }
+    }
+}
+
+impl FromStr for ServerMessage {
+    type Err = MinrsError;
+
+    fn from_str(line: &str) -> MinrsResult<ServerMessage> {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("player") => Ok(ServerMessage::Player(parse_word(words.next())?)),
+            Some("race") => {
+                let race = Race {
+                    width: parse_word(words.next())?,
+                    height: parse_word(words.next())?,
+                    mines: parse_word(words.next())?,
+                    seed: parse_word(words.next())?,
+                };
+                // clients build the board straight away, so catch one that
+                // can't be built here
+                if race.width > MAX_RACE_SIDE
+                    || race.height > MAX_RACE_SIDE
+                    || race.new_game().is_err()
+                {
+                    return Err(MinrsError::InvalidData);
+                }
+                Ok(ServerMessage::Race(race))
+            }
+            Some("progress") => Ok(ServerMessage::Progress(
+                parse_word(words.next())?,
+                Progress {
+                    cleared: parse_word(words.next())?,
+                    status: parse_word(words.next())?,
+                },
+            )),
+            Some("left") => Ok(ServerMessage::Left(parse_word(words.next())?)),
+            _ => Err(MinrsError::InvalidData),
+        }
+    }
+}
+
+/// A connection to a race server.
+pub struct RaceClient {
+    stream: TcpStream,
+}
+
+impl RaceClient {
+    pub fn connect(addr: impl ToSocketAddrs) -> io::Result<RaceClient> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        Ok(RaceClient { stream })
+    }
+
+    pub fn send(&mut self, message: ClientMessage) -> io::Result<()> {
+        writeln!(self.stream, "{message}")
+    }
+
+    /// Leave the race, which also stops the thread reading messages.
+    pub fn disconnect(&self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+
+    /// Read messages from the server on a new thread, handing each to
+    /// `handler` until the connection closes.
+    ///
+    /// Lines that can't be read are skipped.
+    pub fn listen<F>(&self, mut handler: F) -> io::Result<thread::JoinHandle<()>>
+    where
+        F: FnMut(ServerMessage) + Send + 'static,
+    {
+        let reader = BufReader::new(self.stream.try_clone()?);
+        Ok(thread::spawn(move || {
+            for line in reader.lines() {
+                match line {
+                    Ok(line) => {
+                        if let Ok(message) = line.parse() {
+                            handler(message);
+                        }
+                    }
+                    Err(_) => return,
+                }
+            }
+        }))
+    }
+}
+
+/// What every connection's thread shares.
+struct Shared {
+    race: Race,
+    progress: BTreeMap<u32, Progress>,
+    clients: HashMap<u32, Outbox>,
+    next_player: u32,
+}
+
+impl Shared {
+    /// Send a message to every client, dropping any that went away.
+    fn broadcast(&mut self, message: &ServerMessage) {
+        let text = format!("{message}\n");
+        self.clients.retain(|_, outbox| outbox.send(text.clone()));
+    }
+}
+
+/// Hands out races, and passes on how each racer is doing.
+pub struct RaceServer {
+    listener: TcpListener,
+    difficulty: GameDifficulty,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl RaceServer {
+    /// Listen for racers, starting with a random board of the given size.
+    pub fn bind(addr: impl ToSocketAddrs, difficulty: GameDifficulty) -> io::Result<RaceServer> {
+        RaceServer::with_race(addr, difficulty, Race::random(difficulty))
+    }
+
+    /// Listen for racers of a board that was already picked. Later races are
+    /// random boards of the same difficulty.
+    pub fn with_race(
+        addr: impl ToSocketAddrs,
+        difficulty: GameDifficulty,
+        race: Race,
+    ) -> io::Result<RaceServer> {
+        Ok(RaceServer {
+            listener: TcpListener::bind(addr)?,
+            difficulty,
+            shared: Arc::new(Mutex::new(Shared {
+                race,
+                progress: BTreeMap::new(),
+                clients: HashMap::new(),
+                next_player: 0,
+            })),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accept racers until the listener fails, with a thread for each.
+    pub fn run(self) -> io::Result<()> {
+        for stream in self.listener.incoming() {
+            let stream = stream?;
+            let shared = self.shared.clone();
+            let difficulty = self.difficulty;
+            thread::spawn(move || {
+                let _ = serve_client(stream, &shared, difficulty);
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Catch a new racer up, then pass on their progress until they leave.
+fn serve_client(
+    stream: TcpStream,
+    shared: &Mutex<Shared>,
+    difficulty: GameDifficulty,
+) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let player = {
+        let mut shared = shared.lock().unwrap();
+        let player = shared.next_player;
+        shared.next_player += 1;
+        let mut text = format!("{}\n", ServerMessage::Player(player));
+        text += &format!("{}\n", ServerMessage::Race(shared.race));
+        for (id, progress) in &shared.progress {
+            text += &format!("{}\n", ServerMessage::Progress(*id, *progress));
+        }
+        let outbox = Outbox::new(&stream)?;
+        outbox.send(text);
+        shared.clients.insert(player, outbox);
+        player
+    };
+
+    let raced = pass_on_progress(&mut reader, shared, difficulty, player);
+    let mut shared = shared.lock().unwrap();
+    shared.clients.remove(&player);
+    shared.progress.remove(&player);
+    shared.broadcast(&ServerMessage::Left(player));
+    let _ = stream.shutdown(Shutdown::Both);
+    raced
+}
+
+/// Pass on a racer's progress as it comes in.
+fn pass_on_progress(
+    reader: &mut impl BufRead,
+    shared: &Mutex<Shared>,
+    difficulty: GameDifficulty,
+    player: u32,
+) -> io::Result<()> {
+    let mut line = String::new();
+    while read_line(reader, &mut line)? {
+        let mut shared = shared.lock().unwrap();
+        match line.parse() {
+            Ok(ClientMessage::Progress(progress)) => {
+                shared.progress.insert(player, progress);
+                shared.broadcast(&ServerMessage::Progress(player, progress));
+            }
+            Ok(ClientMessage::NewRace) => {
+                shared.race = Race::random(difficulty);
+                shared.progress.clear();
+                let race = shared.race;
+                shared.broadcast(&ServerMessage::Race(race));
+            }
+            // a racer that can't be understood is only hurting themself
+            Err(_) => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::{self, Receiver};
+    use std::time::Duration;
+
+    fn join(addr: SocketAddr) -> (RaceClient, Receiver<ServerMessage>) {
+        let client = RaceClient::connect(addr).unwrap();
+        let (sender, inbox) = mpsc::channel();
+        client
+            .listen(move |message| {
+                let _ = sender.send(message);
+            })
+            .unwrap();
+        (client, inbox)
+    }
+
+    fn next(inbox: &Receiver<ServerMessage>) -> ServerMessage {
+        inbox.recv_timeout(Duration::from_secs(5)).unwrap()
+    }
+
+    #[test]
+    fn test_race_boards_match() {
+        let race = Race {
+            width: 16,
+            height: 16,
+            mines: 40,
+            seed: 99,
+        };
+        let mut first = race.new_game().unwrap();
+        let second = race.new_game().unwrap();
+        assert_eq!(first.get_mine_positions(), second.get_mine_positions());
+        let progress = Progress::of(&first);
+        assert!(progress.cleared > 0);
+        assert_eq!(progress.status, GameStatus::Playing);
+        assert_eq!(Progress::of(&second), progress);
+
+        let mine = first.get_mine_positions()[0];
+        first.uncover_tile(&mine).unwrap();
+        assert_eq!(Progress::of(&first).status, GameStatus::Lost);
+        let done = Progress {
+            cleared: race.get_safe_tiles(),
+            status: GameStatus::Won,
+        };
+        assert_eq!(done.get_percent(&race), 100.0);
+
+        let overfull = Race { mines: 300, ..race };
+        assert_eq!(overfull.get_safe_tiles(), 0);
+    }
+
+    #[test]
+    fn test_message_round_trip() {
+        let progress = Progress {
+            cleared: 12,
+            status: GameStatus::Lost,
+        };
+        let messages = [
+            ServerMessage::Player(2),
+            ServerMessage::Race(Race {
+                width: 30,
+                height: 16,
+                mines: 99,
+                seed: u64::MAX,
+            }),
+            ServerMessage::Progress(1, progress),
+            ServerMessage::Left(4),
+        ];
+        for message in messages {
+            assert_eq!(
+                message.to_string().parse::<ServerMessage>().unwrap(),
+                message
+            );
+        }
+        for message in [ClientMessage::Progress(progress), ClientMessage::NewRace] {
+            assert_eq!(
+                message.to_string().parse::<ClientMessage>().unwrap(),
+                message
+            );
+        }
+        assert!("progress 3 exploded".parse::<ClientMessage>().is_err());
+        for bad_race in ["race 0 0 0 1", "race 8 8 64 1", "race 60000 60000 10 1"] {
+            assert!(bad_race.parse::<ServerMessage>().is_err());
+        }
+    }
+
+    #[test]
+    fn test_race_over_loopback() {
+        let race = Race::random(GameDifficulty::Easy);
+        let server = RaceServer::with_race("127.0.0.1:0", GameDifficulty::Easy, race).unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+
+        let (mut alice, alice_inbox) = join(addr);
+        assert_eq!(next(&alice_inbox), ServerMessage::Player(0));
+        assert_eq!(next(&alice_inbox), ServerMessage::Race(race));
+        let progress = Progress {
+            cleared: 20,
+            status: GameStatus::Playing,
+        };
+        alice.send(ClientMessage::Progress(progress)).unwrap();
+        assert_eq!(next(&alice_inbox), ServerMessage::Progress(0, progress));
+
+        // a late joiner is told how far everyone already got
+        let (mut bob, bob_inbox) = join(addr);
+        assert_eq!(next(&bob_inbox), ServerMessage::Player(1));
+        assert_eq!(next(&bob_inbox), ServerMessage::Race(race));
+        assert_eq!(next(&bob_inbox), ServerMessage::Progress(0, progress));
+
+        let exploded = Progress {
+            cleared: 3,
+            status: GameStatus::Lost,
+        };
+        bob.send(ClientMessage::Progress(exploded)).unwrap();
+        assert_eq!(next(&alice_inbox), ServerMessage::Progress(1, exploded));
+
+        // anyone can call the next race, which starts everyone over
+        bob.send(ClientMessage::NewRace).unwrap();
+        let ServerMessage::Race(new_race) = next(&alice_inbox) else {
+            panic!("expected a new race");
+        };
+        assert_eq!(new_race.width, race.width);
+
+        bob.disconnect();
+        assert_eq!(next(&alice_inbox), ServerMessage::Left(1));
+    }
+}
@@ -0,0 +1,684 @@
+// Copyright 2022 nitepone <luna@night.horse>
+//
+// Recording games as a list of moves, and playing them back.
+//
+// A replay is plain text, one line per record:
+//
+//     min-rs-weeper replay 1
+//     size <width> <height> <mines>
+//     depth <layers>                  (if 3D)
+//     seed <seed> <no_guess 0|1>      (or)   mines <x>,<y> <x>,<y> ...
+//                                     (or)   board <row> <row> ...
+//     neighborhood <name>             (unless square)
+//     wrap                            (if the edges wrap)
+//     mines_per_tile <max>            (if more than 1)
+//     <millis> open <x> <y>
+//     <millis> chord <x> <y>
+//     <millis> flag <x> <y>
+//     <millis> undo
+//     <millis> redo
+//
+// Moves are timestamped in milliseconds since recording started. On a 3D
+// board, positions also give the layer, as `<x> <y> <z>` or `<x>,<y>,<z>`.
+// A tile holding several mines is listed once for each in the mines line.
+// A board that started with tiles already uncovered or flagged is drawn on
+// the board line instead, a row at a time as read by
+// `StdMinrsGame::from_ascii`, and layer after layer.
+
+use crate::error::{MinrsError, MinrsResult};
+use crate::game::{
+    parse_word, Listener, MinrsGame, Neighborhood, Position, StdMinrsGame, TileState,
+};
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Instant;
+
+const REPLAY_MAGIC: &str = "min-rs-weeper replay";
+const REPLAY_VERSION: u32 = 1;
+
+/// A move made by the player.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Action {
+    Uncover(Position),
+    /// Uncover the neighbors of a number.
+    Chord(Position),
+    CycleFlag(Position),
+    Undo,
+    Redo,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct TimedAction {
+    /// Milliseconds since the recording started.
+    pub millis: u64,
+    pub action: Action,
+}
+
+/// How to rebuild the mine layout of a recorded game.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Layout {
+    Seed {
+        seed: u64,
+        no_guess: bool,
+    },
+    Mines(Vec<Position>),
+    /// The rows of every layer, as drawn by `StdMinrsGame::to_ascii`, for a
+    /// board that didn't start fully covered.
+    Board(Vec<String>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Replay {
+    pub width: u16,
+    pub height: u16,
+    pub depth: u16,
+    pub mine_count: u32,
+    pub layout: Layout,
+    pub neighborhood: Neighborhood,
+    pub wrap: bool,
+    pub mines_per_tile: u8,
+    pub actions: Vec<TimedAction>,
+}
+
+impl Replay {
+    /// Start a replay of a fresh game.
+    pub fn from_game(game: &StdMinrsGame) -> Replay {
+        let layout = match game.get_seed() {
+            Some(seed) => Layout::Seed {
+                seed,
+                no_guess: game.is_no_guess(),
+            },
+            None if game
+                .get_positions()
+                .iter()
+                .all(|pos| game.get_tile_state(pos).ok() == Some(TileState::Covered(None))) =>
+            {
+                Layout::Mines(game.get_mine_positions())
+            }
+            None => Layout::Board(game.to_ascii().lines().map(str::to_string).collect()),
+        };
+        Replay {
+            width: game.get_width(),
+            height: game.get_height(),
+            depth: game.get_depth(),
+            mine_count: game.get_mine_count(),
+            layout,
+            neighborhood: game.get_neighborhood().clone(),
+            wrap: game.is_wrapping(),
+            mines_per_tile: game.get_mines_per_tile(),
+            actions: Vec::new(),
+        }
+    }
+
+    /// Build the game as it was before any move.
+    pub fn new_game(&self) -> MinrsResult<StdMinrsGame> {
+        let mut game = match &self.layout {
+            Layout::Seed { seed, no_guess } => {
+                let mut game = StdMinrsGame::new_3d_seeded(
+                    self.width,
+                    self.height,
+                    self.depth,
+                    self.mine_count,
+                    *seed,
+                )?;
+                game.set_no_guess(*no_guess)?;
+                game
+            }
+            Layout::Mines(mines) => {
+                let mut unique = Vec::new();
+                for pos in mines {
+                    if !unique.contains(pos) {
+                        unique.push(*pos);
+                    }
+                }
+                StdMinrsGame::from_mines_3d(self.width, self.height, self.depth, &unique)?
+            }
+            Layout::Board(rows) => {
+                let layers: Vec<String> = rows
+                    .chunks(self.height.max(1) as usize)
+                    .map(|layer| layer.join("\n"))
+                    .collect();
+                let game = StdMinrsGame::from_ascii(&layers.join("\n\n"))?;
+                if (game.get_width(), game.get_height(), game.get_depth())
+                    != (self.width, self.height, self.depth)
+                {
+                    return Err(MinrsError::InvalidData);
+                }
+                game
+            }
+        };
+        game.set_neighborhood(self.neighborhood.clone())?;
+        game.set_wrap(self.wrap)?;
+        game.set_mines_per_tile(self.mines_per_tile)?;
+        if let Layout::Mines(mines) = &self.layout {
+            // stack the mines of tiles listed more than once
+            let mut seen = HashSet::new();
+            for pos in mines {
+                if !seen.insert(pos) {
+                    game.add_mine(pos)?;
+                }
+            }
+        }
+        Ok(game)
+    }
+}
+
+impl Action {
+    /// Make this move on a game.
+    pub fn apply(&self, game: &mut dyn MinrsGame) -> MinrsResult<()> {
+        match self {
+            Action::Uncover(pos) => game.uncover_tile(pos),
+            Action::Chord(pos) => game.uncover_neighbors(pos),
+            Action::CycleFlag(pos) => game.cycle_flag(pos),
+            Action::Undo => game.undo(),
+            Action::Redo => game.redo(),
+        }
+    }
+}
+
+impl fmt::Display for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{REPLAY_MAGIC} {REPLAY_VERSION}")?;
+        writeln!(f, "size {} {} {}", self.width, self.height, self.mine_count)?;
+        if self.depth > 1 {
+            writeln!(f, "depth {}", self.depth)?;
+        }
+        match &self.layout {
+            Layout::Seed { seed, no_guess } => writeln!(f, "seed {seed} {}", *no_guess as u8)?,
+            Layout::Mines(mines) => {
+                write!(f, "mines")?;
+                for pos in mines {
+                    write!(f, " {},{}", pos.x, pos.y)?;
+                    if pos.z > 0 {
+                        write!(f, ",{}", pos.z)?;
+                    }
+                }
+                writeln!(f)?;
+            }
+            Layout::Board(rows) => writeln!(f, "board {}", rows.join(" "))?,
+        }
+        if self.neighborhood != Neighborhood::Square {
+            writeln!(f, "neighborhood {}", self.neighborhood)?;
+        }
+        if self.wrap {
+            writeln!(f, "wrap")?;
+        }
+        if self.mines_per_tile > 1 {
+            writeln!(f, "mines_per_tile {}", self.mines_per_tile)?;
+        }
+        for timed in &self.actions {
+            write!(f, "{} ", timed.millis)?;
+            let pos = match timed.action {
+                Action::Uncover(pos) => {
+                    write!(f, "open")?;
+                    pos
+                }
+                Action::Chord(pos) => {
+                    write!(f, "chord")?;
+                    pos
+                }
+                Action::CycleFlag(pos) => {
+                    write!(f, "flag")?;
+                    pos
+                }
+                Action::Undo => {
+                    writeln!(f, "undo")?;
+                    continue;
+                }
+                Action::Redo => {
+                    writeln!(f, "redo")?;
+                    continue;
+                }
+            };
+            write!(f, " {} {}", pos.x, pos.y)?;
+            if pos.z > 0 {
+                write!(f, " {}", pos.z)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse `<x> <y>`, with an optional `<z>` after.
+fn parse_position<'a>(mut words: impl Iterator<Item = &'a str>) -> MinrsResult<Position> {
+    let x = parse_word(words.next())?;
+    let y = parse_word(words.next())?;
+    let z = match words.next() {
+        Some(word) => parse_word(Some(word))?,
+        None => 0,
+    };
+    Ok(Position::new_3d(x, y, z))
+}
+
+impl FromStr for Replay {
+    type Err = MinrsError;
+
+    fn from_str(text: &str) -> MinrsResult<Replay> {
+        let mut lines = text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .peekable();
+
+        let header = lines.next().ok_or(MinrsError::InvalidData)?;
+        let version = header
+            .strip_prefix(REPLAY_MAGIC)
+            .ok_or(MinrsError::InvalidData)?;
+        if parse_word::<u32>(Some(version.trim()))? != REPLAY_VERSION {
+            return Err(MinrsError::InvalidData);
+        }
+
+        let mut size = lines
+            .next()
+            .ok_or(MinrsError::InvalidData)?
+            .split_whitespace();
+        if size.next() != Some("size") {
+            return Err(MinrsError::InvalidData);
+        }
+        let width = parse_word(size.next())?;
+        let height = parse_word(size.next())?;
+        let mine_count = parse_word(size.next())?;
+        let mut depth = 1;
+        if let Some(line) = lines.next_if(|line| line.starts_with("depth")) {
+            depth = parse_word(line.split_whitespace().nth(1))?;
+        }
+
+        let mut layout_words = lines
+            .next()
+            .ok_or(MinrsError::InvalidData)?
+            .split_whitespace();
+        let layout = match layout_words.next() {
+            Some("seed") => Layout::Seed {
+                seed: parse_word(layout_words.next())?,
+                no_guess: parse_word::<u8>(layout_words.next())? != 0,
+            },
+            Some("mines") => Layout::Mines(
+                layout_words
+                    .map(|word| parse_position(word.split(',')))
+                    .collect::<MinrsResult<Vec<Position>>>()?,
+            ),
+            Some("board") => Layout::Board(layout_words.map(str::to_string).collect()),
+            _ => return Err(MinrsError::InvalidData),
+        };
+
+        let mut neighborhood = Neighborhood::Square;
+        if let Some(line) = lines.next_if(|line| line.starts_with("neighborhood")) {
+            neighborhood = parse_word(line.split_whitespace().nth(1))?;
+        }
+        let wrap = lines.next_if(|line| line.trim() == "wrap").is_some();
+        let mut mines_per_tile = 1;
+        if let Some(line) = lines.next_if(|line| line.starts_with("mines_per_tile")) {
+            mines_per_tile = parse_word(line.split_whitespace().nth(1))?;
+        }
+
+        let mut actions = Vec::new();
+        for line in lines {
+            let mut words = line.split_whitespace();
+            let millis = parse_word(words.next())?;
+            let action = match words.next() {
+                Some("open") => Action::Uncover(parse_position(words)?),
+                Some("chord") => Action::Chord(parse_position(words)?),
+                Some("flag") => Action::CycleFlag(parse_position(words)?),
+                Some("undo") => Action::Undo,
+                Some("redo") => Action::Redo,
+                _ => return Err(MinrsError::InvalidData),
+            };
+            actions.push(TimedAction { millis, action });
+        }
+
+        Ok(Replay {
+            width,
+            height,
+            depth,
+            mine_count,
+            layout,
+            neighborhood,
+            wrap,
+            mines_per_tile,
+            actions,
+        })
+    }
+}
+
+/// Wraps a game, noting down every move that succeeds.
+pub struct Recorder<G: MinrsGame> {
+    game: G,
+    replay: Replay,
+    start: Instant,
+}
+
+impl Recorder<StdMinrsGame> {
+    /// Start recording a fresh game.
+    pub fn new(game: StdMinrsGame) -> Recorder<StdMinrsGame> {
+        let replay = Replay::from_game(&game);
+        Recorder::resume(game, replay)
+    }
+}
+
+impl<G: MinrsGame> Recorder<G> {
+    /// Keep recording a game already in the state `replay` leads to.
+    ///
+    /// New moves are timed as if they came straight after the last one.
+    pub fn resume(game: G, replay: Replay) -> Recorder<G> {
+        let elapsed = replay.actions.last().map_or(0, |timed| timed.millis);
+        Recorder {
+            game,
+            replay,
+            start: Instant::now() - std::time::Duration::from_millis(elapsed),
+        }
+    }
+
+    pub fn get_game(&self) -> &G {
+        &self.game
+    }
+
+    pub fn get_replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    fn record(&mut self, action: Action) -> MinrsResult<()> {
+        action.apply(&mut self.game)?;
+        self.replay.actions.push(TimedAction {
+            millis: self.start.elapsed().as_millis() as u64,
+            action,
+        });
+        Ok(())
+    }
+}
+
+impl<G: MinrsGame> MinrsGame for Recorder<G> {
+    fn game_over(&self) -> bool {
+        self.game.game_over()
+    }
+
+    fn cycle_flag(&mut self, position: &Position) -> MinrsResult<()> {
+        self.record(Action::CycleFlag(*position))
+    }
+
+    fn uncover_tile(&mut self, position: &Position) -> MinrsResult<()> {
+        self.record(Action::Uncover(*position))
+    }
+
+    fn uncover_neighbors(&mut self, position: &Position) -> MinrsResult<()> {
+        self.record(Action::Chord(*position))
+    }
+
+    fn get_tile_state(&self, position: &Position) -> MinrsResult<TileState> {
+        self.game.get_tile_state(position)
+    }
+
+    fn get_neighbors_pos(&self, position: &Position) -> MinrsResult<Vec<Position>> {
+        self.game.get_neighbors_pos(position)
+    }
+
+    fn get_width(&self) -> u16 {
+        self.game.get_width()
+    }
+
+    fn get_height(&self) -> u16 {
+        self.game.get_height()
+    }
+
+    fn get_depth(&self) -> u16 {
+        self.game.get_depth()
+    }
+
+    fn get_mine_count(&self) -> u32 {
+        self.game.get_mine_count()
+    }
+
+    fn get_mines_per_tile(&self) -> u8 {
+        self.game.get_mines_per_tile()
+    }
+
+    fn victory(&self) -> bool {
+        self.game.victory()
+    }
+
+    fn undo(&mut self) -> MinrsResult<()> {
+        self.record(Action::Undo)
+    }
+
+    fn redo(&mut self) -> MinrsResult<()> {
+        self.record(Action::Redo)
+    }
+
+    fn subscribe(&mut self, listener: Listener) {
+        self.game.subscribe(listener);
+    }
+}
+
+/// Steps through a replay, rebuilding the game after any number of moves.
+pub struct Player {
+    replay: Replay,
+    game: StdMinrsGame,
+    played: usize,
+}
+
+impl Player {
+    pub fn new(replay: Replay) -> MinrsResult<Player> {
+        Ok(Player {
+            game: replay.new_game()?,
+            replay,
+            played: 0,
+        })
+    }
+
+    /// Make the next recorded move, returning it.
+    ///
+    /// Returns None once every move has been played.
+    pub fn step(&mut self) -> MinrsResult<Option<TimedAction>> {
+        let timed = match self.replay.actions.get(self.played) {
+            Some(timed) => *timed,
+            None => return Ok(None),
+        };
+        timed.action.apply(&mut self.game)?;
+        self.played += 1;
+        Ok(Some(timed))
+    }
+
+    /// Rebuild the game as it was after the first `played` moves.
+    pub fn seek(&mut self, played: usize) -> MinrsResult<()> {
+        if played > self.replay.actions.len() {
+            return Err(MinrsError::InvalidArgument);
+        }
+        if played < self.played {
+            self.game = self.replay.new_game()?;
+            self.played = 0;
+        }
+        while self.played < played {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    pub fn get_game(&self) -> &StdMinrsGame {
+        &self.game
+    }
+
+    /// Get how many moves have been played so far.
+    pub fn get_played(&self) -> usize {
+        self.played
+    }
+
+    /// Carry on recording from the current move, dropping any moves after it.
+    pub fn into_recorder(mut self) -> Recorder<StdMinrsGame> {
+        self.replay.actions.truncate(self.played);
+        Recorder::resume(self.game, self.replay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board_states(game: &dyn MinrsGame) -> Vec<TileState> {
+        game.get_positions()
+            .iter()
+            .map(|pos| game.get_tile_state(pos).unwrap())
+            .collect()
+    }
+
+    fn record_game(game: StdMinrsGame) -> Recorder<StdMinrsGame> {
+        let mut recorder = Recorder::new(game);
+        recorder.uncover_tile(&Position::new(5, 5)).unwrap();
+        recorder.cycle_flag(&Position::new(0, 0)).unwrap();
+        // rejected moves are not recorded
+        assert!(recorder.uncover_neighbors(&Position::new(0, 0)).is_err());
+        recorder.cycle_flag(&Position::new(0, 0)).unwrap();
+        recorder.undo().unwrap();
+        recorder.redo().unwrap();
+        recorder
+    }
+
+    #[test]
+    fn test_replay_round_trip() {
+        let mut game = StdMinrsGame::new_seeded(12, 10, 20, 1234).unwrap();
+        game.set_no_guess(true).unwrap();
+        let recorder = record_game(game);
+        let replay = recorder.get_replay();
+        assert_eq!(replay.actions.len(), 5);
+        assert_eq!(
+            replay.layout,
+            Layout::Seed {
+                seed: 1234,
+                no_guess: true
+            }
+        );
+
+        let text = replay.to_string();
+        assert!(text.starts_with("min-rs-weeper replay 1\n"));
+        assert_eq!(&text.parse::<Replay>().unwrap(), replay);
+    }
+
+    /// A board set up part way through is replayed from where it started.
+    #[test]
+    fn test_ascii_layout_round_trip() {
+        let layout = "
+            F.......
+            ........
+            ...___..
+            ...___..
+            ...___..
+            ........
+            ........
+            .......*
+        ";
+        let game = StdMinrsGame::from_ascii(layout).unwrap();
+        let start = game.to_ascii();
+        let mut recorder = Recorder::new(game);
+        recorder.cycle_flag(&Position::new(7, 7)).unwrap();
+        recorder.uncover_tile(&Position::new(1, 1)).unwrap();
+        let replay = recorder.get_replay();
+        assert!(matches!(replay.layout, Layout::Board(_)));
+        let text = replay.to_string();
+        assert!(text.contains("\nboard F....... "));
+        let parsed: Replay = text.parse().unwrap();
+        assert_eq!(&parsed, replay);
+        assert_eq!(parsed.new_game().unwrap().to_ascii(), start);
+
+        let mut player = Player::new(parsed).unwrap();
+        while player.step().unwrap().is_some() {}
+        assert_eq!(
+            board_states(player.get_game()),
+            board_states(recorder.get_game())
+        );
+    }
+
+    #[test]
+    fn test_explicit_layout_round_trip() {
+        let mines = [Position::new(0, 0), Position::new(7, 7)];
+        let mut game = StdMinrsGame::from_mines(8, 8, &mines).unwrap();
+        game.set_neighborhood(Neighborhood::Hex).unwrap();
+        let recorder = record_game(game);
+        let replay = recorder.get_replay();
+        assert_eq!(replay.layout, Layout::Mines(mines.to_vec()));
+        let text = replay.to_string();
+        assert!(text.contains("\nneighborhood hex\n"));
+        assert_eq!(&text.parse::<Replay>().unwrap(), replay);
+        assert_eq!(
+            replay.new_game().unwrap().get_neighborhood(),
+            &Neighborhood::Hex
+        );
+    }
+
+    #[test]
+    fn test_3d_replay_round_trip() {
+        let mines = [Position::new_3d(0, 0, 1), Position::new(7, 7)];
+        let game = StdMinrsGame::from_mines_3d(8, 8, 2, &mines).unwrap();
+        let mut recorder = Recorder::new(game);
+        recorder.uncover_tile(&Position::new_3d(3, 3, 1)).unwrap();
+        recorder.cycle_flag(&Position::new_3d(0, 0, 1)).unwrap();
+        let text = recorder.get_replay().to_string();
+        assert!(text.contains("\ndepth 2\n"));
+        assert!(text.contains("mines 7,7 0,0,1\n"));
+        assert!(text.contains(" flag 0 0 1\n"));
+
+        let mut player = Player::new(text.parse().unwrap()).unwrap();
+        while player.step().unwrap().is_some() {}
+        assert_eq!(board_states(player.get_game()), board_states(&recorder));
+    }
+
+    #[test]
+    fn test_stacked_mines_replay_round_trip() {
+        let mut game = StdMinrsGame::from_mines(8, 8, &[Position::new(0, 0)]).unwrap();
+        game.set_mines_per_tile(2).unwrap();
+        game.add_mine(&Position::new(0, 0)).unwrap();
+        let mut recorder = Recorder::new(game);
+        recorder.uncover_tile(&Position::new(1, 1)).unwrap();
+        let text = recorder.get_replay().to_string();
+        assert!(text.contains("mines 0,0 0,0\nmines_per_tile 2\n"));
+
+        let mut player = Player::new(text.parse().unwrap()).unwrap();
+        assert_eq!(player.get_game().get_mine_count(), 2);
+        while player.step().unwrap().is_some() {}
+        assert_eq!(board_states(player.get_game()), board_states(&recorder));
+    }
+
+    #[test]
+    fn test_player_rebuilds_every_state() {
+        let mut game = StdMinrsGame::new_seeded(16, 16, 40, 77).unwrap();
+        game.set_wrap(true).unwrap();
+        let mut recorder = Recorder::new(game);
+        let mut states = vec![board_states(&recorder)];
+        for (x, y) in [(8, 8), (0, 0), (15, 15), (3, 12)] {
+            let pos = Position::new(x, y);
+            if recorder.uncover_tile(&pos).is_ok() {
+                states.push(board_states(&recorder));
+            }
+        }
+
+        let text = recorder.get_replay().to_string();
+        let mut player = Player::new(text.parse().unwrap()).unwrap();
+        assert!(player.get_game().is_wrapping());
+        player.seek(states.len() - 1).unwrap();
+        for played in (0..states.len()).rev() {
+            player.seek(played).unwrap();
+            assert_eq!(player.get_played(), played);
+            assert_eq!(board_states(player.get_game()), states[played]);
+        }
+        assert!(player.seek(states.len()).is_err());
+
+        // playing on from a replay keeps the earlier moves
+        player.seek(1).unwrap();
+        let mut recorder = player.into_recorder();
+        assert_eq!(recorder.get_replay().actions.len(), 1);
+        recorder.undo().unwrap();
+        assert_eq!(board_states(&recorder), states[0]);
+    }
+
+    #[test]
+    fn test_bad_replays() {
+        assert!("".parse::<Replay>().is_err());
+        assert!("min-rs-weeper replay 2\nsize 8 8 1\nseed 1 0\n"
+            .parse::<Replay>()
+            .is_err());
+        assert!("min-rs-weeper replay 1\nsize 8 8 1\nseed 1 0\n5 jump 1 1\n"
+            .parse::<Replay>()
+            .is_err());
+        assert!("min-rs-weeper replay 1\nsize 8 8 1\nseed 1 0\n5 open 1\n"
+            .parse::<Replay>()
+            .is_err());
+    }
+}
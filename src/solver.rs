@@ -0,0 +1,262 @@
+// Copyright 2022 nitepone <luna@night.horse>
+//
+// A logical solver that only looks at what a player could see.
+
+use crate::error::{MinrsError, MinrsResult};
+use crate::game::{MinrsGame, Position, TileContents, TileState};
+use std::collections::{HashMap, HashSet};
+
+/// Covered tiles whose contents can be proven from the uncovered numbers.
+#[derive(Debug, Default)]
+pub struct Deductions {
+    /// Covered tiles that can not hold a mine.
+    pub safe: HashSet<Position>,
+    /// Covered tiles that must hold a mine.
+    pub mines: HashSet<Position>,
+}
+
+/// Exactly `mines` of `tiles` are mines.
+///
+/// Every uncovered number gives one of these, and the board's total mine
+/// count gives one more over all covered tiles.
+pub(crate) struct Constraint {
+    pub(crate) tiles: HashSet<Position>,
+    pub(crate) mines: usize,
+}
+
+impl Constraint {
+    /// Drop tiles already deduced, lowering the mine count for known mines.
+    pub(crate) fn reduce(&self, deductions: &Deductions) -> Constraint {
+        let known_mines = self
+            .tiles
+            .iter()
+            .filter(|pos| deductions.mines.contains(pos))
+            .count();
+        Constraint {
+            tiles: self
+                .tiles
+                .iter()
+                .filter(|pos| !deductions.safe.contains(pos) && !deductions.mines.contains(pos))
+                .copied()
+                .collect(),
+            mines: self.mines.saturating_sub(known_mines),
+        }
+    }
+}
+
+/// Find every covered tile whose contents follow from the uncovered numbers
+/// and the remaining mine count.
+///
+/// This uses the single number rules first, and only falls back to comparing
+/// pairs of overlapping numbers once those are exhausted.
+/// Flags are ignored, since the player may have placed them wrongly.
+///
+/// throws InvalidArgument if a tile can hold more than one mine.
+pub fn solve(game: &dyn MinrsGame) -> MinrsResult<Deductions> {
+    let constraints = get_constraints(game)?;
+    let mut deductions = Deductions::default();
+
+    loop {
+        let reduced: Vec<Constraint> = constraints
+            .iter()
+            .map(|constraint| constraint.reduce(&deductions))
+            .filter(|constraint| !constraint.tiles.is_empty())
+            .collect();
+        if !apply_single_rules(&reduced, &mut deductions)
+            && !apply_pair_rules(&reduced, &mut deductions)
+        {
+            break;
+        }
+    }
+
+    Ok(deductions)
+}
+
+/// Get a single tile that is proven safe, if there is one.
+pub fn hint(game: &dyn MinrsGame) -> MinrsResult<Option<Position>> {
+    Ok(solve(game)?
+        .safe
+        .into_iter()
+        .min_by_key(|pos| (pos.z, pos.x, pos.y)))
+}
+
+/// A number with no unknown mines left is all safe, and a number with as many
+/// mines as tiles is all mines.
+fn apply_single_rules(constraints: &[Constraint], deductions: &mut Deductions) -> bool {
+    let mut progress = false;
+    for constraint in constraints {
+        if constraint.mines == 0 {
+            deductions.safe.extend(&constraint.tiles);
+            progress = true;
+        } else if constraint.mines == constraint.tiles.len() {
+            deductions.mines.extend(&constraint.tiles);
+            progress = true;
+        }
+    }
+    progress
+}
+
+/// Compare every pair of constraints sharing a tile.
+///
+/// The mines in the shared tiles are bounded by both constraints, which
+/// bounds the mines in the tiles only one of them touches. When `b`'s own
+/// tiles are forced to be all mines or all safe, say so.
+/// This covers the subset/superset case, where `a`'s own tiles are empty.
+fn apply_pair_rules(constraints: &[Constraint], deductions: &mut Deductions) -> bool {
+    let mut by_tile: HashMap<Position, Vec<usize>> = HashMap::new();
+    for (idx, constraint) in constraints.iter().enumerate() {
+        for pos in &constraint.tiles {
+            by_tile.entry(*pos).or_default().push(idx);
+        }
+    }
+
+    let mut progress = false;
+    for (a_idx, a) in constraints.iter().enumerate() {
+        let mut checked = HashSet::new();
+        for pos in &a.tiles {
+            for &b_idx in &by_tile[pos] {
+                if b_idx == a_idx || !checked.insert(b_idx) {
+                    continue;
+                }
+                let b = &constraints[b_idx];
+                let shared = a.tiles.intersection(&b.tiles).count();
+                let a_only = a.tiles.len() - shared;
+                let b_only: Vec<&Position> = b.tiles.difference(&a.tiles).collect();
+                if b_only.is_empty() {
+                    continue;
+                }
+
+                let shared_max = shared.min(a.mines).min(b.mines);
+                let shared_min = a
+                    .mines
+                    .saturating_sub(a_only)
+                    .max(b.mines.saturating_sub(b_only.len()));
+                if b.mines.saturating_sub(shared_min) == 0 {
+                    deductions.safe.extend(b_only);
+                    progress = true;
+                } else if b.mines.saturating_sub(shared_max) == b_only.len() {
+                    deductions.mines.extend(b_only);
+                    progress = true;
+                }
+            }
+        }
+        // the tiles in a reduced constraint are all still unknown, so stop
+        // as soon as anything has been learnt and reduce again.
+        if progress {
+            return true;
+        }
+    }
+    false
+}
+
+/// Build a constraint for every uncovered number touching a covered tile, and
+/// one for the board's total mine count, which always comes last.
+pub(crate) fn get_constraints(game: &dyn MinrsGame) -> MinrsResult<Vec<Constraint>> {
+    // every rule here assumes a tile is either a mine or not
+    if game.get_mines_per_tile() > 1 {
+        return Err(MinrsError::InvalidArgument);
+    }
+    let mut constraints = Vec::new();
+    let mut covered = HashSet::new();
+    let mut uncovered_mines = 0;
+    for pos in game.get_positions() {
+        let mines = match game.get_tile_state(&pos)? {
+            TileState::Uncovered(TileContents::MineCount(count)) => count as usize,
+            TileState::Uncovered(TileContents::Mines(count)) => {
+                uncovered_mines += count as usize;
+                continue;
+            }
+            TileState::Covered(_) => {
+                covered.insert(pos);
+                continue;
+            }
+        };
+        let mut tiles = HashSet::new();
+        for n_pos in game.get_neighbors_pos(&pos)? {
+            if let TileState::Covered(_) = game.get_tile_state(&n_pos)? {
+                tiles.insert(n_pos);
+            }
+        }
+        if !tiles.is_empty() {
+            constraints.push(Constraint { tiles, mines });
+        }
+    }
+    constraints.push(Constraint {
+        tiles: covered,
+        mines: (game.get_mine_count() as usize).saturating_sub(uncovered_mines),
+    });
+    Ok(constraints)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::StdMinrsGame;
+
+    fn row(y: u16, xs: std::ops::Range<u16>) -> Vec<Position> {
+        xs.map(|x| Position::new(x, y)).collect()
+    }
+
+    /// Plays seeded games using only the solver's safe tiles, which must
+    /// never hit a mine.
+    #[test]
+    fn test_solver_deductions_are_sound() {
+        for seed in 0..20 {
+            let mut game = StdMinrsGame::new_seeded(16, 16, 40, seed).unwrap();
+            game.uncover_tile(&Position::new(8, 8)).unwrap();
+            loop {
+                let deductions = solve(&game).unwrap();
+                assert!(deductions.safe.is_disjoint(&deductions.mines));
+                if deductions.safe.is_empty() {
+                    break;
+                }
+                for pos in deductions.safe {
+                    if let TileState::Covered(_) = game.get_tile_state(&pos).unwrap() {
+                        game.uncover_tile(&pos).unwrap();
+                    }
+                    assert!(!game.game_over());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_solver_fresh_board_has_no_deductions() {
+        let game = StdMinrsGame::new_seeded(8, 8, 10, 7).unwrap();
+        let deductions = solve(&game).unwrap();
+        assert!(deductions.safe.is_empty());
+        assert!(deductions.mines.is_empty());
+        assert_eq!(hint(&game).unwrap(), None);
+    }
+
+    /// A row of 1s along the top wall. No single number proves anything, but
+    /// the 1-1 subset pattern clears the third tile in from either end.
+    #[test]
+    fn test_solver_subset_rule() {
+        let mines = [
+            Position::new(1, 0),
+            Position::new(4, 0),
+            Position::new(7, 0),
+        ];
+        let game = StdMinrsGame::with_layout(8, 8, &mines, &row(0, 0..8));
+        let deductions = solve(&game).unwrap();
+        assert!(deductions.mines.is_empty());
+        assert_eq!(
+            deductions.safe,
+            [Position::new(2, 0), Position::new(5, 0)].into()
+        );
+    }
+
+    /// The corner tile touches no number, only the total mine count can
+    /// clear it.
+    #[test]
+    fn test_solver_global_mine_count() {
+        let mut covered = row(0, 0..2);
+        covered.extend(row(1, 0..2));
+        let game = StdMinrsGame::with_layout(8, 8, &[Position::new(1, 1)], &covered);
+        let deductions = solve(&game).unwrap();
+        assert!(deductions.mines.contains(&Position::new(1, 1)));
+        assert!(deductions.safe.contains(&Position::new(0, 0)));
+        assert_eq!(hint(&game).unwrap(), Some(Position::new(0, 0)));
+    }
+}